@@ -72,7 +72,6 @@ struct Client {
     unblinded_tokens: Vec<UnblindedToken>,
 }
 
-#[cfg(not(feature = "merlin"))]
 impl Client {
     fn create_tokens(&mut self, n: u8) -> SigningRequest {
         let mut rng = OsRng;
@@ -137,7 +136,6 @@ struct Server {
     spent_tokens: Vec<TokenPreimage>,
 }
 
-#[cfg(not(feature = "merlin"))]
 impl Server {
     fn sign_tokens(&self, req: SigningRequest) -> SigningResponse {
         let mut rng = OsRng;
@@ -188,6 +186,56 @@ impl Server {
     }
 }
 
-criterion_group!(benches, e2e_server_benchmarks,);
+pub fn batch_signing_benchmarks(c: &mut Criterion) {
+    let mut rng = OsRng;
+    let signing_key = SigningKey::random(&mut rng);
+    let n_tokens = 1000;
+
+    let blinded_tokens: Vec<BlindedToken> = (0..n_tokens)
+        .map(|_| Token::random::<Sha512, OsRng>(&mut rng).blind())
+        .collect();
+    let signed_tokens: Vec<SignedToken> = blinded_tokens
+        .iter()
+        .map(|t| signing_key.sign(t).unwrap())
+        .collect();
+
+    c.bench_function("sign 1000 tokens", |b| {
+        b.iter(|| {
+            let _signed_tokens: Vec<SignedToken> = blinded_tokens
+                .iter()
+                .map(|t| signing_key.sign(t).unwrap())
+                .collect();
+        });
+    });
+
+    #[cfg(feature = "rayon")]
+    c.bench_function("sign 1000 tokens in parallel", |b| {
+        b.iter(|| {
+            let _signed_tokens = signing_key.sign_batch(&blinded_tokens).unwrap();
+        });
+    });
+
+    c.bench_function("prove 1000 tokens", |b| {
+        b.iter(|| {
+            let _batch_proof = BatchDLEQProof::new::<Sha512, OsRng>(
+                &mut rng,
+                &blinded_tokens,
+                &signed_tokens,
+                &signing_key,
+            )
+            .unwrap();
+        });
+    });
+
+    c.bench_function("issue 1000 tokens", |b| {
+        b.iter(|| {
+            let _issued = signing_key
+                .issue::<Sha512, OsRng>(&mut rng, &blinded_tokens)
+                .unwrap();
+        });
+    });
+}
+
+criterion_group!(benches, e2e_server_benchmarks, batch_signing_benchmarks,);
 
 criterion_main!(benches);
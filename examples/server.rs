@@ -0,0 +1,226 @@
+//! A reference issuer and redeemer, for use as a local stand-in in integration tests.
+//!
+//! ```text
+//! cargo run --example server --features serde_base64 -- --key signing_key.b64 --port 8080
+//! ```
+//!
+//! The key file holds a base64 encoded `SigningKey`, and is created with a new random key
+//! if it does not exist. Spent tokens are kept in memory unless `--spent` names a file to
+//! persist them to. Requests and responses are JSON, with values base64 encoded:
+//!
+//! * `POST /issue` takes `{"blinded_tokens": [..]}` and returns
+//!   `{"signed_tokens": [..], "batch_proof": .., "public_key": ..}`.
+//! * `POST /redeem` takes `{"preimage": .., "signature": .., "payload": ".."}` and returns
+//!   `{}` once the token is verified and marked as spent, `403` if it fails verification
+//!   or `409` if it has already been spent.
+//!
+//! Errors are returned as `{"error": ".."}`. The hash and MAC are fixed to SHA-512 and
+//! HMAC-SHA512.
+
+use std::env;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use challenge_bypass_ristretto::errors::{InternalError, TokenError};
+use challenge_bypass_ristretto::redemption::{
+    FileSpentTokenStore, MemorySpentTokenStore, RedeemError, Redeemer, SpentTokenStore,
+};
+use challenge_bypass_ristretto::voprf::*;
+use hmac::Hmac;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+type HmacSha512 = Hmac<Sha512>;
+
+#[derive(Deserialize)]
+struct IssueRequest {
+    blinded_tokens: Vec<BlindedToken>,
+}
+
+#[derive(Serialize)]
+struct IssueResponse {
+    signed_tokens: Vec<SignedToken>,
+    batch_proof: BatchDLEQProof,
+    public_key: PublicKey,
+}
+
+#[derive(Deserialize)]
+struct RedeemRequest {
+    preimage: TokenPreimage,
+    signature: VerificationSignature,
+    payload: String,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+struct Config {
+    key: String,
+    port: u16,
+    spent: Option<String>,
+}
+
+const USAGE: &str = "usage: server --key <FILE> [--port <PORT>] [--spent <FILE>]";
+
+fn parse_args() -> Result<Config, Box<dyn Error>> {
+    let mut key = None;
+    let mut port = 8080;
+    let mut spent = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+        match arg.as_str() {
+            "--key" => key = Some(value()?),
+            "--port" => port = value()?.parse()?,
+            "--spent" => spent = Some(value()?),
+            _ => return Err(format!("unexpected argument {}", arg).into()),
+        }
+    }
+
+    Ok(Config {
+        key: key.ok_or("--key is required")?,
+        port,
+        spent,
+    })
+}
+
+/// Read the `SigningKey` from `path`, or generate one and write it there if it does not
+/// exist yet.
+fn load_key(path: &Path) -> Result<SigningKey, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(encoded) => Ok(SigningKey::decode_base64(encoded.trim())?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let signing_key = SigningKey::random(&mut OsRng);
+            fs::write(path, signing_key.encode_base64())?;
+            Ok(signing_key)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn issue(signing_key: &SigningKey, body: &str) -> Result<IssueResponse, TokenError> {
+    let req: IssueRequest =
+        serde_json::from_str(body).map_err(|_| TokenError(InternalError::DecodingError))?;
+
+    let (signed_tokens, batch_proof) =
+        signing_key.issue::<Sha512, _>(&mut OsRng, &req.blinded_tokens)?;
+
+    Ok(IssueResponse {
+        signed_tokens,
+        batch_proof,
+        public_key: signing_key.public_key,
+    })
+}
+
+fn redeem<S: SpentTokenStore>(
+    redeemer: &Redeemer<S>,
+    body: &str,
+) -> Result<(), RedeemError<S::Error>> {
+    let req: RedeemRequest =
+        serde_json::from_str(body).map_err(|_| TokenError(InternalError::DecodingError))?;
+
+    redeemer.redeem::<Sha512, HmacSha512>(&req.preimage, &req.signature, req.payload.as_bytes())
+}
+
+fn json_response<T: Serialize>(status: u16, value: &T) -> Response<io::Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Response::from_data(serde_json::to_vec(value).unwrap())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn error_response<E: Display>(status: u16, error: E) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(
+        status,
+        &ErrorResponse {
+            error: error.to_string(),
+        },
+    )
+}
+
+fn handle<S>(redeemer: &Redeemer<S>, request: &mut Request) -> Response<io::Cursor<Vec<u8>>>
+where
+    S: SpentTokenStore,
+    S::Error: Display,
+{
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body) {
+        return error_response(400, e);
+    }
+
+    match (request.method(), request.url()) {
+        (Method::Post, "/issue") => match issue(redeemer.signing_key(), &body) {
+            Ok(response) => json_response(200, &response),
+            Err(e) => error_response(400, e),
+        },
+        (Method::Post, "/redeem") => match redeem(redeemer, &body) {
+            Ok(()) => json_response(200, &serde_json::json!({})),
+            Err(RedeemError::Token(e @ TokenError(InternalError::VerifyError))) => {
+                error_response(403, e)
+            }
+            Err(RedeemError::Token(e @ TokenError(InternalError::DoubleSpendError))) => {
+                error_response(409, e)
+            }
+            Err(RedeemError::Token(e)) => error_response(400, e),
+            Err(e @ RedeemError::Store(_)) => error_response(500, e),
+        },
+        (_, "/issue") | (_, "/redeem") => error_response(405, "method not allowed"),
+        _ => error_response(404, "not found"),
+    }
+}
+
+fn serve<S>(server: Server, redeemer: Redeemer<S>)
+where
+    S: SpentTokenStore,
+    S::Error: Display,
+{
+    for mut request in server.incoming_requests() {
+        let response = handle(&redeemer, &mut request);
+        if let Err(e) = request.respond(response) {
+            eprintln!("failed to send response: {}", e);
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let config = parse_args()?;
+    let signing_key = load_key(Path::new(&config.key))?;
+    let server = Server::http(("127.0.0.1", config.port)).map_err(|e| e as Box<dyn Error>)?;
+
+    eprintln!(
+        "listening on http://127.0.0.1:{} with public key {}",
+        config.port,
+        signing_key.public_key.encode_base64()
+    );
+
+    match config.spent {
+        Some(path) => serve(
+            server,
+            Redeemer::new(signing_key, FileSpentTokenStore::open(path)?),
+        ),
+        None => serve(
+            server,
+            Redeemer::new(signing_key, MemorySpentTokenStore::new()),
+        ),
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("server: {}\n{}", e, USAGE);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,579 @@
+#ifndef CHALLENGE_BYPASS_RISTRETTO_H
+#define CHALLENGE_BYPASS_RISTRETTO_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The length of an encoded `TokenPreimage`, in bytes.
+ */
+#define CBR_TOKEN_PREIMAGE_LENGTH 64
+
+/**
+ * The length of an encoded `Token`, in bytes.
+ */
+#define CBR_TOKEN_LENGTH 96
+
+/**
+ * The length of an encoded `BlindedToken`, in bytes.
+ */
+#define CBR_BLINDED_TOKEN_LENGTH 32
+
+/**
+ * The length of an encoded `SignedToken`, in bytes.
+ */
+#define CBR_SIGNED_TOKEN_LENGTH 32
+
+/**
+ * The length of an encoded `UnblindedToken`, in bytes.
+ */
+#define CBR_UNBLINDED_TOKEN_LENGTH 96
+
+/**
+ * The length of an encoded `PublicKey`, in bytes.
+ */
+#define CBR_PUBLIC_KEY_LENGTH 32
+
+/**
+ * The length of an encoded `SigningKey`, in bytes.
+ */
+#define CBR_SIGNING_KEY_LENGTH 32
+
+/**
+ * The length of an encoded `VerificationSignature`, in bytes.
+ */
+#define CBR_VERIFICATION_SIGNATURE_LENGTH 64
+
+/**
+ * The length of an encoded `BatchDLEQProof`, in bytes.
+ */
+#define CBR_BATCH_DLEQ_PROOF_LENGTH 64
+
+/**
+ * A `BatchDLEQProof` is a proof of the equivalence of the discrete logarithm between a common
+ * pair of points and one or more other pairs of points.
+ */
+typedef struct CbrBatchDLEQProof CbrBatchDLEQProof;
+
+/**
+ * A `BlindedToken` is sent to the server for signing.
+ *
+ * It is the result of the scalar multiplication of the point derived from the token
+ * preimage with the blinding factor.
+ *
+ * \\(P = T^r = H_1(t)^r\\)
+ */
+typedef struct CbrBlindedToken CbrBlindedToken;
+
+/**
+ * A `PublicKey` is a committment by the server to a particular `SigningKey`.
+ *
+ * \\(Y = X^k\\)
+ */
+typedef struct CbrPublicKey CbrPublicKey;
+
+/**
+ * A `SignedToken` is the result of signing a `BlindedToken`.
+ *
+ * \\(Q = P^k = (T^r)^k\\)
+ */
+typedef struct CbrSignedToken CbrSignedToken;
+
+/**
+ * A `SigningKey` is used to sign a `BlindedToken` and verify an `UnblindedToken`.
+ *
+ * This is a server secret and should NEVER be revealed to the client.
+ */
+typedef struct CbrSigningKey CbrSigningKey;
+
+/**
+ * A `Token` consists of a randomly chosen preimage and blinding factor.
+ *
+ * Since a token includes the blinding factor it should be treated
+ * as a client secret and NEVER revealed to the server.
+ */
+typedef struct CbrToken CbrToken;
+
+/**
+ * A `TokenPreimage` is a slice of bytes which can be hashed to a `RistrettoPoint`.
+ *
+ * The hash function must ensure the discrete log with respect to other points is unknown.
+ * In this construction `RistrettoPoint::from_uniform_bytes` is used as the hash function.
+ */
+typedef struct CbrTokenPreimage CbrTokenPreimage;
+
+/**
+ * An `UnblindedToken` is the result of unblinding a `SignedToken`.
+ *
+ * While both the client and server both "know" this value,
+ * it should nevertheless not be sent between the two.
+ */
+typedef struct CbrUnblindedToken CbrUnblindedToken;
+
+/**
+ * A `VerificationSignature` which can be verified given the `VerificationKey` and message
+ */
+typedef struct CbrVerificationSignature CbrVerificationSignature;
+
+/**
+ * The result of a fallible FFI call.
+ */
+typedef int CbrError;
+
+/**
+ * The call succeeded
+ */
+#define CBR_OK 0
+
+/**
+ * A required pointer argument was null
+ */
+#define CBR_NULL_POINTER -1
+
+/**
+ * See `InternalError::PointDecompressionError`
+ */
+#define CBR_POINT_DECOMPRESSION_ERROR 1
+
+/**
+ * See `InternalError::ScalarFormatError`
+ */
+#define CBR_SCALAR_FORMAT_ERROR 2
+
+/**
+ * See `InternalError::BytesLengthError`
+ */
+#define CBR_BYTES_LENGTH_ERROR 3
+
+/**
+ * See `InternalError::VerifyError`
+ */
+#define CBR_VERIFY_ERROR 4
+
+/**
+ * See `InternalError::LengthMismatchError`
+ */
+#define CBR_LENGTH_MISMATCH_ERROR 5
+
+/**
+ * See `InternalError::DecodingError`
+ */
+#define CBR_DECODING_ERROR 6
+
+/**
+ * See `InternalError::InvalidInputError`
+ */
+#define CBR_INVALID_INPUT_ERROR 7
+
+/**
+ * See `InternalError::UnknownKeyError`
+ */
+#define CBR_UNKNOWN_KEY_ERROR 8
+
+/**
+ * See `InternalError::DoubleSpendError`
+ */
+#define CBR_DOUBLE_SPEND_ERROR 9
+
+/**
+ * See `InternalError::KeyMismatchError`
+ */
+#define CBR_KEY_MISMATCH_ERROR 10
+
+/**
+ * See `InternalError::InvalidShareError`
+ */
+#define CBR_INVALID_SHARE_ERROR 11
+
+/**
+ * Destroy a `TokenPreimage` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_token_preimage_destroy(CbrTokenPreimage *handle);
+
+/**
+ * Write the CBR_TOKEN_PREIMAGE_LENGTH byte encoding of a `TokenPreimage` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_token_preimage_to_bytes(const CbrTokenPreimage *handle, uint8_t *out);
+
+/**
+ * Decode a `TokenPreimage` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_token_preimage_from_bytes(const uint8_t *bytes, uintptr_t len, CbrTokenPreimage **out);
+
+/**
+ * Destroy a `Token` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_token_destroy(CbrToken *handle);
+
+/**
+ * Write the CBR_TOKEN_LENGTH byte encoding of a `Token` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_token_to_bytes(const CbrToken *handle, uint8_t *out);
+
+/**
+ * Decode a `Token` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_token_from_bytes(const uint8_t *bytes, uintptr_t len, CbrToken **out);
+
+/**
+ * Destroy a `BlindedToken` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_blinded_token_destroy(CbrBlindedToken *handle);
+
+/**
+ * Write the CBR_BLINDED_TOKEN_LENGTH byte encoding of a `BlindedToken` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_blinded_token_to_bytes(const CbrBlindedToken *handle, uint8_t *out);
+
+/**
+ * Decode a `BlindedToken` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_blinded_token_from_bytes(const uint8_t *bytes, uintptr_t len, CbrBlindedToken **out);
+
+/**
+ * Destroy a `SignedToken` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_signed_token_destroy(CbrSignedToken *handle);
+
+/**
+ * Write the CBR_SIGNED_TOKEN_LENGTH byte encoding of a `SignedToken` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_signed_token_to_bytes(const CbrSignedToken *handle, uint8_t *out);
+
+/**
+ * Decode a `SignedToken` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_signed_token_from_bytes(const uint8_t *bytes, uintptr_t len, CbrSignedToken **out);
+
+/**
+ * Destroy a `UnblindedToken` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_unblinded_token_destroy(CbrUnblindedToken *handle);
+
+/**
+ * Write the CBR_UNBLINDED_TOKEN_LENGTH byte encoding of a `UnblindedToken` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_unblinded_token_to_bytes(const CbrUnblindedToken *handle, uint8_t *out);
+
+/**
+ * Decode a `UnblindedToken` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_unblinded_token_from_bytes(const uint8_t *bytes,
+                                        uintptr_t len,
+                                        CbrUnblindedToken **out);
+
+/**
+ * Destroy a `PublicKey` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_public_key_destroy(CbrPublicKey *handle);
+
+/**
+ * Write the CBR_PUBLIC_KEY_LENGTH byte encoding of a `PublicKey` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_public_key_to_bytes(const CbrPublicKey *handle, uint8_t *out);
+
+/**
+ * Decode a `PublicKey` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_public_key_from_bytes(const uint8_t *bytes, uintptr_t len, CbrPublicKey **out);
+
+/**
+ * Destroy a `SigningKey` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_signing_key_destroy(CbrSigningKey *handle);
+
+/**
+ * Write the CBR_SIGNING_KEY_LENGTH byte encoding of a `SigningKey` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_signing_key_to_bytes(const CbrSigningKey *handle, uint8_t *out);
+
+/**
+ * Decode a `SigningKey` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_signing_key_from_bytes(const uint8_t *bytes, uintptr_t len, CbrSigningKey **out);
+
+/**
+ * Destroy a `VerificationSignature` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_verification_signature_destroy(CbrVerificationSignature *handle);
+
+/**
+ * Write the CBR_VERIFICATION_SIGNATURE_LENGTH byte encoding of a `VerificationSignature` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_verification_signature_to_bytes(const CbrVerificationSignature *handle, uint8_t *out);
+
+/**
+ * Decode a `VerificationSignature` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_verification_signature_from_bytes(const uint8_t *bytes,
+                                               uintptr_t len,
+                                               CbrVerificationSignature **out);
+
+/**
+ * Destroy a `BatchDLEQProof` handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * The handle must have been returned by this library and not already destroyed.
+ */
+void cbr_batch_dleq_proof_destroy(CbrBatchDLEQProof *handle);
+
+/**
+ * Write the CBR_BATCH_DLEQ_PROOF_LENGTH byte encoding of a `BatchDLEQProof` to `out`.
+ *
+ * # Safety
+ *
+ * `handle` must be a valid handle and `out` must point to a buffer of at least
+ * that many bytes.
+ */
+CbrError cbr_batch_dleq_proof_to_bytes(const CbrBatchDLEQProof *handle, uint8_t *out);
+
+/**
+ * Decode a `BatchDLEQProof` from `len` bytes, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+ */
+CbrError cbr_batch_dleq_proof_from_bytes(const uint8_t *bytes,
+                                         uintptr_t len,
+                                         CbrBatchDLEQProof **out);
+
+/**
+ * Generate a new random `Token`.
+ */
+CbrToken *cbr_token_random(void);
+
+/**
+ * Blind a `Token`, returning a new `BlindedToken` handle, or null if `token` is null.
+ *
+ * # Safety
+ *
+ * `token` must be a valid handle.
+ */
+CbrBlindedToken *cbr_token_blind(const CbrToken *token);
+
+/**
+ * Generate a new random `SigningKey`.
+ */
+CbrSigningKey *cbr_signing_key_random(void);
+
+/**
+ * Return a new handle to the `PublicKey` of a `SigningKey`, or null if `key` is null.
+ *
+ * # Safety
+ *
+ * `key` must be a valid handle.
+ */
+CbrPublicKey *cbr_signing_key_public_key(const CbrSigningKey *key);
+
+/**
+ * Sign a `BlindedToken`, writing a new `SignedToken` handle to `out`.
+ *
+ * # Safety
+ *
+ * `key` and `blinded_token` must be valid handles and `out` a valid pointer.
+ */
+CbrError cbr_signing_key_sign(const CbrSigningKey *key,
+                              const CbrBlindedToken *blinded_token,
+                              CbrSignedToken **out);
+
+/**
+ * Check a redemption: rederive the `UnblindedToken` for `preimage` and verify the client's
+ * `VerificationSignature` over `message`.
+ *
+ * Returns `CBR_OK` if the signature is valid and `CBR_VERIFY_ERROR` otherwise. Checking
+ * that the token has not been spent before is left to the caller.
+ *
+ * # Safety
+ *
+ * All handles must be valid and `message` must point to `message_len` readable bytes.
+ */
+CbrError cbr_signing_key_redeem(const CbrSigningKey *key,
+                                const CbrTokenPreimage *preimage,
+                                const CbrVerificationSignature *signature,
+                                const uint8_t *message,
+                                uintptr_t message_len);
+
+/**
+ * Return a new handle to the `TokenPreimage` of an `UnblindedToken`, or null if
+ * `token` is null.
+ *
+ * # Safety
+ *
+ * `token` must be a valid handle.
+ */
+CbrTokenPreimage *cbr_unblinded_token_preimage(const CbrUnblindedToken *token);
+
+/**
+ * Sign `message` with the `VerificationKey` derived from an `UnblindedToken`, writing a
+ * new `VerificationSignature` handle to `out`.
+ *
+ * # Safety
+ *
+ * `token` must be a valid handle, `message` must point to `message_len` readable bytes
+ * and `out` must be a valid pointer.
+ */
+CbrError cbr_unblinded_token_sign(const CbrUnblindedToken *token,
+                                  const uint8_t *message,
+                                  uintptr_t message_len,
+                                  CbrVerificationSignature **out);
+
+/**
+ * Construct a `BatchDLEQProof` over `n` `BlindedToken`s and the corresponding
+ * `SignedToken`s, writing a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `blinded_tokens` and `signed_tokens` must each point to `n` valid handles, `key` must
+ * be a valid handle and `out` a valid pointer.
+ */
+CbrError cbr_batch_dleq_proof_new(const CbrSigningKey *key,
+                                  const CbrBlindedToken *const *blinded_tokens,
+                                  const CbrSignedToken *const *signed_tokens,
+                                  uintptr_t n,
+                                  CbrBatchDLEQProof **out);
+
+/**
+ * Verify a `BatchDLEQProof` over `n` `BlindedToken`s and `SignedToken`s.
+ *
+ * # Safety
+ *
+ * `blinded_tokens` and `signed_tokens` must each point to `n` valid handles, and `proof`
+ * and `public_key` must be valid handles.
+ */
+CbrError cbr_batch_dleq_proof_verify(const CbrBatchDLEQProof *proof,
+                                     const CbrBlindedToken *const *blinded_tokens,
+                                     const CbrSignedToken *const *signed_tokens,
+                                     uintptr_t n,
+                                     const CbrPublicKey *public_key);
+
+/**
+ * Verify a `BatchDLEQProof` and unblind the `SignedToken`s, writing `n` new
+ * `UnblindedToken` handles to the array `out`.
+ *
+ * On failure every element of `out` is set to null.
+ *
+ * # Safety
+ *
+ * `tokens`, `blinded_tokens` and `signed_tokens` must each point to `n` valid handles,
+ * `proof` and `public_key` must be valid handles and `out` must point to space for `n`
+ * pointers.
+ */
+CbrError cbr_batch_dleq_proof_verify_and_unblind(const CbrBatchDLEQProof *proof,
+                                                 const CbrToken *const *tokens,
+                                                 const CbrBlindedToken *const *blinded_tokens,
+                                                 const CbrSignedToken *const *signed_tokens,
+                                                 uintptr_t n,
+                                                 const CbrPublicKey *public_key,
+                                                 CbrUnblindedToken **out);
+
+#endif  /* CHALLENGE_BYPASS_RISTRETTO_H */
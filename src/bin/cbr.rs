@@ -0,0 +1,349 @@
+//! `cbr`, a command-line tool for reproducing each step of the protocol offline.
+//!
+//! Every subcommand except `keygen` and `inspect` reads a JSON document from standard input,
+//! performs one step and writes the document back out with the step's results added, so
+//! that steps can be piped together:
+//!
+//! ```text
+//! cbr keygen | cbr blind -n 3 | cbr sign | cbr prove | cbr unblind --payload hello | cbr redeem
+//! ```
+//!
+//! Values in the document use the base64 encodings of the library types. The hash and MAC
+//! are fixed to SHA-512 and HMAC-SHA512.
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::process;
+
+use challenge_bypass_ristretto::errors::{InternalError, TokenError};
+use challenge_bypass_ristretto::voprf::*;
+use clap::{Parser, Subcommand};
+use hmac::Hmac;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// The JSON document passed between subcommands.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Document {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signing_key: Option<SigningKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_key: Option<PublicKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<Vec<Token>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blinded_tokens: Option<Vec<BlindedToken>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signed_tokens: Option<Vec<SignedToken>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_proof: Option<BatchDLEQProof>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unblinded_tokens: Option<Vec<UnblindedToken>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preimages: Option<Vec<TokenPreimage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification_signatures: Option<Vec<VerificationSignature>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "cbr",
+    version,
+    about = "Privacy Pass tokens using the Ristretto group"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate a new `signing_key` and its `public_key`
+    Keygen,
+    /// Derive the `public_key` of the `signing_key`
+    Pubkey,
+    /// Generate new `tokens` and their `blinded_tokens`
+    Blind {
+        /// The number of tokens to generate
+        #[arg(short = 'n', long, default_value_t = 1)]
+        count: usize,
+    },
+    /// Sign the `blinded_tokens` with the `signing_key`, producing `signed_tokens`
+    Sign,
+    /// Prove that the `signed_tokens` were signed with the `signing_key`, producing a
+    /// `batch_proof`
+    Prove,
+    /// Verify the `batch_proof` against the `public_key`
+    VerifyProof,
+    /// Verify the `batch_proof` and unblind the `signed_tokens`, producing `unblinded_tokens`
+    Unblind {
+        /// Also sign this payload with each token, producing `preimages` and
+        /// `verification_signatures` for redemption
+        #[arg(long)]
+        payload: Option<String>,
+    },
+    /// Check the `verification_signatures` over the `payload` with the `signing_key`
+    Redeem,
+    /// Report the types a base64 value decodes as
+    Inspect {
+        /// The base64 encoded value
+        value: String,
+    },
+}
+
+/// Return a required field of the document, or an error naming it.
+fn field<'a, T>(value: &'a Option<T>, name: &str) -> Result<&'a T, Box<dyn Error>> {
+    value
+        .as_ref()
+        .ok_or_else(|| format!("input is missing `{}`", name).into())
+}
+
+/// Run a subcommand which transforms a document.
+fn run(command: &Command, mut doc: Document) -> Result<Document, Box<dyn Error>> {
+    match command {
+        Command::Keygen => {
+            let signing_key = SigningKey::random(&mut OsRng);
+            doc.public_key = Some(signing_key.public_key);
+            doc.signing_key = Some(signing_key);
+        }
+        Command::Pubkey => {
+            doc.public_key = Some(field(&doc.signing_key, "signing_key")?.public_key);
+        }
+        Command::Blind { count } => {
+            let tokens: Vec<Token> = (0..*count)
+                .map(|_| Token::random::<Sha512, _>(&mut OsRng))
+                .collect();
+            doc.blinded_tokens = Some(tokens.iter().map(|t| t.blind()).collect());
+            doc.tokens = Some(tokens);
+        }
+        Command::Sign => {
+            let signing_key = field(&doc.signing_key, "signing_key")?;
+            let signed_tokens = field(&doc.blinded_tokens, "blinded_tokens")?
+                .iter()
+                .map(|t| signing_key.sign(t))
+                .collect::<Result<Vec<_>, _>>()?;
+            doc.public_key = Some(signing_key.public_key);
+            doc.signed_tokens = Some(signed_tokens);
+        }
+        Command::Prove => {
+            let signing_key = field(&doc.signing_key, "signing_key")?;
+            let batch_proof = BatchDLEQProof::new::<Sha512, _>(
+                &mut OsRng,
+                field(&doc.blinded_tokens, "blinded_tokens")?,
+                field(&doc.signed_tokens, "signed_tokens")?,
+                signing_key,
+            )?;
+            doc.public_key = Some(signing_key.public_key);
+            doc.batch_proof = Some(batch_proof);
+        }
+        Command::VerifyProof => {
+            field(&doc.batch_proof, "batch_proof")?.verify::<Sha512>(
+                field(&doc.blinded_tokens, "blinded_tokens")?,
+                field(&doc.signed_tokens, "signed_tokens")?,
+                field(&doc.public_key, "public_key")?,
+            )?;
+        }
+        Command::Unblind { payload } => {
+            let tokens = field(&doc.tokens, "tokens")?;
+            let blinded_tokens = field(&doc.blinded_tokens, "blinded_tokens")?;
+            if tokens.len() != blinded_tokens.len() {
+                return Err(TokenError(InternalError::LengthMismatchError).into());
+            }
+
+            let unblinded_tokens = field(&doc.batch_proof, "batch_proof")?
+                .verify_and_unblind::<Sha512, _>(
+                    tokens,
+                    blinded_tokens,
+                    field(&doc.signed_tokens, "signed_tokens")?,
+                    field(&doc.public_key, "public_key")?,
+                )?;
+
+            if let Some(payload) = payload {
+                doc.preimages = Some(unblinded_tokens.iter().map(|t| t.t).collect());
+                doc.verification_signatures = Some(
+                    unblinded_tokens
+                        .iter()
+                        .map(|t| {
+                            t.derive_verification_key::<Sha512>()
+                                .sign::<HmacSha512>(payload.as_bytes())
+                        })
+                        .collect(),
+                );
+                doc.payload = Some(payload.clone());
+            }
+            doc.unblinded_tokens = Some(unblinded_tokens);
+        }
+        Command::Redeem => {
+            let signing_key = field(&doc.signing_key, "signing_key")?;
+            let preimages = field(&doc.preimages, "preimages")?;
+            let signatures = field(&doc.verification_signatures, "verification_signatures")?;
+            let payload = field(&doc.payload, "payload")?;
+            if preimages.len() != signatures.len() {
+                return Err(TokenError(InternalError::LengthMismatchError).into());
+            }
+
+            for (preimage, signature) in preimages.iter().zip(signatures) {
+                let verification_key = signing_key
+                    .rederive_unblinded_token(preimage)
+                    .derive_verification_key::<Sha512>();
+                if !verification_key.verify::<HmacSha512>(signature, payload.as_bytes()) {
+                    return Err(TokenError(InternalError::VerifyError).into());
+                }
+            }
+        }
+        Command::Inspect { .. } => unreachable!("inspect does not take a document"),
+    }
+    Ok(doc)
+}
+
+/// List the types which `value` decodes as.
+fn inspect(value: &str) -> Result<serde_json::Value, Box<dyn Error>> {
+    let bytes = base64::decode(value).map_err(|_| TokenError(InternalError::DecodingError))?;
+
+    let mut types = Vec::new();
+    macro_rules! try_decode {
+        ($($t:ident),*) => {
+            $(
+                if $t::decode_base64(value).is_ok() {
+                    types.push(stringify!($t));
+                }
+            )*
+        };
+    }
+    try_decode!(
+        TokenPreimage,
+        Token,
+        BlindedToken,
+        SignedToken,
+        UnblindedToken,
+        PublicKey,
+        SigningKey,
+        KeyId,
+        VerificationSignature,
+        DLEQProof,
+        BatchDLEQProof
+    );
+
+    Ok(serde_json::json!({ "length": bytes.len(), "types": types }))
+}
+
+/// Read the input document, if any, run the subcommand and write its output.
+fn try_main(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let output = match cli.command {
+        Command::Inspect { ref value } => inspect(value)?,
+        Command::Keygen => serde_json::to_value(run(&cli.command, Document::default())?)?,
+        ref command => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            let doc = if input.trim().is_empty() {
+                Document::default()
+            } else {
+                serde_json::from_str(&input)?
+            };
+            serde_json::to_value(run(command, doc)?)?
+        }
+    };
+
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &output)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = try_main(Cli::parse()) {
+        eprintln!("cbr: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(doc: Document) -> Document {
+        serde_json::from_str(&serde_json::to_string(&doc).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pipeline_works() {
+        let mut doc = Document::default();
+        for command in [
+            Command::Keygen,
+            Command::Blind { count: 3 },
+            Command::Sign,
+            Command::Prove,
+            Command::VerifyProof,
+            Command::Unblind {
+                payload: Some("test message".into()),
+            },
+            Command::Redeem,
+        ] {
+            doc = round_trip(run(&command, doc).unwrap());
+        }
+        assert_eq!(doc.verification_signatures.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn rejects_bad_inputs() {
+        let mut doc = Document::default();
+        for command in [Command::Keygen, Command::Blind { count: 2 }, Command::Sign] {
+            doc = round_trip(run(&command, doc).unwrap());
+        }
+
+        let err = run(&Command::Prove, Document::default()).err().unwrap();
+        assert_eq!(err.to_string(), "input is missing `signing_key`");
+
+        // a proof from another key does not verify
+        let mut other = run(&Command::Keygen, Document::default()).unwrap();
+        other.blinded_tokens = doc.blinded_tokens.clone();
+        other.signed_tokens = Some(
+            doc.blinded_tokens
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|t| other.signing_key.as_ref().unwrap().sign(t).unwrap())
+                .collect(),
+        );
+        let other = run(&Command::Prove, other).unwrap();
+        doc.batch_proof = other.batch_proof;
+        let err = run(&Command::VerifyProof, doc).err().unwrap();
+        assert_eq!(err.to_string(), "Verification failed");
+
+        // a redemption with a different payload does not verify
+        let mut doc = Document::default();
+        for command in [
+            Command::Keygen,
+            Command::Blind { count: 1 },
+            Command::Sign,
+            Command::Prove,
+            Command::Unblind {
+                payload: Some("test message".into()),
+            },
+        ] {
+            doc = round_trip(run(&command, doc).unwrap());
+        }
+        doc.payload = Some("other message".into());
+        let err = run(&Command::Redeem, doc).err().unwrap();
+        assert_eq!(err.to_string(), "Verification failed");
+    }
+
+    #[test]
+    fn inspect_works() {
+        let token = Token::random::<Sha512, _>(&mut OsRng);
+        let output = inspect(&token.encode_base64()).unwrap();
+        assert_eq!(output["length"], 96);
+        assert!(output["types"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("Token")));
+
+        assert!(inspect("not base64!").is_err());
+    }
+}
@@ -0,0 +1,29 @@
+//! Helpers for decoding the variable length wire formats.
+
+use crate::errors::{InternalError, TokenError};
+
+/// A cursor over bytes being decoded.
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn read(&mut self, length: usize) -> Result<&'a [u8], TokenError> {
+        if self.0.len() < length {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+        let (head, tail) = self.0.split_at(length);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, TokenError> {
+        let mut bits = [0u8; 4];
+        bits.copy_from_slice(self.read(4)?);
+        Ok(u32::from_be_bytes(bits))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, TokenError> {
+        let mut bits = [0u8; 8];
+        bits.copy_from_slice(self.read(8)?);
+        Ok(u64::from_be_bytes(bits))
+    }
+}
@@ -0,0 +1,385 @@
+//! Signed commitments to the set of issuer keys, so that clients can detect an issuer
+//! presenting different `PublicKey`s to different users.
+//!
+//! The issuer publishes a `KeyCommitment`: a versioned, timestamped list of the `KeyId`,
+//! `PublicKey` and validity window of each key, signed with a long-term key as a
+//! `SignedKeyCommitment`. Any signature scheme implementing the `signature` crate's
+//! `Signer` / `Verifier` traits may be used, such as Ed25519 or the Schnorr signatures of
+//! the `schnorr` module.
+//!
+//! A client verifies the signature once to obtain a `VerifiedKeyCommitment`, and then
+//! refuses tokens from any key which it does not list, for example via
+//! `BatchDLEQProof::verify_and_unblind_committed`. When the issuer rotates keys it
+//! publishes a new commitment with a higher version, which
+//! `SignedKeyCommitment::verify_update` checks against the one the client already holds.
+//!
+//! Times are given in seconds since the Unix epoch, and a key is valid from `not_before`
+//! up to but excluding `not_after`.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use digest::generic_array::typenum::U32;
+use digest::Digest;
+use signature::{Signature, Signer, Verifier};
+
+use crate::codec::Reader;
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{KeyId, PublicKey, KEY_ID_LENGTH, PUBLIC_KEY_LENGTH};
+
+/// The context prepended to the encoded `KeyCommitment` before signing, so that the
+/// long-term key's signatures cannot be confused with those over other messages.
+const KEY_COMMITMENT_CONTEXT: &[u8] = b"challenge-bypass-ristretto key commitment";
+
+/// The length of a `CommittedKey`, in bytes.
+pub const COMMITTED_KEY_LENGTH: usize = KEY_ID_LENGTH + PUBLIC_KEY_LENGTH + 8 + 8;
+
+/// A `PublicKey` listed in a `KeyCommitment`.
+#[derive(Copy, Clone, Debug)]
+pub struct CommittedKey {
+    /// The `KeyId` of the key
+    pub id: KeyId,
+    /// The `PublicKey` itself
+    pub public_key: PublicKey,
+    /// The time from which tokens issued under the key are valid
+    pub not_before: u64,
+    /// The time from which tokens issued under the key are no longer valid
+    pub not_after: u64,
+}
+
+impl CommittedKey {
+    /// Whether the key is within its validity window at `time`.
+    pub fn is_valid_at(&self, time: u64) -> bool {
+        self.not_before <= time && time < self.not_after
+    }
+}
+
+/// A versioned, timestamped list of issuer keys.
+#[derive(Clone, Debug)]
+pub struct KeyCommitment {
+    version: u64,
+    timestamp: u64,
+    keys: Vec<CommittedKey>,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(KeyCommitment, H: Digest<OutputSize = U32> + Default);
+
+impl KeyCommitment {
+    /// Construct an empty `KeyCommitment`.
+    ///
+    /// Each commitment the issuer publishes should have a higher `version` than the last.
+    pub fn new(version: u64, timestamp: u64) -> Self {
+        KeyCommitment {
+            version,
+            timestamp,
+            keys: Vec::new(),
+        }
+    }
+
+    /// The version of the commitment.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The time at which the commitment was made.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Add `public_key` with the given validity window, returning its `KeyId`.
+    ///
+    /// If the key is already present its validity window is updated instead.
+    pub fn add_key<H>(&mut self, public_key: &PublicKey, not_before: u64, not_after: u64) -> KeyId
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let id = public_key.key_id::<H>();
+        match self.keys.iter_mut().find(|key| key.id == id) {
+            Some(key) => {
+                key.not_before = not_before;
+                key.not_after = not_after;
+            }
+            None => self.keys.push(CommittedKey {
+                id,
+                public_key: *public_key,
+                not_before,
+                not_after,
+            }),
+        }
+        id
+    }
+
+    /// The keys listed in the commitment.
+    pub fn keys(&self) -> &[CommittedKey] {
+        &self.keys
+    }
+
+    /// Look up the key identified by `id`.
+    pub fn key(&self, id: &KeyId) -> Option<&CommittedKey> {
+        self.keys.iter().find(|key| key.id == *id)
+    }
+
+    /// Sign the commitment with the issuer's long-term key.
+    ///
+    /// Returns the error of `signing_key` if signing fails.
+    pub fn sign<S, K>(self, signing_key: &K) -> Result<SignedKeyCommitment, signature::Error>
+    where
+        S: Signature,
+        K: Signer<S>,
+    {
+        let signature = signing_key.try_sign(&self.signing_message())?;
+        Ok(SignedKeyCommitment {
+            commitment: self,
+            signature: signature.as_bytes().to_vec(),
+        })
+    }
+
+    fn signing_message(&self) -> Vec<u8> {
+        let mut message = KEY_COMMITMENT_CONTEXT.to_vec();
+        message.extend_from_slice(&self.to_bytes());
+        message
+    }
+
+    /// Convert this `KeyCommitment` to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + 8 + 4 + self.keys.len() * COMMITTED_KEY_LENGTH);
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        bytes.extend_from_slice(&(self.keys.len() as u32).to_be_bytes());
+        for key in self.keys.iter() {
+            bytes.extend_from_slice(&key.id.to_bytes());
+            bytes.extend_from_slice(&key.public_key.to_bytes());
+            bytes.extend_from_slice(&key.not_before.to_be_bytes());
+            bytes.extend_from_slice(&key.not_after.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Construct a `KeyCommitment` from bytes produced by `to_bytes`.
+    ///
+    /// `H` is the hash function the `KeyId`s were derived with, and a `TokenError` is
+    /// returned if any `KeyId` does not match its `PublicKey`.
+    pub fn from_bytes<H>(bytes: &[u8]) -> Result<KeyCommitment, TokenError>
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let mut reader = Reader(bytes);
+        let commitment = KeyCommitment::read::<H>(&mut reader)?;
+        if !reader.0.is_empty() {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+        Ok(commitment)
+    }
+
+    fn read<H>(reader: &mut Reader) -> Result<KeyCommitment, TokenError>
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let version = reader.read_u64()?;
+        let timestamp = reader.read_u64()?;
+        let keys = (0..reader.read_u32()?)
+            .map(|_| {
+                let id = KeyId::from_bytes(reader.read(KEY_ID_LENGTH)?)?;
+                let public_key = PublicKey::from_bytes(reader.read(PUBLIC_KEY_LENGTH)?)?;
+                if id != public_key.key_id::<H>() {
+                    return Err(TokenError(InternalError::DecodingError));
+                }
+                Ok(CommittedKey {
+                    id,
+                    public_key,
+                    not_before: reader.read_u64()?,
+                    not_after: reader.read_u64()?,
+                })
+            })
+            .collect::<Result<Vec<_>, TokenError>>()?;
+        Ok(KeyCommitment {
+            version,
+            timestamp,
+            keys,
+        })
+    }
+}
+
+/// A `KeyCommitment` together with the issuer's signature over it.
+#[derive(Clone, Debug)]
+pub struct SignedKeyCommitment {
+    commitment: KeyCommitment,
+    signature: Vec<u8>,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(SignedKeyCommitment, H: Digest<OutputSize = U32> + Default);
+
+impl SignedKeyCommitment {
+    /// The `KeyCommitment`, which has not been verified.
+    pub fn commitment(&self) -> &KeyCommitment {
+        &self.commitment
+    }
+
+    /// Verify the signature over the commitment with the issuer's long-term key.
+    pub fn verify<S, V>(&self, verifying_key: &V) -> Result<VerifiedKeyCommitment, TokenError>
+    where
+        S: Signature,
+        V: Verifier<S>,
+    {
+        let signature =
+            S::from_bytes(&self.signature).or(Err(TokenError(InternalError::VerifyError)))?;
+        verifying_key
+            .verify(&self.commitment.signing_message(), &signature)
+            .or(Err(TokenError(InternalError::VerifyError)))?;
+        Ok(VerifiedKeyCommitment(self.commitment.clone()))
+    }
+
+    /// Verify the commitment as with `verify`, and check that it supersedes `previous`.
+    ///
+    /// The new commitment must have a higher version and must not predate `previous`,
+    /// so that an issuer cannot roll a client back to an older set of keys.
+    pub fn verify_update<S, V>(
+        &self,
+        verifying_key: &V,
+        previous: &VerifiedKeyCommitment,
+    ) -> Result<VerifiedKeyCommitment, TokenError>
+    where
+        S: Signature,
+        V: Verifier<S>,
+    {
+        let verified = self.verify::<S, V>(verifying_key)?;
+        if verified.0.version <= previous.0.version || verified.0.timestamp < previous.0.timestamp {
+            return Err(TokenError(InternalError::VerifyError));
+        }
+        Ok(verified)
+    }
+
+    /// Convert this `SignedKeyCommitment` to a byte array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.commitment.to_bytes();
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    /// Construct a `SignedKeyCommitment` from bytes produced by `to_bytes`.
+    ///
+    /// `H` is the hash function the `KeyId`s were derived with, as for
+    /// `KeyCommitment::from_bytes`.
+    pub fn from_bytes<H>(bytes: &[u8]) -> Result<SignedKeyCommitment, TokenError>
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let mut reader = Reader(bytes);
+        let commitment = KeyCommitment::read::<H>(&mut reader)?;
+        Ok(SignedKeyCommitment {
+            commitment,
+            signature: reader.0.to_vec(),
+        })
+    }
+}
+
+/// A `KeyCommitment` whose signature has been verified.
+#[derive(Clone, Debug)]
+pub struct VerifiedKeyCommitment(KeyCommitment);
+
+impl VerifiedKeyCommitment {
+    /// The verified `KeyCommitment`.
+    pub fn commitment(&self) -> &KeyCommitment {
+        &self.0
+    }
+
+    /// Check that `public_key` is listed in the commitment and valid at `time`.
+    ///
+    /// Returns a `TokenError` if it is not.
+    pub fn check(&self, public_key: &PublicKey, time: u64) -> Result<&CommittedKey, TokenError> {
+        self.0
+            .keys
+            .iter()
+            .find(|key| key.public_key.0 == public_key.0 && key.is_valid_at(time))
+            .ok_or(TokenError(InternalError::UnknownKeyError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+    use sha2::Sha256;
+
+    use super::*;
+    use crate::oprf::SigningKey;
+
+    #[test]
+    fn commitment_works() {
+        let mut rng = OsRng;
+        let issuer = Keypair::generate(&mut rng);
+
+        let old_key = SigningKey::random(&mut rng);
+        let new_key = SigningKey::random(&mut rng);
+
+        let mut commitment = KeyCommitment::new(1, 1000);
+        let old_id = commitment.add_key::<Sha256>(&old_key.public_key, 1000, 2000);
+        let signed = commitment.sign(&issuer).unwrap();
+
+        let signed = SignedKeyCommitment::decode_base64::<Sha256>(&signed.encode_base64()).unwrap();
+        let verified = signed.verify(&issuer.public).unwrap();
+        assert_eq!(verified.commitment().version(), 1);
+        assert_eq!(
+            verified.check(&old_key.public_key, 1500).unwrap().id,
+            old_id
+        );
+
+        // keys outside the commitment or their validity window are refused
+        assert_eq!(
+            verified.check(&new_key.public_key, 1500).unwrap_err(),
+            TokenError(InternalError::UnknownKeyError)
+        );
+        assert!(verified.check(&old_key.public_key, 999).is_err());
+        assert!(verified.check(&old_key.public_key, 2000).is_err());
+
+        // a commitment signed by another key does not verify
+        let other = Keypair::generate(&mut rng);
+        assert!(signed.verify(&other.public).is_err());
+
+        // a tampered commitment does not verify
+        let mut bytes = signed.to_bytes();
+        bytes[7] ^= 1;
+        assert!(SignedKeyCommitment::from_bytes::<Sha256>(&bytes)
+            .unwrap()
+            .verify(&issuer.public)
+            .is_err());
+
+        // rotation
+        let mut commitment = KeyCommitment::new(2, 1500);
+        commitment.add_key::<Sha256>(&old_key.public_key, 1000, 2000);
+        commitment.add_key::<Sha256>(&new_key.public_key, 1500, 3000);
+        let update = commitment.sign(&issuer).unwrap();
+        let updated = update.verify_update(&issuer.public, &verified).unwrap();
+        assert!(updated.check(&new_key.public_key, 2500).is_ok());
+
+        // but not back again
+        assert_eq!(
+            signed.verify_update(&issuer.public, &updated).unwrap_err(),
+            TokenError(InternalError::VerifyError)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let mut commitment = KeyCommitment::new(1, 1000);
+        commitment.add_key::<Sha256>(&SigningKey::random(&mut OsRng).public_key, 0, 1);
+
+        let bytes = commitment.to_bytes();
+        assert!(KeyCommitment::from_bytes::<Sha256>(&bytes).is_ok());
+        assert!(KeyCommitment::from_bytes::<Sha256>(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(KeyCommitment::from_bytes::<Sha256>(&extended).is_err());
+
+        // the key id must match the public key
+        let mut mismatched = bytes.clone();
+        mismatched[20] ^= 1;
+        assert!(KeyCommitment::from_bytes::<Sha256>(&mismatched).is_err());
+    }
+}
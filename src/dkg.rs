@@ -0,0 +1,694 @@
+//! Distributed generation of threshold issuer keys, so that no single server ever holds the
+//! full `SigningKey`.
+//!
+//! This is the Pedersen DKG built from Feldman verifiable secret sharing. Each of the `n`
+//! participants acts as a dealer of a random polynomial \\(f_j\\) of degree \\(t - 1\\):
+//!
+//! 1. Every dealer broadcasts a `DealerCommitment` to the coefficients of its polynomial,
+//!    \\(A_{jk} = a_{jk} X\\), and sends each participant \\(i\\) its `SecretShare`
+//!    \\(f_j(i)\\) over a private, authenticated channel.
+//! 2. Each participant checks the shares it received against the dealers' commitments with
+//!    `Participant::receive_share`, and broadcasts a `Complaint` against any dealer whose
+//!    share is invalid.
+//! 3. An accused dealer answers with `Participant::reveal`, broadcasting the disputed share.
+//!    Every participant then calls `Participant::resolve`, which disqualifies the dealer if
+//!    it did not answer or the revealed share is also invalid.
+//! 4. `Participant::finish` sums the shares of the qualified dealers into a `KeyShare`
+//!    \\(k_i = \\sum_j f_j(i)\\) of the group key \\(k = \\sum_j f_j(0)\\), whose `PublicKey`
+//!    \\(Y = \\sum_j A_{j0}\\) is known to everyone but never reconstructed as a secret.
+//!
+//! Since commitments and complaints are broadcast, all honest participants agree on the
+//! qualified dealers and so on the group `PublicKey` and the `PublicKeyShare`s for a
+//! `threshold::Combiner`.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::iter;
+
+use curve25519_dalek::constants;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::codec::Reader;
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::PublicKey;
+use crate::threshold::{KeyShare, PublicKeyShare};
+
+/// The length of a `SecretShare`, in bytes.
+pub const SECRET_SHARE_LENGTH: usize = 4 + 4 + 32;
+/// The length of a `Complaint`, in bytes.
+pub const COMPLAINT_LENGTH: usize = 4 + 4;
+
+/// The commitment of a dealer to the coefficients of its polynomial.
+#[derive(Clone, Debug)]
+pub struct DealerCommitment {
+    /// The index of the dealer
+    pub dealer: u32,
+    coefficients: Vec<CompressedRistretto>,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(DealerCommitment);
+
+#[cfg(feature = "serde")]
+impl_serde!(DealerCommitment);
+
+#[allow(non_snake_case)]
+impl DealerCommitment {
+    /// Check that `share` is the evaluation of the committed polynomial at its recipient,
+    /// \\(f_j(i) X = \\sum_k i^k A_{jk}\\).
+    pub fn verify(&self, share: &SecretShare) -> Result<(), TokenError> {
+        if share.dealer != self.dealer {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let expected = self.evaluate(share.recipient)?;
+        if &share.value * &constants::RISTRETTO_BASEPOINT_TABLE == expected {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// The commitment to the dealer's share for participant `index`.
+    fn evaluate(&self, index: u32) -> Result<RistrettoPoint, TokenError> {
+        let x = Scalar::from(index);
+        self.coefficients
+            .iter()
+            .rev()
+            .try_fold(RistrettoPoint::default(), |acc, A| {
+                Ok(acc * x + decompress(A)?)
+            })
+    }
+
+    /// Convert this `DealerCommitment` to bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 + self.coefficients.len() * 32);
+        bytes.extend_from_slice(&self.dealer.to_be_bytes());
+        bytes.extend_from_slice(&(self.coefficients.len() as u32).to_be_bytes());
+        for coefficient in &self.coefficients {
+            bytes.extend_from_slice(coefficient.as_bytes());
+        }
+        bytes
+    }
+
+    /// A `DealerCommitment` has no fixed length, so decoding fails rather than reporting one.
+    #[cfg(feature = "serde")]
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::DecodingError)
+    }
+
+    /// Construct a `DealerCommitment` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DealerCommitment, TokenError> {
+        let mut reader = Reader(bytes);
+        let dealer = read_index(&mut reader)?;
+        let coefficients = (0..reader.read_u32()?)
+            .map(|_| {
+                let A = CompressedRistretto::from_slice(reader.read(32)?);
+                decompress(&A)?;
+                Ok(A)
+            })
+            .collect::<Result<Vec<_>, TokenError>>()?;
+        if !reader.0.is_empty() {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+        Ok(DealerCommitment {
+            dealer,
+            coefficients,
+        })
+    }
+}
+
+/// The share of one dealer's polynomial for one participant, \\(f_j(i)\\).
+///
+/// This must only be sent to its recipient, unless revealed in answer to a `Complaint`.
+#[derive(Debug)]
+pub struct SecretShare {
+    /// The index of the dealer
+    pub dealer: u32,
+    /// The index of the participant the share is for
+    pub recipient: u32,
+    value: Scalar,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(SecretShare);
+
+#[cfg(feature = "serde")]
+impl_serde!(SecretShare);
+
+/// Overwrite share with null when it goes out of scope.
+impl Drop for SecretShare {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl SecretShare {
+    /// Convert this `SecretShare` to a byte array.
+    pub fn to_bytes(&self) -> [u8; SECRET_SHARE_LENGTH] {
+        let mut bytes: [u8; SECRET_SHARE_LENGTH] = [0u8; SECRET_SHARE_LENGTH];
+        bytes[..4].copy_from_slice(&self.dealer.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.recipient.to_be_bytes());
+        bytes[8..].copy_from_slice(self.value.as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "SecretShare",
+            length: SECRET_SHARE_LENGTH,
+        })
+    }
+
+    /// Construct a `SecretShare` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SecretShare, TokenError> {
+        if bytes.len() != SECRET_SHARE_LENGTH {
+            return Err(SecretShare::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        let dealer = read_index(&mut reader)?;
+        let recipient = read_index(&mut reader)?;
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(reader.read(32)?);
+        let value = Scalar::from_canonical_bytes(bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        Ok(SecretShare {
+            dealer,
+            recipient,
+            value,
+        })
+    }
+}
+
+/// A complaint by a participant that the share it received from a dealer is invalid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Complaint {
+    /// The index of the participant making the complaint
+    pub accuser: u32,
+    /// The index of the dealer the complaint is against
+    pub dealer: u32,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Complaint);
+
+#[cfg(feature = "serde")]
+impl_serde!(Complaint);
+
+impl Complaint {
+    /// Convert this `Complaint` to a byte array.
+    pub fn to_bytes(&self) -> [u8; COMPLAINT_LENGTH] {
+        let mut bytes: [u8; COMPLAINT_LENGTH] = [0u8; COMPLAINT_LENGTH];
+        bytes[..4].copy_from_slice(&self.accuser.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.dealer.to_be_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Complaint",
+            length: COMPLAINT_LENGTH,
+        })
+    }
+
+    /// Construct a `Complaint` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Complaint, TokenError> {
+        if bytes.len() != COMPLAINT_LENGTH {
+            return Err(Complaint::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        Ok(Complaint {
+            accuser: read_index(&mut reader)?,
+            dealer: read_index(&mut reader)?,
+        })
+    }
+}
+
+fn decompress(point: &CompressedRistretto) -> Result<RistrettoPoint, TokenError> {
+    point
+        .decompress()
+        .ok_or(TokenError(InternalError::PointDecompressionError))
+}
+
+fn read_index(reader: &mut Reader) -> Result<u32, TokenError> {
+    match reader.read_u32()? {
+        0 => Err(TokenError(InternalError::InvalidInputError)),
+        index => Ok(index),
+    }
+}
+
+/// The state of one participant in the DKG, acting as both a dealer and a recipient.
+#[derive(Debug)]
+pub struct Participant {
+    index: u32,
+    threshold: u32,
+    participants: u32,
+    coefficients: Vec<Scalar>,
+    commitments: Vec<Option<DealerCommitment>>,
+    shares: Vec<Option<Scalar>>,
+    disqualified: Vec<bool>,
+}
+
+/// Overwrite polynomial and shares with null when they go out of scope.
+impl Drop for Participant {
+    fn drop(&mut self) {
+        self.coefficients.zeroize();
+        for share in self.shares.iter_mut().flatten() {
+            share.zeroize();
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+impl Participant {
+    /// Start the DKG as participant `index` of `participants`, any `threshold` of whom will
+    /// be able to issue tokens with the resulting key.
+    ///
+    /// Returns a `TokenError` if `index` is not between 1 and `participants`, or `threshold`
+    /// is zero or greater than `participants`.
+    pub fn new<T>(
+        rng: &mut T,
+        index: u32,
+        threshold: u32,
+        participants: u32,
+    ) -> Result<Self, TokenError>
+    where
+        T: Rng + CryptoRng,
+    {
+        if index == 0 || index > participants || threshold == 0 || threshold > participants {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+
+        let coefficients: Vec<Scalar> = iter::repeat_with(|| Scalar::random(rng))
+            .take(threshold as usize)
+            .collect();
+
+        let mut participant = Participant {
+            index,
+            threshold,
+            participants,
+            coefficients,
+            commitments: vec![None; participants as usize],
+            shares: vec![None; participants as usize],
+            disqualified: vec![false; participants as usize],
+        };
+        let own = participant.position(index)?;
+        participant.commitments[own] = Some(participant.commitment());
+        participant.shares[own] = Some(participant.evaluate(index));
+        Ok(participant)
+    }
+
+    /// The index of this participant.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    fn position(&self, index: u32) -> Result<usize, TokenError> {
+        if index == 0 || index > self.participants {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(index as usize - 1)
+    }
+
+    fn evaluate(&self, index: u32) -> Scalar {
+        let x = Scalar::from(index);
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(Scalar::zero(), |acc, a| acc * x + a)
+    }
+
+    /// The `DealerCommitment` to this participant's polynomial, to be broadcast to all other
+    /// participants.
+    pub fn commitment(&self) -> DealerCommitment {
+        DealerCommitment {
+            dealer: self.index,
+            coefficients: self
+                .coefficients
+                .iter()
+                .map(|a| (a * &constants::RISTRETTO_BASEPOINT_TABLE).compress())
+                .collect(),
+        }
+    }
+
+    /// The `SecretShare` of this participant's polynomial for `recipient`, to be sent to it
+    /// privately.
+    pub fn share_for(&self, recipient: u32) -> Result<SecretShare, TokenError> {
+        self.position(recipient)?;
+        Ok(SecretShare {
+            dealer: self.index,
+            recipient,
+            value: self.evaluate(recipient),
+        })
+    }
+
+    /// Answer a `Complaint` against this participant by revealing the disputed share, to be
+    /// broadcast to all other participants.
+    pub fn reveal(&self, complaint: &Complaint) -> Result<SecretShare, TokenError> {
+        if complaint.dealer != self.index {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        self.share_for(complaint.accuser)
+    }
+
+    /// Record the `DealerCommitment` broadcast by another participant.
+    ///
+    /// Commitments of the wrong degree are rejected, leaving the dealer out of the group key.
+    pub fn receive_commitment(&mut self, commitment: DealerCommitment) -> Result<(), TokenError> {
+        let position = self.position(commitment.dealer)?;
+        if commitment.coefficients.len() != self.threshold as usize
+            || self.commitments[position].is_some()
+        {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        self.commitments[position] = Some(commitment);
+        Ok(())
+    }
+
+    /// Verify and record the `SecretShare` sent to this participant by a dealer, whose
+    /// commitment must already have been received.
+    ///
+    /// Returns the `Complaint` to broadcast if the share is invalid.
+    pub fn receive_share(&mut self, share: SecretShare) -> Result<Option<Complaint>, TokenError> {
+        let position = self.position(share.dealer)?;
+        if share.recipient != self.index || self.shares[position].is_some() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let commitment = self.commitments[position]
+            .as_ref()
+            .ok_or(TokenError(InternalError::InvalidInputError))?;
+
+        match commitment.verify(&share) {
+            Ok(()) => {
+                self.shares[position] = Some(share.value);
+                Ok(None)
+            }
+            Err(TokenError(InternalError::VerifyError)) => Ok(Some(Complaint {
+                accuser: self.index,
+                dealer: share.dealer,
+            })),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Resolve a broadcast `Complaint` given the share revealed by the accused dealer, if it
+    /// answered.
+    ///
+    /// The dealer is disqualified unless the revealed share is valid, in which case the
+    /// accuser adopts it in place of the one it was sent.
+    pub fn resolve(
+        &mut self,
+        complaint: &Complaint,
+        revealed: Option<SecretShare>,
+    ) -> Result<(), TokenError> {
+        let position = self.position(complaint.dealer)?;
+        self.position(complaint.accuser)?;
+
+        let revealed = revealed.filter(|share| {
+            share.recipient == complaint.accuser
+                && matches!(
+                    &self.commitments[position],
+                    Some(commitment) if commitment.verify(share).is_ok()
+                )
+        });
+        match revealed {
+            Some(share) if complaint.accuser == self.index => {
+                self.shares[position] = Some(share.value);
+            }
+            Some(_) => {}
+            None => self.disqualified[position] = true,
+        }
+        Ok(())
+    }
+
+    /// The indices of the dealers whose polynomials make up the group key.
+    pub fn qualified(&self) -> Vec<u32> {
+        (1..=self.participants)
+            .filter(|&index| {
+                let position = index as usize - 1;
+                self.commitments[position].is_some() && !self.disqualified[position]
+            })
+            .collect()
+    }
+
+    fn qualified_commitments(&self) -> Result<Vec<&DealerCommitment>, TokenError> {
+        let commitments: Vec<&DealerCommitment> = self
+            .qualified()
+            .into_iter()
+            .filter_map(|index| self.commitments[index as usize - 1].as_ref())
+            .collect();
+        if commitments.len() < self.threshold as usize {
+            return Err(TokenError(InternalError::VerifyError));
+        }
+        Ok(commitments)
+    }
+
+    /// The group `PublicKey`, \\(Y = \\sum_j A_{j0}\\) over the qualified dealers.
+    pub fn public_key(&self) -> Result<PublicKey, TokenError> {
+        let Y = self
+            .qualified_commitments()?
+            .iter()
+            .try_fold(RistrettoPoint::default(), |acc, commitment| {
+                Ok(acc + decompress(&commitment.coefficients[0])?)
+            })?;
+        Ok(PublicKey(Y.compress()))
+    }
+
+    /// The `PublicKeyShare` of every participant, for constructing a `threshold::Combiner`.
+    pub fn public_shares(&self) -> Result<Vec<PublicKeyShare>, TokenError> {
+        let commitments = self.qualified_commitments()?;
+        (1..=self.participants)
+            .map(|index| {
+                let Y = commitments
+                    .iter()
+                    .try_fold(RistrettoPoint::default(), |acc, commitment| {
+                        Ok(acc + commitment.evaluate(index)?)
+                    })?;
+                Ok(PublicKeyShare {
+                    index,
+                    public_key: PublicKey(Y.compress()),
+                })
+            })
+            .collect()
+    }
+
+    /// Complete the DKG, returning this participant's `KeyShare` of the group key.
+    ///
+    /// Returns a `TokenError` if fewer than `threshold` dealers are qualified, or a valid
+    /// share has not been received from each of them.
+    pub fn finish(&self) -> Result<KeyShare, TokenError> {
+        let public_key = self.public_key()?;
+        let mut k = Scalar::zero();
+        for index in self.qualified() {
+            k += self.shares[index as usize - 1].ok_or(TokenError(InternalError::VerifyError))?;
+        }
+        let key_share = KeyShare::new(self.index, public_key, k);
+        k.zeroize();
+        Ok(key_share)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::{BlindedToken, Token};
+    use crate::threshold::{Combiner, PartialIssuance};
+
+    /// Run the first round between all `participants`, except that dealer 2 sends participant
+    /// 1 a bad share.
+    fn deal(participants: &mut [Participant]) -> Vec<Complaint> {
+        let commitments: Vec<DealerCommitment> =
+            participants.iter().map(|p| p.commitment()).collect();
+        let mut complaints = Vec::new();
+        for dealer in 0..participants.len() {
+            for recipient in 0..participants.len() {
+                if dealer == recipient {
+                    continue;
+                }
+                let mut share = participants[dealer]
+                    .share_for(participants[recipient].index())
+                    .unwrap();
+                if dealer == 1 && recipient == 0 {
+                    share.value += Scalar::one();
+                }
+                let recipient = &mut participants[recipient];
+                recipient
+                    .receive_commitment(commitments[dealer].clone())
+                    .unwrap();
+                complaints.extend(recipient.receive_share(share).unwrap());
+            }
+        }
+        complaints
+    }
+
+    #[test]
+    fn dkg_works() {
+        let mut rng = OsRng;
+
+        let mut participants: Vec<Participant> = (1..=4)
+            .map(|index| Participant::new(&mut rng, index, 3, 4).unwrap())
+            .collect();
+
+        let complaints = deal(&mut participants);
+        assert_eq!(
+            complaints,
+            vec![Complaint {
+                accuser: 1,
+                dealer: 2
+            }]
+        );
+
+        // Dealer 2 answers with the correct share, so it is not disqualified.
+        let revealed = participants[1].reveal(&complaints[0]).unwrap();
+        for participant in participants.iter_mut() {
+            let revealed = SecretShare::from_bytes(&revealed.to_bytes()).unwrap();
+            participant.resolve(&complaints[0], Some(revealed)).unwrap();
+            assert_eq!(participant.qualified(), vec![1, 2, 3, 4]);
+        }
+
+        let public_key = participants[0].public_key().unwrap();
+        let public_shares = participants[0].public_shares().unwrap();
+        let key_shares: Vec<KeyShare> = participants.iter().map(|p| p.finish().unwrap()).collect();
+        for (participant, key_share) in participants.iter().zip(&key_shares) {
+            assert_eq!(participant.public_key().unwrap().0, public_key.0);
+            assert_eq!(key_share.group_public_key().0, public_key.0);
+            assert_eq!(
+                key_share.public_share().public_key.0,
+                public_shares[key_share.index() as usize - 1].public_key.0
+            );
+        }
+
+        // Any three of the shares can issue tokens under the group key.
+        let combiner = Combiner::new(public_key, 3, public_shares).unwrap();
+        let tokens: Vec<Token> = (0..3)
+            .map(|_| Token::random::<Sha512, _>(&mut rng))
+            .collect();
+        let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+        let partials: Vec<PartialIssuance> = key_shares[1..]
+            .iter()
+            .map(|share| share.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap())
+            .collect();
+        let signed_tokens = combiner
+            .combine::<Sha512>(&blinded_tokens, &partials)
+            .unwrap();
+
+        let partials: Vec<PartialIssuance> = key_shares[..3]
+            .iter()
+            .map(|share| share.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap())
+            .collect();
+        let other_signed_tokens = combiner
+            .combine::<Sha512>(&blinded_tokens, &partials)
+            .unwrap();
+        for (a, b) in signed_tokens.iter().zip(&other_signed_tokens) {
+            assert_eq!(a.0, b.0);
+        }
+    }
+
+    #[test]
+    fn disqualifies_dealers() {
+        let mut rng = OsRng;
+
+        let mut participants: Vec<Participant> = (1..=3)
+            .map(|index| Participant::new(&mut rng, index, 2, 3).unwrap())
+            .collect();
+
+        let complaints = deal(&mut participants);
+
+        // Dealer 2 reveals another bad share, so it is disqualified.
+        let mut revealed = participants[1].reveal(&complaints[0]).unwrap();
+        revealed.value += Scalar::one();
+        for participant in participants.iter_mut() {
+            let revealed = SecretShare::from_bytes(&revealed.to_bytes()).unwrap();
+            participant.resolve(&complaints[0], Some(revealed)).unwrap();
+            assert_eq!(participant.qualified(), vec![1, 3]);
+        }
+
+        let public_key = participants[0].public_key().unwrap();
+        let expected = participants[0].commitment().coefficients[0]
+            .decompress()
+            .unwrap()
+            + participants[2].commitment().coefficients[0]
+                .decompress()
+                .unwrap();
+        assert_eq!(public_key.0, expected.compress());
+        for participant in &participants {
+            assert_eq!(
+                participant.finish().unwrap().group_public_key().0,
+                public_key.0
+            );
+        }
+
+        // Without an answer the threshold can no longer be met.
+        participants[0]
+            .resolve(
+                &Complaint {
+                    accuser: 2,
+                    dealer: 3,
+                },
+                None,
+            )
+            .unwrap();
+        assert!(participants[0].finish().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_messages() {
+        let mut rng = OsRng;
+
+        assert!(Participant::new(&mut rng, 0, 2, 3).is_err());
+        assert!(Participant::new(&mut rng, 4, 2, 3).is_err());
+        assert!(Participant::new(&mut rng, 1, 4, 3).is_err());
+
+        let mut first = Participant::new(&mut rng, 1, 2, 3).unwrap();
+        let second = Participant::new(&mut rng, 2, 2, 3).unwrap();
+        let third = Participant::new(&mut rng, 3, 3, 3).unwrap();
+
+        // Share before commitment
+        assert!(first.receive_share(second.share_for(1).unwrap()).is_err());
+        // Wrong degree
+        assert!(first.receive_commitment(third.commitment()).is_err());
+
+        let commitment = DealerCommitment::decode_base64(&second.commitment().encode_base64());
+        first.receive_commitment(commitment.unwrap()).unwrap();
+        assert!(first.receive_commitment(second.commitment()).is_err());
+        // Share for someone else
+        assert!(first.receive_share(second.share_for(3).unwrap()).is_err());
+        assert_eq!(
+            first.receive_share(second.share_for(1).unwrap()).unwrap(),
+            None
+        );
+        // Duplicate share
+        assert!(first.receive_share(second.share_for(1).unwrap()).is_err());
+        // Dealer 3 never committed, so is not qualified.
+        assert_eq!(first.qualified(), vec![1, 2]);
+
+        assert!(first
+            .reveal(&Complaint {
+                accuser: 2,
+                dealer: 3
+            })
+            .is_err());
+        let complaint = Complaint::decode_base64(
+            &Complaint {
+                accuser: 1,
+                dealer: 2,
+            }
+            .encode_base64(),
+        )
+        .unwrap();
+        assert_eq!(complaint.accuser, 1);
+        assert!(Complaint::from_bytes(&[0u8; COMPLAINT_LENGTH]).is_err());
+        assert!(DealerCommitment::from_bytes(&second.commitment().to_bytes()[..40]).is_err());
+    }
+}
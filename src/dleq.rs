@@ -7,19 +7,58 @@ use std::vec::Vec;
 use core::iter;
 
 use curve25519_dalek::constants;
-use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::VartimeMultiscalarMul;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
 use digest::generic_array::typenum::U64;
 use digest::Digest;
-use rand::{CryptoRng, Rng, SeedableRng};
-use rand_chacha::ChaChaRng;
+#[cfg(feature = "merlin")]
+use merlin::Transcript;
+use rand::{CryptoRng, Rng};
 
+use crate::commitment::VerifiedKeyCommitment;
+#[cfg(feature = "merlin")]
+use crate::dleq_merlin::MerlinBatchDLEQProof;
 use crate::errors::{InternalError, TokenError};
 use crate::oprf::*;
+use crate::transcript::{DigestTranscript, ProofTranscript};
 
 /// The length of a `DLEQProof`, in bytes.
 pub const DLEQ_PROOF_LENGTH: usize = 64;
+/// The length of a `BatchableDLEQProof`, in bytes.
+pub const BATCHABLE_DLEQ_PROOF_LENGTH: usize = 96;
+/// The length of a `TaggedBatchDLEQProof`, in bytes.
+pub const TAGGED_BATCH_DLEQ_PROOF_LENGTH: usize = 1 + DLEQ_PROOF_LENGTH;
+
+/// A `DigestTranscript` bound to `context`, for the `*_with_context` proofs.
+fn context_transcript<D>(context: &[u8]) -> DigestTranscript<D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    let mut transcript = DigestTranscript::<D>::new();
+    transcript.append_context(b"context", context);
+    transcript
+}
+
+/// Unblind each `SignedToken` using the corresponding `Token`, requiring exactly one `Token`
+/// for each `SignedToken`.
+pub(crate) fn unblind_all<'a, I>(
+    tokens: I,
+    signed_tokens: &[SignedToken],
+) -> Result<Vec<UnblindedToken>, TokenError>
+where
+    I: IntoIterator<Item = &'a Token>,
+{
+    let unblinded_tokens = tokens
+        .into_iter()
+        .zip(signed_tokens.iter())
+        .map(|(token, signed_token)| token.unblind(signed_token))
+        .collect::<Result<Vec<UnblindedToken>, TokenError>>()?;
+    if unblinded_tokens.len() != signed_tokens.len() {
+        return Err(TokenError(InternalError::LengthMismatchError));
+    }
+    Ok(unblinded_tokens)
+}
 
 /// A `DLEQProof` is a proof of the equivalence of the discrete logarithm between two pairs of points.
 #[allow(non_snake_case)]
@@ -42,37 +81,44 @@ impl_serde!(DLEQProof);
 #[allow(non_snake_case)]
 impl DLEQProof {
     /// Construct a new `DLEQProof`
-    fn _new<D, T>(rng: &mut T, P: RistrettoPoint, Q: RistrettoPoint, k: &SigningKey) -> Self
+    pub(crate) fn _new<F, T>(
+        transcript: &mut F,
+        rng: &mut T,
+        P: RistrettoPoint,
+        Q: RistrettoPoint,
+        k: &SigningKey,
+    ) -> Self
     where
-        D: Digest<OutputSize = U64> + Default,
+        F: ProofTranscript,
         T: Rng + CryptoRng,
     {
-        let t = Scalar::random(rng);
-
-        let A = &t * &constants::RISTRETTO_BASEPOINT_TABLE;
-        let B = t * P;
+        DLEQProof::_new_with_commitments(transcript, rng, P, Q, k).0
+    }
 
-        let mut h = D::default();
+    /// Construct a new `DLEQProof`, also returning its commitments \\(A\\) and \\(B\\)
+    fn _new_with_commitments<F, T>(
+        transcript: &mut F,
+        rng: &mut T,
+        P: RistrettoPoint,
+        Q: RistrettoPoint,
+        k: &SigningKey,
+    ) -> (Self, RistrettoPoint, RistrettoPoint)
+    where
+        F: ProofTranscript,
+        T: Rng + CryptoRng,
+    {
+        DLEQProof::append_statement(transcript, &k.public_key, &P, &Q);
 
-        let X = constants::RISTRETTO_BASEPOINT_COMPRESSED;
-        let Y = k.public_key.0;
-        let P = P.compress();
-        let Q = Q.compress();
-        let A = A.compress();
-        let B = B.compress();
+        let t = transcript.witness_nonce(b"k", &k.k, rng);
 
-        h.update(X.as_bytes());
-        h.update(Y.as_bytes());
-        h.update(P.as_bytes());
-        h.update(Q.as_bytes());
-        h.update(A.as_bytes());
-        h.update(B.as_bytes());
+        let A = &t * &constants::RISTRETTO_BASEPOINT_TABLE;
+        let B = t * P;
 
-        let c = Scalar::from_hash(h);
+        let c = DLEQProof::commitment_challenge(transcript, &A, &B);
 
         let s = t - c * k.k;
 
-        DLEQProof { c, s }
+        (DLEQProof { c, s }, A, B)
     }
 
     /// Construct a new `DLEQProof`
@@ -86,7 +132,29 @@ impl DLEQProof {
         D: Digest<OutputSize = U64> + Default,
         T: Rng + CryptoRng,
     {
-        Ok(Self::_new::<D, T>(
+        Self::new_with_transcript(
+            &mut DigestTranscript::<D>::new(),
+            rng,
+            blinded_token,
+            signed_token,
+            k,
+        )
+    }
+
+    /// Construct a new `DLEQProof` with the challenge derived from `transcript`
+    pub fn new_with_transcript<F, T>(
+        transcript: &mut F,
+        rng: &mut T,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        k: &SigningKey,
+    ) -> Result<Self, TokenError>
+    where
+        F: ProofTranscript,
+        T: Rng + CryptoRng,
+    {
+        Ok(Self::_new(
+            transcript,
             rng,
             blinded_token
                 .0
@@ -100,17 +168,61 @@ impl DLEQProof {
         ))
     }
 
+    /// Compute the challenge \\(c=H_3(X,Y,P,Q,A,B)\\)
+    pub(crate) fn challenge<F>(
+        transcript: &mut F,
+        public_key: &PublicKey,
+        P: &RistrettoPoint,
+        Q: &RistrettoPoint,
+        A: &RistrettoPoint,
+        B: &RistrettoPoint,
+    ) -> Scalar
+    where
+        F: ProofTranscript,
+    {
+        DLEQProof::append_statement(transcript, public_key, P, Q);
+        DLEQProof::commitment_challenge(transcript, A, B)
+    }
+
+    /// Append the statement \\(X,Y,P,Q\\) from which the nonce and challenge are derived
+    fn append_statement<F>(
+        transcript: &mut F,
+        public_key: &PublicKey,
+        P: &RistrettoPoint,
+        Q: &RistrettoPoint,
+    ) where
+        F: ProofTranscript,
+    {
+        transcript.domain_separator(b"dleq");
+
+        transcript.append_point(b"X", &constants::RISTRETTO_BASEPOINT_COMPRESSED);
+        transcript.append_point(b"Y", &public_key.0);
+        transcript.append_point(b"P", &P.compress());
+        transcript.append_point(b"Q", &Q.compress());
+    }
+
+    /// Append the commitments \\(A,B\\) and compute the challenge
+    fn commitment_challenge<F>(transcript: &mut F, A: &RistrettoPoint, B: &RistrettoPoint) -> Scalar
+    where
+        F: ProofTranscript,
+    {
+        transcript.append_point(b"A", &A.compress());
+        transcript.append_point(b"B", &B.compress());
+
+        transcript.challenge_scalar(b"c")
+    }
+
     /// Verify the `DLEQProof`
-    fn _verify<D>(
+    pub(crate) fn _verify<F>(
         &self,
+        transcript: &mut F,
         P: RistrettoPoint,
         Q: RistrettoPoint,
         public_key: &PublicKey,
     ) -> Result<(), TokenError>
     where
-        D: Digest<OutputSize = U64> + Default,
+        F: ProofTranscript,
     {
-        let X = constants::RISTRETTO_BASEPOINT_COMPRESSED;
         let Y = public_key.0;
 
         let A = (&self.s * &constants::RISTRETTO_BASEPOINT_TABLE)
@@ -119,21 +231,7 @@ impl DLEQProof {
                     .ok_or(TokenError(InternalError::PointDecompressionError))?);
         let B = (self.s * P) + (self.c * Q);
 
-        let A = A.compress();
-        let B = B.compress();
-        let P = P.compress();
-        let Q = Q.compress();
-
-        let mut h = D::default();
-
-        h.update(X.as_bytes());
-        h.update(Y.as_bytes());
-        h.update(P.as_bytes());
-        h.update(Q.as_bytes());
-        h.update(A.as_bytes());
-        h.update(B.as_bytes());
-
-        let c = Scalar::from_hash(h);
+        let c = DLEQProof::challenge(transcript, public_key, &P, &Q, &A, &B);
 
         if c == self.c {
             Ok(())
@@ -152,15 +250,130 @@ impl DLEQProof {
     where
         D: Digest<OutputSize = U64> + Default,
     {
-        self._verify::<D>(
+        self.verify_with_transcript(
+            &mut DigestTranscript::<D>::new(),
+            blinded_token,
+            signed_token,
+            public_key,
+        )
+    }
+
+    /// Verify a `DLEQProof` constructed with `DLEQProof::new_with_transcript`
+    pub fn verify_with_transcript<F>(
+        &self,
+        transcript: &mut F,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError>
+    where
+        F: ProofTranscript,
+    {
+        self._verify(
+            transcript,
+            blinded_token
+                .0
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?,
+            signed_token
+                .0
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?,
+            public_key,
+        )
+    }
+
+    /// Construct a new `DLEQProof` for a `SignedToken` produced by `SigningKey::sign_with_info`
+    ///
+    /// Since \\(P = Q^{k'}\\), this proves \\(\log_X(Y') = \log_Q(P)\\) for the tweaked key.
+    pub fn new_with_info<D, T>(
+        rng: &mut T,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        k: &SigningKey,
+        info: &[u8],
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        Ok(Self::_new(
+            &mut DigestTranscript::<D>::new(),
+            rng,
+            signed_token
+                .0
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?,
             blinded_token
                 .0
                 .decompress()
                 .ok_or(TokenError(InternalError::PointDecompressionError))?,
+            &k.tweak::<D>(info)?,
+        ))
+    }
+
+    /// Verify a `DLEQProof` constructed with `DLEQProof::new_with_info`
+    pub fn verify_with_info<D>(
+        &self,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        public_key: &PublicKey,
+        info: &[u8],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        self._verify(
+            &mut DigestTranscript::<D>::new(),
             signed_token
                 .0
                 .decompress()
                 .ok_or(TokenError(InternalError::PointDecompressionError))?,
+            blinded_token
+                .0
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?,
+            &public_key.tweak::<D>(info)?,
+        )
+    }
+
+    /// Construct a new `DLEQProof` whose challenge also covers `context`, such as an issuer ID,
+    /// request ID or key epoch, so that it does not verify in any other context.
+    pub fn new_with_context<D, T>(
+        rng: &mut T,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        k: &SigningKey,
+        context: &[u8],
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        Self::new_with_transcript(
+            &mut context_transcript::<D>(context),
+            rng,
+            blinded_token,
+            signed_token,
+            k,
+        )
+    }
+
+    /// Verify a `DLEQProof` constructed with `DLEQProof::new_with_context`
+    pub fn verify_with_context<D>(
+        &self,
+        blinded_token: &BlindedToken,
+        signed_token: &SignedToken,
+        public_key: &PublicKey,
+        context: &[u8],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        self.verify_with_transcript(
+            &mut context_transcript::<D>(context),
+            blinded_token,
+            signed_token,
             public_key,
         )
     }
@@ -208,7 +421,7 @@ impl DLEQProof {
 /// pair of points and one or more other pairs of points.
 #[allow(non_snake_case)]
 #[derive(Debug)]
-pub struct BatchDLEQProof(DLEQProof);
+pub struct BatchDLEQProof(pub(crate) DLEQProof);
 
 #[cfg(any(test, feature = "base64"))]
 impl_base64!(BatchDLEQProof);
@@ -218,49 +431,51 @@ impl_serde!(BatchDLEQProof);
 
 #[allow(non_snake_case)]
 impl BatchDLEQProof {
-    fn calculate_composites<D>(
+    fn composite_scalars<F>(
+        transcript: &mut F,
         blinded_tokens: &[BlindedToken],
         signed_tokens: &[SignedToken],
         public_key: &PublicKey,
-    ) -> Result<(RistrettoPoint, RistrettoPoint), TokenError>
+    ) -> Result<Vec<Scalar>, TokenError>
     where
-        D: Digest<OutputSize = U64> + Default,
+        F: ProofTranscript,
     {
         if blinded_tokens.len() != signed_tokens.len() {
             return Err(TokenError(InternalError::LengthMismatchError));
         }
 
-        let mut h = D::default();
-
-        h.update(constants::RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
-        h.update(public_key.0.as_bytes());
+        transcript.append_point(b"X", &constants::RISTRETTO_BASEPOINT_COMPRESSED);
+        transcript.append_point(b"Y", &public_key.0);
 
         for (Pi, Qi) in blinded_tokens.iter().zip(signed_tokens.iter()) {
-            h.update(Pi.0.as_bytes());
-            h.update(Qi.0.as_bytes());
+            transcript.append_point(b"Pi", &Pi.0);
+            transcript.append_point(b"Qi", &Qi.0);
         }
 
-        let result = h.finalize();
-
-        let mut seed: [u8; 32] = [0u8; 32];
-        seed.copy_from_slice(&result[..32]);
+        Ok(transcript.challenge_scalars(b"c_i", blinded_tokens.len()))
+    }
 
-        let mut prng: ChaChaRng = SeedableRng::from_seed(seed);
-        let c_m: Vec<Scalar> = iter::repeat_with(|| Scalar::random(&mut prng))
-            .take(blinded_tokens.len())
-            .collect();
+    pub(crate) fn calculate_composites<F>(
+        transcript: &mut F,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<(RistrettoPoint, RistrettoPoint), TokenError>
+    where
+        F: ProofTranscript,
+    {
+        let c_m = BatchDLEQProof::composite_scalars(
+            transcript,
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )?;
 
-        let M = RistrettoPoint::optional_multiscalar_mul(
-            &c_m,
-            blinded_tokens.iter().map(|Pi| Pi.0.decompress()),
-        )
-        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let M = composite(&c_m, blinded_tokens, |Pi| Pi.0.decompress())
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
 
-        let Z = RistrettoPoint::optional_multiscalar_mul(
-            &c_m,
-            signed_tokens.iter().map(|Qi| Qi.0.decompress()),
-        )
-        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let Z = composite(&c_m, signed_tokens, |Qi| Qi.0.decompress())
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
 
         Ok((M, Z))
     }
@@ -276,12 +491,38 @@ impl BatchDLEQProof {
         D: Digest<OutputSize = U64> + Default,
         T: Rng + CryptoRng,
     {
-        let (M, Z) = BatchDLEQProof::calculate_composites::<D>(
+        Self::new_with_transcript(
+            &mut DigestTranscript::<D>::new(),
+            rng,
+            blinded_tokens,
+            signed_tokens,
+            signing_key,
+        )
+    }
+
+    /// Construct a new `BatchDLEQProof` with the composites and challenge derived from
+    /// `transcript`
+    pub fn new_with_transcript<F, T>(
+        transcript: &mut F,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        signing_key: &SigningKey,
+    ) -> Result<Self, TokenError>
+    where
+        F: ProofTranscript,
+        T: Rng + CryptoRng,
+    {
+        transcript.domain_separator(b"dleq");
+
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            transcript,
             blinded_tokens,
             signed_tokens,
             &signing_key.public_key,
         )?;
-        Ok(BatchDLEQProof(DLEQProof::_new::<D, T>(
+        Ok(BatchDLEQProof(DLEQProof::_new(
+            transcript,
             rng,
             M,
             Z,
@@ -299,10 +540,35 @@ impl BatchDLEQProof {
     where
         D: Digest<OutputSize = U64> + Default,
     {
-        let (M, Z) =
-            BatchDLEQProof::calculate_composites::<D>(blinded_tokens, signed_tokens, public_key)?;
+        self.verify_with_transcript(
+            &mut DigestTranscript::<D>::new(),
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )
+    }
+
+    /// Verify a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_transcript`
+    pub fn verify_with_transcript<F>(
+        &self,
+        transcript: &mut F,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError>
+    where
+        F: ProofTranscript,
+    {
+        transcript.domain_separator(b"dleq");
+
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            transcript,
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )?;
 
-        self.0._verify::<D>(M, Z, public_key)
+        self.0._verify(transcript, M, Z, public_key)
     }
 
     /// Verify the `BatchDLEQProof` then unblind the `SignedToken`s using each corresponding `Token`
@@ -319,48 +585,715 @@ impl BatchDLEQProof {
     {
         self.verify::<D>(blinded_tokens, signed_tokens, public_key)?;
 
-        let unblinded_tokens: Result<Vec<UnblindedToken>, TokenError> = tokens
-            .into_iter()
-            .zip(signed_tokens.iter())
-            .map(|(token, signed_token)| token.unblind(signed_token))
-            .collect();
-        unblinded_tokens.and_then(|unblinded_tokens| {
-            if unblinded_tokens.len() != signed_tokens.len() {
-                return Err(TokenError(InternalError::LengthMismatchError));
-            }
-            Ok(unblinded_tokens)
-        })
+        unblind_all(tokens, signed_tokens)
     }
-}
 
-impl BatchDLEQProof {
-    /// Convert this `BatchDLEQProof` to a byte array.
-    pub fn to_bytes(&self) -> [u8; DLEQ_PROOF_LENGTH] {
-        self.0.to_bytes()
+    /// Verify and unblind as with `verify_and_unblind`, refusing any `public_key` which is
+    /// not listed in `commitment` or is outside its validity window at `time`.
+    pub fn verify_and_unblind_committed<'a, D, I>(
+        &self,
+        tokens: I,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        commitment: &VerifiedKeyCommitment,
+        time: u64,
+    ) -> Result<Vec<UnblindedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        I: IntoIterator<Item = &'a Token>,
+    {
+        commitment.check(public_key, time)?;
+        self.verify_and_unblind::<D, I>(tokens, blinded_tokens, signed_tokens, public_key)
     }
 
-    #[cfg(feature = "serde")]
-    fn bytes_length_error() -> TokenError {
-        DLEQProof::bytes_length_error()
+    /// Construct a new `BatchDLEQProof` for `SignedToken`s produced by `SigningKey::sign_with_info`
+    pub fn new_with_info<D, T>(
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        signing_key: &SigningKey,
+        info: &[u8],
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let tweaked_key = signing_key.tweak::<D>(info)?;
+        let mut transcript = DigestTranscript::<D>::new();
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut transcript,
+            blinded_tokens,
+            signed_tokens,
+            &tweaked_key.public_key,
+        )?;
+        // The tokens were signed with the inverse of the tweaked key, so \(M = Z^{k'}\)
+        Ok(BatchDLEQProof(DLEQProof::_new(
+            &mut transcript,
+            rng,
+            Z,
+            M,
+            &tweaked_key,
+        )))
     }
 
-    /// Construct a `BatchDLEQProof` from a slice of bytes.
-    pub fn from_bytes(bytes: &[u8]) -> Result<BatchDLEQProof, TokenError> {
-        DLEQProof::from_bytes(bytes).map(BatchDLEQProof)
+    /// Verify a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_info`
+    pub fn verify_with_info<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        info: &[u8],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let tweaked_public_key = public_key.tweak::<D>(info)?;
+        let mut transcript = DigestTranscript::<D>::new();
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut transcript,
+            blinded_tokens,
+            signed_tokens,
+            &tweaked_public_key,
+        )?;
+
+        self.0._verify(&mut transcript, Z, M, &tweaked_public_key)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use curve25519_dalek::ristretto::CompressedRistretto;
-    use rand::rngs::OsRng;
-    use sha2::Sha512;
+    /// Verify a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_info` then unblind
+    /// the `SignedToken`s using each corresponding `Token`
+    pub fn verify_and_unblind_with_info<'a, D, I>(
+        &self,
+        tokens: I,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        info: &[u8],
+    ) -> Result<Vec<UnblindedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        I: IntoIterator<Item = &'a Token>,
+    {
+        self.verify_with_info::<D>(blinded_tokens, signed_tokens, public_key, info)?;
 
-    use super::*;
-    use crate::oprf::Token;
+        unblind_all(tokens, signed_tokens)
+    }
 
-    #[test]
-    #[allow(non_snake_case)]
+    /// Construct a new `BatchDLEQProof` whose challenge also covers `context`, such as an issuer
+    /// ID, request ID or key epoch, so that it does not verify in any other context.
+    pub fn new_with_context<D, T>(
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        signing_key: &SigningKey,
+        context: &[u8],
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        Self::new_with_transcript(
+            &mut context_transcript::<D>(context),
+            rng,
+            blinded_tokens,
+            signed_tokens,
+            signing_key,
+        )
+    }
+
+    /// Verify a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_context`
+    pub fn verify_with_context<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        context: &[u8],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        self.verify_with_transcript(
+            &mut context_transcript::<D>(context),
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )
+    }
+
+    /// Verify a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_context` then unblind
+    /// the `SignedToken`s using each corresponding `Token`
+    pub fn verify_and_unblind_with_context<'a, D, I>(
+        &self,
+        tokens: I,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        context: &[u8],
+    ) -> Result<Vec<UnblindedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        I: IntoIterator<Item = &'a Token>,
+    {
+        self.verify_with_context::<D>(blinded_tokens, signed_tokens, public_key, context)?;
+
+        unblind_all(tokens, signed_tokens)
+    }
+}
+
+impl BatchDLEQProof {
+    /// Convert this `BatchDLEQProof` to a byte array.
+    pub fn to_bytes(&self) -> [u8; DLEQ_PROOF_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    #[cfg(feature = "serde")]
+    fn bytes_length_error() -> TokenError {
+        DLEQProof::bytes_length_error()
+    }
+
+    /// Construct a `BatchDLEQProof` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BatchDLEQProof, TokenError> {
+        DLEQProof::from_bytes(bytes).map(BatchDLEQProof)
+    }
+
+    /// Convert this `BatchDLEQProof` to a `BatchableDLEQProof` over the same public inputs.
+    ///
+    /// The commitments are recomputed as \\(A = sX + cY\\) and \\(B = sM + cZ\\), which
+    /// costs about as much as verifying the proof. The result is valid exactly when this proof
+    /// is, and converts back to it with `BatchableDLEQProof::to_compact`.
+    #[allow(non_snake_case)]
+    pub fn to_batchable<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<BatchableDLEQProof, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut DigestTranscript::<D>::new(),
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )?;
+        let Y = public_key
+            .0
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+
+        let DLEQProof { c, s } = self.0;
+        let A = RistrettoPoint::vartime_multiscalar_mul(
+            &[s, c],
+            &[constants::RISTRETTO_BASEPOINT_POINT, Y],
+        );
+        let B = RistrettoPoint::vartime_multiscalar_mul(&[s, c], &[M, Z]);
+
+        Ok(BatchableDLEQProof {
+            A: A.compress(),
+            B: B.compress(),
+            s,
+        })
+    }
+}
+
+/// A `BatchableDLEQProof` is a `BatchDLEQProof` which carries its commitments \\(A\\) and
+/// \\(B\\) in place of the challenge \\(c\\).
+///
+/// Since the verification equations \\(A = sX + cY\\) and \\(B = sM + cZ\\) can then be
+/// checked directly, many proofs can be verified together with `BatchableDLEQProof::verify_batch`.
+#[allow(non_snake_case)]
+#[derive(Debug)]
+pub struct BatchableDLEQProof {
+    /// `A` is a `CompressedRistretto`
+    /// \\(A = tX\\)
+    pub(crate) A: CompressedRistretto,
+    /// `B` is a `CompressedRistretto`
+    /// \\(B = tM\\)
+    pub(crate) B: CompressedRistretto,
+    /// `s` is a `Scalar`
+    /// \\(s = (t - ck) \mod q\\)
+    pub(crate) s: Scalar,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(BatchableDLEQProof);
+
+#[cfg(feature = "serde")]
+impl_serde!(BatchableDLEQProof);
+
+#[allow(non_snake_case)]
+impl BatchableDLEQProof {
+    /// Construct a new `BatchableDLEQProof`
+    pub fn new<D, T>(
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        signing_key: &SigningKey,
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let mut transcript = DigestTranscript::<D>::new();
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut transcript,
+            blinded_tokens,
+            signed_tokens,
+            &signing_key.public_key,
+        )?;
+        let (proof, A, B) =
+            DLEQProof::_new_with_commitments(&mut transcript, rng, M, Z, signing_key);
+        Ok(BatchableDLEQProof {
+            A: A.compress(),
+            B: B.compress(),
+            s: proof.s,
+        })
+    }
+
+    /// Verify a `BatchableDLEQProof`
+    pub fn verify<D, T>(
+        &self,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        BatchableDLEQProof::verify_batch::<D, T>(
+            rng,
+            &[(self, blinded_tokens, signed_tokens, public_key)],
+        )
+    }
+
+    /// Verify many proofs at once, each over its own `BlindedToken`s and `SignedToken`s under
+    /// a `PublicKey`.
+    ///
+    /// The equations \\(s_i X + c_i Y_i - A_i = 0\\) and \\(s_i M_i + c_i Z_i - B_i = 0\\)
+    /// are weighted by random scalars \\(z_i\\) and \\(w_i\\) and the sum checked with a
+    /// single multiscalar multiplication. This only reports whether every proof is valid, use
+    /// `BatchableDLEQProof::find_invalid` to identify the ones which are not.
+    pub fn verify_batch<D, T>(
+        rng: &mut T,
+        proofs: &[(
+            &BatchableDLEQProof,
+            &[BlindedToken],
+            &[SignedToken],
+            &PublicKey,
+        )],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let mut X_coefficient = Scalar::zero();
+        let mut scalars: Vec<Scalar> = Vec::with_capacity(5 * proofs.len());
+        let mut points: Vec<RistrettoPoint> = Vec::with_capacity(5 * proofs.len());
+
+        for (proof, blinded_tokens, signed_tokens, public_key) in proofs {
+            let mut transcript = DigestTranscript::<D>::new();
+            let (M, Z) = BatchDLEQProof::calculate_composites(
+                &mut transcript,
+                blinded_tokens,
+                signed_tokens,
+                public_key,
+            )?;
+            let decompress = |point: &CompressedRistretto| {
+                point
+                    .decompress()
+                    .ok_or(TokenError(InternalError::PointDecompressionError))
+            };
+            let Y = decompress(&public_key.0)?;
+            let A = decompress(&proof.A)?;
+            let B = decompress(&proof.B)?;
+
+            let c = DLEQProof::challenge(&mut transcript, public_key, &M, &Z, &A, &B);
+            let z = Scalar::random(rng);
+            let w = Scalar::random(rng);
+
+            X_coefficient += z * proof.s;
+            scalars.extend_from_slice(&[z * c, -z, w * proof.s, w * c, -w]);
+            points.extend_from_slice(&[Y, A, M, Z, B]);
+        }
+
+        let sum = RistrettoPoint::vartime_multiscalar_mul(
+            iter::once(X_coefficient).chain(scalars),
+            iter::once(constants::RISTRETTO_BASEPOINT_POINT).chain(points),
+        );
+
+        if sum.is_identity() {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Verify many proofs at once as with `BatchableDLEQProof::verify_batch`, returning the
+    /// indices of the proofs which are invalid.
+    ///
+    /// If the batch fails it is split in half and each half checked in turn, so that a few
+    /// invalid proofs among many are found with a handful of batch verifications. An empty
+    /// result means that every proof is valid.
+    pub fn find_invalid<D, T>(
+        rng: &mut T,
+        proofs: &[(
+            &BatchableDLEQProof,
+            &[BlindedToken],
+            &[SignedToken],
+            &PublicKey,
+        )],
+    ) -> Vec<usize>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let mut invalid = Vec::new();
+        BatchableDLEQProof::bisect::<D, T>(rng, proofs, 0, &mut invalid);
+        invalid
+    }
+
+    fn bisect<D, T>(
+        rng: &mut T,
+        proofs: &[(
+            &BatchableDLEQProof,
+            &[BlindedToken],
+            &[SignedToken],
+            &PublicKey,
+        )],
+        offset: usize,
+        invalid: &mut Vec<usize>,
+    ) where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        if proofs.is_empty() || BatchableDLEQProof::verify_batch::<D, T>(rng, proofs).is_ok() {
+            return;
+        }
+        if proofs.len() == 1 {
+            invalid.push(offset);
+            return;
+        }
+
+        let (left, right) = proofs.split_at(proofs.len() / 2);
+        BatchableDLEQProof::bisect::<D, T>(rng, left, offset, invalid);
+        BatchableDLEQProof::bisect::<D, T>(rng, right, offset + left.len(), invalid);
+    }
+
+    /// Convert this `BatchableDLEQProof` to the compact `BatchDLEQProof` over the same public
+    /// inputs.
+    ///
+    /// The challenge \\(c\\) is recomputed from the commitments. The result is valid exactly
+    /// when this proof is, and converts back to it with `BatchDLEQProof::to_batchable`.
+    #[allow(non_snake_case)]
+    pub fn to_compact<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<BatchDLEQProof, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut transcript = DigestTranscript::<D>::new();
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut transcript,
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )?;
+        let A = self
+            .A
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let B = self
+            .B
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+
+        let c = DLEQProof::challenge(&mut transcript, public_key, &M, &Z, &A, &B);
+        Ok(BatchDLEQProof(DLEQProof { c, s: self.s }))
+    }
+}
+
+impl BatchableDLEQProof {
+    /// Convert this `BatchableDLEQProof` to a byte array.
+    pub fn to_bytes(&self) -> [u8; BATCHABLE_DLEQ_PROOF_LENGTH] {
+        let mut proof_bytes: [u8; BATCHABLE_DLEQ_PROOF_LENGTH] = [0u8; BATCHABLE_DLEQ_PROOF_LENGTH];
+
+        proof_bytes[..32].copy_from_slice(self.A.as_bytes());
+        proof_bytes[32..64].copy_from_slice(self.B.as_bytes());
+        proof_bytes[64..].copy_from_slice(self.s.as_bytes());
+        proof_bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "BatchableDLEQProof",
+            length: BATCHABLE_DLEQ_PROOF_LENGTH,
+        })
+    }
+
+    /// Construct a `BatchableDLEQProof` from a slice of bytes.
+    #[allow(non_snake_case)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<BatchableDLEQProof, TokenError> {
+        if bytes.len() != BATCHABLE_DLEQ_PROOF_LENGTH {
+            return Err(BatchableDLEQProof::bytes_length_error());
+        }
+
+        let mut A_bits: [u8; 32] = [0u8; 32];
+        let mut B_bits: [u8; 32] = [0u8; 32];
+        let mut s_bits: [u8; 32] = [0u8; 32];
+
+        A_bits.copy_from_slice(&bytes[..32]);
+        B_bits.copy_from_slice(&bytes[32..64]);
+        s_bits.copy_from_slice(&bytes[64..]);
+
+        let s = Scalar::from_canonical_bytes(s_bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+
+        Ok(BatchableDLEQProof {
+            A: CompressedRistretto(A_bits),
+            B: CompressedRistretto(B_bits),
+            s,
+        })
+    }
+}
+
+#[allow(non_snake_case)]
+impl SigningKey {
+    /// Sign each of the provided `BlindedToken`s and construct a `BatchDLEQProof` over them.
+    ///
+    /// This is equivalent to calling `SigningKey::sign` for each token followed by
+    /// `BatchDLEQProof::new`, but decompresses each `BlindedToken` only once. Since
+    /// \\(Z = \\sum c_i Q_i = k \\sum c_i P_i = kM\\), the composite of the signed tokens is
+    /// computed with a single scalar multiplication.
+    pub fn issue<D, T>(
+        &self,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<(Vec<SignedToken>, BatchDLEQProof), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        self.issue_with_transcript(&mut DigestTranscript::<D>::new(), rng, blinded_tokens)
+    }
+
+    /// Sign each of the provided `BlindedToken`s and construct a `BatchDLEQProof` over them,
+    /// as with `BatchDLEQProof::new_with_context`.
+    pub fn issue_with_context<D, T>(
+        &self,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+        context: &[u8],
+    ) -> Result<(Vec<SignedToken>, BatchDLEQProof), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        self.issue_with_transcript(&mut context_transcript::<D>(context), rng, blinded_tokens)
+    }
+
+    /// Sign each of the provided `BlindedToken`s and construct a `BatchDLEQProof` over them,
+    /// as with `BatchDLEQProof::new_with_transcript`.
+    pub fn issue_with_transcript<F, T>(
+        &self,
+        transcript: &mut F,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<(Vec<SignedToken>, BatchDLEQProof), TokenError>
+    where
+        F: ProofTranscript,
+        T: Rng + CryptoRng,
+    {
+        let P: Vec<RistrettoPoint> = blinded_tokens
+            .iter()
+            .map(|Pi| {
+                Pi.0.decompress()
+                    .ok_or(TokenError(InternalError::PointDecompressionError))
+            })
+            .collect::<Result<_, _>>()?;
+        let signed_tokens: Vec<SignedToken> = P
+            .iter()
+            .map(|Pi| SignedToken((self.k * Pi).compress()))
+            .collect();
+
+        transcript.domain_separator(b"dleq");
+
+        let c_m = BatchDLEQProof::composite_scalars(
+            transcript,
+            blinded_tokens,
+            &signed_tokens,
+            &self.public_key,
+        )?;
+        let M = composite(&c_m, &P, |Pi| Some(*Pi))
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let Z = self.k * M;
+
+        let proof = BatchDLEQProof(DLEQProof::_new(transcript, rng, M, Z, self));
+        Ok((signed_tokens, proof))
+    }
+}
+
+/// The proof system used to construct a batch DLEQ proof.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProofFormat {
+    /// A `BatchDLEQProof`, whose challenge is computed with a `Digest`
+    Digest = 0,
+    /// A `MerlinBatchDLEQProof`, whose challenge is computed from a merlin `Transcript`
+    Merlin = 1,
+}
+
+/// A batch DLEQ proof tagged with its `ProofFormat`, so that a verifier can accept proofs
+/// from either proof system.
+///
+/// Proofs in the `Merlin` format can only be decoded and verified with the `merlin`
+/// feature. Since the `Merlin` variant only exists with that feature, the enum is
+/// non-exhaustive and matches on it need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TaggedBatchDLEQProof {
+    /// A `BatchDLEQProof`
+    Digest(BatchDLEQProof),
+    /// A `MerlinBatchDLEQProof`
+    #[cfg(feature = "merlin")]
+    Merlin(MerlinBatchDLEQProof),
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(TaggedBatchDLEQProof);
+
+#[cfg(feature = "serde")]
+impl_serde!(TaggedBatchDLEQProof);
+
+impl From<BatchDLEQProof> for TaggedBatchDLEQProof {
+    fn from(proof: BatchDLEQProof) -> Self {
+        TaggedBatchDLEQProof::Digest(proof)
+    }
+}
+
+#[cfg(feature = "merlin")]
+impl From<MerlinBatchDLEQProof> for TaggedBatchDLEQProof {
+    fn from(proof: MerlinBatchDLEQProof) -> Self {
+        TaggedBatchDLEQProof::Merlin(proof)
+    }
+}
+
+impl TaggedBatchDLEQProof {
+    /// The `ProofFormat` of this proof.
+    pub fn format(&self) -> ProofFormat {
+        match self {
+            TaggedBatchDLEQProof::Digest(_) => ProofFormat::Digest,
+            #[cfg(feature = "merlin")]
+            TaggedBatchDLEQProof::Merlin(_) => ProofFormat::Merlin,
+        }
+    }
+
+    /// Verify the proof with the proof system given by its `ProofFormat`.
+    ///
+    /// `D` is the hash function of `Digest` proofs, and `label` the label of the
+    /// `Transcript` for `Merlin` proofs.
+    #[cfg_attr(not(feature = "merlin"), allow(unused_variables))]
+    pub fn verify<D>(
+        &self,
+        label: &'static [u8],
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        match self {
+            TaggedBatchDLEQProof::Digest(proof) => {
+                proof.verify::<D>(blinded_tokens, signed_tokens, public_key)
+            }
+            #[cfg(feature = "merlin")]
+            TaggedBatchDLEQProof::Merlin(proof) => proof.verify(
+                &mut Transcript::new(label),
+                blinded_tokens,
+                signed_tokens,
+                public_key,
+            ),
+        }
+    }
+
+    /// Verify the proof as with `verify`, then unblind the `SignedToken`s using each
+    /// corresponding `Token`
+    pub fn verify_and_unblind<'a, D, I>(
+        &self,
+        label: &'static [u8],
+        tokens: I,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<Vec<UnblindedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        I: IntoIterator<Item = &'a Token>,
+    {
+        self.verify::<D>(label, blinded_tokens, signed_tokens, public_key)?;
+
+        unblind_all(tokens, signed_tokens)
+    }
+
+    /// Convert this `TaggedBatchDLEQProof` to a byte array.
+    pub fn to_bytes(&self) -> [u8; TAGGED_BATCH_DLEQ_PROOF_LENGTH] {
+        let mut bytes: [u8; TAGGED_BATCH_DLEQ_PROOF_LENGTH] = [0u8; TAGGED_BATCH_DLEQ_PROOF_LENGTH];
+        bytes[0] = self.format() as u8;
+        bytes[1..].copy_from_slice(&match self {
+            TaggedBatchDLEQProof::Digest(proof) => proof.to_bytes(),
+            #[cfg(feature = "merlin")]
+            TaggedBatchDLEQProof::Merlin(proof) => proof.to_bytes(),
+        });
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "TaggedBatchDLEQProof",
+            length: TAGGED_BATCH_DLEQ_PROOF_LENGTH,
+        })
+    }
+
+    /// Construct a `TaggedBatchDLEQProof` from a slice of bytes.
+    ///
+    /// Returns a `TokenError` if the tag is unknown, or is `Merlin` without the `merlin`
+    /// feature.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TaggedBatchDLEQProof, TokenError> {
+        if bytes.len() != TAGGED_BATCH_DLEQ_PROOF_LENGTH {
+            return Err(TaggedBatchDLEQProof::bytes_length_error());
+        }
+
+        match bytes[0] {
+            tag if tag == ProofFormat::Digest as u8 => {
+                BatchDLEQProof::from_bytes(&bytes[1..]).map(TaggedBatchDLEQProof::Digest)
+            }
+            #[cfg(feature = "merlin")]
+            tag if tag == ProofFormat::Merlin as u8 => {
+                MerlinBatchDLEQProof::from_bytes(&bytes[1..]).map(TaggedBatchDLEQProof::Merlin)
+            }
+            _ => Err(TokenError(InternalError::DecodingError)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use curve25519_dalek::ristretto::CompressedRistretto;
+    use rand::rngs::OsRng;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::Token;
+
+    #[test]
+    #[allow(non_snake_case)]
     fn works() {
         let mut rng = OsRng;
 
@@ -370,16 +1303,42 @@ mod tests {
         let P = RistrettoPoint::random(&mut rng);
         let Q = key1.k * P;
 
-        let proof = DLEQProof::_new::<Sha512, _>(&mut rng, P, Q, &key1);
-
-        assert!(proof._verify::<Sha512>(P, Q, &key1.public_key).is_ok());
+        let proof = DLEQProof::_new(
+            &mut DigestTranscript::<Sha512>::new(),
+            &mut rng,
+            P,
+            Q,
+            &key1,
+        );
+
+        assert!(proof
+            ._verify(
+                &mut DigestTranscript::<Sha512>::new(),
+                P,
+                Q,
+                &key1.public_key
+            )
+            .is_ok());
 
         let P = RistrettoPoint::random(&mut rng);
         let Q = key2.k * P;
 
-        let proof = DLEQProof::_new::<Sha512, _>(&mut rng, P, Q, &key1);
-
-        assert!(!proof._verify::<Sha512>(P, Q, &key1.public_key).is_ok());
+        let proof = DLEQProof::_new(
+            &mut DigestTranscript::<Sha512>::new(),
+            &mut rng,
+            P,
+            Q,
+            &key1,
+        );
+
+        assert!(!proof
+            ._verify(
+                &mut DigestTranscript::<Sha512>::new(),
+                P,
+                Q,
+                &key1.public_key
+            )
+            .is_ok());
     }
 
     #[allow(non_snake_case)]
@@ -412,10 +1371,23 @@ mod tests {
             let seed: [u8; 32] = [0u8; 32];
             let mut prng: ChaChaRng = SeedableRng::from_seed(seed);
 
-            let dleq = DLEQProof::_new::<Sha512, _>(&mut prng, P, Q, &server_key);
+            let dleq = DLEQProof::_new(
+                &mut DigestTranscript::<Sha512>::new(),
+                &mut prng,
+                P,
+                Q,
+                &server_key,
+            );
             assert_eq!(dleq.encode_base64(), dleq_b64);
 
-            assert!(dleq._verify::<Sha512>(P, Q, &server_key.public_key).is_ok());
+            assert!(dleq
+                ._verify(
+                    &mut DigestTranscript::<Sha512>::new(),
+                    P,
+                    Q,
+                    &server_key.public_key
+                )
+                .is_ok());
         }
     }
 
@@ -455,9 +1427,13 @@ mod tests {
                 })
                 .collect();
 
-            let (M, Z) =
-                BatchDLEQProof::calculate_composites::<Sha512>(&P, &Q, &server_key.public_key)
-                    .unwrap();
+            let (M, Z) = BatchDLEQProof::calculate_composites(
+                &mut DigestTranscript::<Sha512>::new(),
+                &P,
+                &Q,
+                &server_key.public_key,
+            )
+            .unwrap();
 
             assert_eq!(base64::encode(&M.compress().to_bytes()[..]), M_b64);
             assert_eq!(base64::encode(&Z.compress().to_bytes()[..]), Z_b64);
@@ -498,4 +1474,461 @@ mod tests {
             .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
             .is_ok());
     }
+
+    #[test]
+    fn verify_and_unblind_committed_works() {
+        use crate::commitment::KeyCommitment;
+        use ed25519_dalek::Keypair;
+        use sha2::Sha256;
+        use std::vec::Vec;
+
+        let mut rng = OsRng;
+
+        let issuer = Keypair::generate(&mut rng);
+        let key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+
+        let mut commitment = KeyCommitment::new(1, 1000);
+        commitment.add_key::<Sha256>(&key.public_key, 1000, 2000);
+        let commitment = commitment
+            .sign(&issuer)
+            .unwrap()
+            .verify(&issuer.public)
+            .unwrap();
+
+        for (signing_key, time, ok) in [
+            (&key, 1500, true),
+            (&key, 2500, false),
+            (&other_key, 1500, false),
+        ] {
+            let tokens: Vec<Token> = (0..3)
+                .map(|_| Token::random::<Sha512, _>(&mut rng))
+                .collect();
+            let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+            let (signed_tokens, proof) = signing_key
+                .issue::<Sha512, _>(&mut rng, &blinded_tokens)
+                .unwrap();
+
+            let result = proof.verify_and_unblind_committed::<Sha512, _>(
+                &tokens,
+                &blinded_tokens,
+                &signed_tokens,
+                &signing_key.public_key,
+                &commitment,
+                time,
+            );
+            if ok {
+                assert_eq!(result.unwrap().len(), 3);
+            } else {
+                assert_eq!(
+                    result.unwrap_err(),
+                    TokenError(InternalError::UnknownKeyError)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn works_with_info() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+
+        let blinded_token = Token::random::<Sha512, _>(&mut rng).blind();
+        let signed_token = key
+            .sign_with_info::<Sha512>(&blinded_token, b"epoch=1")
+            .unwrap();
+
+        let proof = DLEQProof::new_with_info::<Sha512, _>(
+            &mut rng,
+            &blinded_token,
+            &signed_token,
+            &key,
+            b"epoch=1",
+        )
+        .unwrap();
+
+        assert!(proof
+            .verify_with_info::<Sha512>(&blinded_token, &signed_token, &key.public_key, b"epoch=1")
+            .is_ok());
+        assert!(proof
+            .verify_with_info::<Sha512>(&blinded_token, &signed_token, &key.public_key, b"epoch=2")
+            .is_err());
+    }
+
+    #[test]
+    fn works_with_context() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+
+        let blinded_token = Token::random::<Sha512, _>(&mut rng).blind();
+        let signed_token = key.sign(&blinded_token).unwrap();
+
+        let proof = DLEQProof::new_with_context::<Sha512, _>(
+            &mut rng,
+            &blinded_token,
+            &signed_token,
+            &key,
+            b"request=1",
+        )
+        .unwrap();
+
+        assert!(proof
+            .verify_with_context::<Sha512>(
+                &blinded_token,
+                &signed_token,
+                &key.public_key,
+                b"request=1"
+            )
+            .is_ok());
+        assert!(proof
+            .verify_with_context::<Sha512>(
+                &blinded_token,
+                &signed_token,
+                &key.public_key,
+                b"request=2"
+            )
+            .is_err());
+        assert!(proof
+            .verify::<Sha512>(&blinded_token, &signed_token, &key.public_key)
+            .is_err());
+    }
+
+    #[test]
+    fn batchable_proofs_verify_together() {
+        use std::vec::Vec;
+
+        let mut rng = OsRng;
+
+        let keys: Vec<SigningKey> = (0..2).map(|_| SigningKey::random(&mut rng)).collect();
+        let issuances: Vec<(Vec<BlindedToken>, Vec<SignedToken>, &SigningKey)> = (0..7)
+            .map(|i| {
+                let key = &keys[i % keys.len()];
+                let blinded_tokens: Vec<BlindedToken> = (0..i + 1)
+                    .map(|_| Token::random::<Sha512, _>(&mut rng).blind())
+                    .collect();
+                let signed_tokens: Vec<SignedToken> = blinded_tokens
+                    .iter()
+                    .map(|t| key.sign(t).unwrap())
+                    .collect();
+                (blinded_tokens, signed_tokens, key)
+            })
+            .collect();
+        let proofs: Vec<BatchableDLEQProof> = issuances
+            .iter()
+            .map(|(blinded_tokens, signed_tokens, key)| {
+                BatchableDLEQProof::new::<Sha512, _>(&mut rng, blinded_tokens, signed_tokens, key)
+                    .unwrap()
+            })
+            .collect();
+
+        let mut items: Vec<(
+            &BatchableDLEQProof,
+            &[BlindedToken],
+            &[SignedToken],
+            &PublicKey,
+        )> = proofs
+            .iter()
+            .zip(issuances.iter())
+            .map(|(proof, (blinded_tokens, signed_tokens, key))| {
+                (
+                    proof,
+                    &blinded_tokens[..],
+                    &signed_tokens[..],
+                    &key.public_key,
+                )
+            })
+            .collect();
+
+        assert!(BatchableDLEQProof::verify_batch::<Sha512, _>(&mut rng, &items).is_ok());
+        assert!(BatchableDLEQProof::verify_batch::<Sha512, _>(&mut rng, &[]).is_ok());
+        assert!(BatchableDLEQProof::find_invalid::<Sha512, _>(&mut rng, &items).is_empty());
+        for (proof, blinded_tokens, signed_tokens, public_key) in items.iter() {
+            assert!(proof
+                .verify::<Sha512, _>(&mut rng, blinded_tokens, signed_tokens, public_key)
+                .is_ok());
+        }
+
+        // proofs checked against the wrong key or tokens are found by bisection
+        items[2].3 = &keys[1].public_key;
+        items[5].2 = &issuances[4].1[..5];
+        items[5].1 = &issuances[4].0[..5];
+        assert_eq!(
+            BatchableDLEQProof::verify_batch::<Sha512, _>(&mut rng, &items).unwrap_err(),
+            TokenError(InternalError::VerifyError)
+        );
+        assert_eq!(
+            BatchableDLEQProof::find_invalid::<Sha512, _>(&mut rng, &items),
+            vec![2, 5]
+        );
+    }
+
+    #[test]
+    fn batchable_proofs_convert_losslessly() {
+        use std::vec::Vec;
+
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..5)
+            .map(|_| Token::random::<Sha512, _>(&mut rng).blind())
+            .collect();
+        let (signed_tokens, proof) = key.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap();
+
+        let batchable = proof
+            .to_batchable::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .unwrap();
+        assert!(batchable
+            .verify::<Sha512, _>(&mut rng, &blinded_tokens, &signed_tokens, &key.public_key)
+            .is_ok());
+        let compact = batchable
+            .to_compact::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .unwrap();
+        assert_eq!(compact.to_bytes(), proof.to_bytes());
+
+        let batchable =
+            BatchableDLEQProof::new::<Sha512, _>(&mut rng, &blinded_tokens, &signed_tokens, &key)
+                .unwrap();
+        let compact = batchable
+            .to_compact::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .unwrap();
+        assert!(compact
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .is_ok());
+        assert_eq!(
+            compact
+                .to_batchable::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+                .unwrap()
+                .to_bytes()
+                .to_vec(),
+            batchable.to_bytes().to_vec()
+        );
+
+        // an invalid proof stays invalid in either form
+        let batchable = proof
+            .to_batchable::<Sha512>(&blinded_tokens, &signed_tokens, &other_key.public_key)
+            .unwrap();
+        assert!(batchable
+            .verify::<Sha512, _>(
+                &mut rng,
+                &blinded_tokens,
+                &signed_tokens,
+                &other_key.public_key
+            )
+            .is_err());
+
+        let encoded = batchable.encode_base64();
+        let decoded = BatchableDLEQProof::decode_base64(&encoded).unwrap();
+        assert_eq!(decoded.to_bytes().to_vec(), batchable.to_bytes().to_vec());
+        assert!(BatchableDLEQProof::from_bytes(&batchable.to_bytes()[..64]).is_err());
+    }
+
+    #[test]
+    fn batch_works_with_context() {
+        use std::vec::Vec;
+
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let context = b"issuer=1;request=2";
+
+        let tokens: Vec<Token> = (0..3)
+            .map(|_| Token::random::<Sha512, _>(&mut rng))
+            .collect();
+        let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+
+        let (signed_tokens, batch_proof) = key
+            .issue_with_context::<Sha512, _>(&mut rng, &blinded_tokens, context)
+            .unwrap();
+        let expected_proof = BatchDLEQProof::new_with_context::<Sha512, _>(
+            &mut rng,
+            &blinded_tokens,
+            &signed_tokens,
+            &key,
+            context,
+        )
+        .unwrap();
+
+        for proof in [batch_proof, expected_proof].iter() {
+            let unblinded_tokens = proof
+                .verify_and_unblind_with_context::<Sha512, _>(
+                    &tokens,
+                    &blinded_tokens,
+                    &signed_tokens,
+                    &key.public_key,
+                    context,
+                )
+                .unwrap();
+            assert_eq!(unblinded_tokens.len(), tokens.len());
+
+            // the proof cannot be replayed into another request, or without any context
+            assert!(proof
+                .verify_with_context::<Sha512>(
+                    &blinded_tokens,
+                    &signed_tokens,
+                    &key.public_key,
+                    b"issuer=1;request=3"
+                )
+                .is_err());
+            assert!(proof
+                .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn batch_works_with_info() {
+        use std::vec::Vec;
+
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let info = b"campaign=abc";
+
+        let tokens: Vec<Token> = (0..3)
+            .map(|_| Token::random::<Sha512, _>(&mut rng))
+            .collect();
+        let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+        let signed_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .filter_map(|t| key.sign_with_info::<Sha512>(t, info).ok())
+            .collect();
+
+        let batch_proof = BatchDLEQProof::new_with_info::<Sha512, _>(
+            &mut rng,
+            &blinded_tokens,
+            &signed_tokens,
+            &key,
+            info,
+        )
+        .unwrap();
+
+        let unblinded_tokens = batch_proof
+            .verify_and_unblind_with_info::<Sha512, _>(
+                &tokens,
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key,
+                info,
+            )
+            .unwrap();
+        for unblinded_token in unblinded_tokens.iter() {
+            assert_eq!(
+                unblinded_token.to_bytes().to_vec(),
+                key.rederive_unblinded_token_with_info::<Sha512>(&unblinded_token.t, info)
+                    .unwrap()
+                    .to_bytes()
+                    .to_vec()
+            );
+        }
+
+        // a proof for one bucket does not verify for another, or against the untweaked key
+        assert!(batch_proof
+            .verify_with_info::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key, b"other")
+            .is_err());
+        assert!(batch_proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .is_err());
+    }
+
+    #[test]
+    fn issue_matches_two_step() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..10)
+            .map(|_| Token::random::<Sha512, _>(&mut rng).blind())
+            .collect();
+
+        let mut seed = [0u8; 32];
+        rng.fill(&mut seed);
+
+        let (signed_tokens, batch_proof) = key
+            .issue::<Sha512, _>(&mut ChaChaRng::from_seed(seed), &blinded_tokens)
+            .unwrap();
+
+        let expected_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .map(|t| key.sign(t).unwrap())
+            .collect();
+        let expected_proof = BatchDLEQProof::new::<Sha512, _>(
+            &mut ChaChaRng::from_seed(seed),
+            &blinded_tokens,
+            &expected_tokens,
+            &key,
+        )
+        .unwrap();
+
+        for (signed_token, expected) in signed_tokens.iter().zip(expected_tokens.iter()) {
+            assert_eq!(signed_token.to_bytes(), expected.to_bytes());
+        }
+        assert_eq!(batch_proof.to_bytes(), expected_proof.to_bytes());
+        assert!(batch_proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .is_ok());
+
+        let mut invalid = blinded_tokens;
+        invalid[3] = BlindedToken::from_bytes(&[0xffu8; 32]).unwrap();
+        assert!(key.issue::<Sha512, _>(&mut rng, &invalid).is_err());
+    }
+
+    #[test]
+    fn tagged_proofs_work() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let tokens: Vec<Token> = (0..3)
+            .map(|_| Token::random::<Sha512, _>(&mut rng))
+            .collect();
+        let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+
+        let (signed_tokens, proof) = key.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap();
+        let proof = TaggedBatchDLEQProof::from(proof);
+        assert_eq!(proof.to_bytes()[0], 0);
+
+        let proof = TaggedBatchDLEQProof::decode_base64(&proof.encode_base64()).unwrap();
+        assert_eq!(proof.format(), ProofFormat::Digest);
+        let unblinded_tokens = proof
+            .verify_and_unblind::<Sha512, _>(
+                b"tagged",
+                &tokens,
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key,
+            )
+            .unwrap();
+        assert_eq!(unblinded_tokens.len(), 3);
+
+        let mut bytes = proof.to_bytes();
+        bytes[0] = 2;
+        assert!(TaggedBatchDLEQProof::from_bytes(&bytes).is_err());
+        bytes[0] = ProofFormat::Merlin as u8;
+        #[cfg(not(feature = "merlin"))]
+        assert!(TaggedBatchDLEQProof::from_bytes(&bytes).is_err());
+
+        #[cfg(feature = "merlin")]
+        {
+            // A digest proof read as a merlin proof does not verify.
+            let proof = TaggedBatchDLEQProof::from_bytes(&bytes).unwrap();
+            assert_eq!(proof.format(), ProofFormat::Merlin);
+            assert!(proof
+                .verify::<Sha512>(b"tagged", &blinded_tokens, &signed_tokens, &key.public_key)
+                .is_err());
+
+            let (signed_tokens, proof) = key
+                .issue_merlin(&mut Transcript::new(b"tagged"), &blinded_tokens)
+                .unwrap();
+            let proof =
+                TaggedBatchDLEQProof::from_bytes(&TaggedBatchDLEQProof::from(proof).to_bytes())
+                    .unwrap();
+            assert!(proof
+                .verify::<Sha512>(b"tagged", &blinded_tokens, &signed_tokens, &key.public_key)
+                .is_ok());
+            assert!(proof
+                .verify::<Sha512>(b"other", &blinded_tokens, &signed_tokens, &key.public_key)
+                .is_err());
+        }
+    }
 }
@@ -4,51 +4,17 @@ use alloc::vec::Vec;
 #[cfg(all(feature = "std"))]
 use std::vec::Vec;
 
-use core::iter;
-
-use curve25519_dalek::ristretto::CompressedRistretto;
 use curve25519_dalek::ristretto::RistrettoPoint;
-use curve25519_dalek::scalar::Scalar;
-use curve25519_dalek::traits::VartimeMultiscalarMul;
+use digest::generic_array::typenum::U64;
+use digest::Digest;
 use merlin::Transcript;
-use rand;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 
+use crate::dleq::{BatchDLEQProof, DLEQProof, DLEQ_PROOF_LENGTH};
 use crate::errors::{InternalError, TokenError};
+use crate::transcript::ProofTranscript;
 use crate::voprf::{BlindedToken, PublicKey, SignedToken, SigningKey};
-use curve25519_dalek::constants;
-
-use rand_chacha::ChaChaRng;
-use rand_core::SeedableRng;
-
-/// The length of a `DLEQProof`, in bytes.
-pub const DLEQ_PROOF_LENGTH: usize = 64;
-
-trait TranscriptProtocol {
-    fn dleq_domain_sep(&mut self);
-    fn batch_dleq_domain_sep(&mut self);
-    fn commit_point(&mut self, label: &'static [u8], point: &CompressedRistretto);
-    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar;
-}
-
-impl TranscriptProtocol for Transcript {
-    fn dleq_domain_sep(&mut self) {
-        self.append_message(b"dom-sep", b"dleq");
-    }
-
-    fn batch_dleq_domain_sep(&mut self) {
-        self.append_message(b"dom-sep", b"batch-dleq");
-    }
-
-    fn commit_point(&mut self, label: &'static [u8], point: &CompressedRistretto) {
-        self.append_message(label, point.as_bytes());
-    }
-
-    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
-        let mut buf = [0; 64];
-        self.challenge_bytes(label, &mut buf);
-        Scalar::from_bytes_mod_order_wide(&buf)
-    }
-}
 
 #[cfg(test)]
 mod tests {
@@ -70,7 +36,7 @@ mod tests {
         let Q = key1.k * P;
 
         let mut verifier = Transcript::new(b"dleqtest");
-        let proof = DLEQProof::_new(&mut verifier, P, Q, &key1).unwrap();
+        let proof = MerlinDLEQProof::_new(&mut verifier, P, Q, &key1);
 
         let mut verifier = Transcript::new(b"dleqtest");
         assert!(proof._verify(&mut verifier, P, Q, &key1.public_key).is_ok());
@@ -79,7 +45,7 @@ mod tests {
         let Q = key2.k * P;
 
         let mut transcript = Transcript::new(b"dleqtest");
-        let proof = DLEQProof::_new(&mut transcript, P, Q, &key1).unwrap();
+        let proof = MerlinDLEQProof::_new(&mut transcript, P, Q, &key1);
 
         let mut transcript = Transcript::new(b"dleqtest");
         assert!(!proof
@@ -104,7 +70,8 @@ mod tests {
 
         let mut transcript = Transcript::new(b"batchdleqtest");
         let batch_proof =
-            BatchDLEQProof::new(&mut transcript, &blinded_tokens, &signed_tokens, &key).unwrap();
+            MerlinBatchDLEQProof::new(&mut transcript, &blinded_tokens, &signed_tokens, &key)
+                .unwrap();
 
         let mut transcript = Transcript::new(b"batchdleqtest");
         assert!(batch_proof
@@ -116,67 +83,172 @@ mod tests {
             )
             .is_ok());
     }
-}
 
-/// A `DLEQProof` is a proof of the equivalence of the discrete logarithm between two pairs of points.
-#[allow(non_snake_case)]
-pub struct DLEQProof {
-    /// `c` is a `Scalar`
-    /// \\(c=H_3(X,Y,P,Q,A,B)\\)
-    pub(crate) c: Scalar,
-    /// `s` is a `Scalar`
-    /// \\(s = (t - ck) \mod q\\)
-    pub(crate) s: Scalar,
-}
+    #[test]
+    fn batch_dleq_proof_works_with_info() {
+        use std::vec::Vec;
 
-#[cfg(feature = "base64")]
-impl_base64!(DLEQProof);
+        let mut rng = OsRng;
 
-#[cfg(feature = "serde")]
-impl_serde!(DLEQProof);
+        let key = SigningKey::random(&mut rng);
 
-#[allow(non_snake_case)]
-impl DLEQProof {
-    /// Construct a new `DLEQProof`
-    fn _new(
-        transcript: &mut Transcript,
-        P: RistrettoPoint,
-        Q: RistrettoPoint,
-        secret_key: &SigningKey,
-    ) -> Result<Self, TokenError> {
-        let X = constants::RISTRETTO_BASEPOINT_COMPRESSED;
-        let Y = secret_key.public_key.0;
+        let blinded_tokens = vec![Token::random::<Sha512, OsRng>(&mut rng).blind()];
+        let signed_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .filter_map(|t| key.sign_with_info::<Sha512>(t, b"epoch=1").ok())
+            .collect();
+
+        let mut transcript = Transcript::new(b"batchdleqtest");
+        let batch_proof = MerlinBatchDLEQProof::new_with_info::<Sha512>(
+            &mut transcript,
+            &blinded_tokens,
+            &signed_tokens,
+            &key,
+            b"epoch=1",
+        )
+        .unwrap();
+
+        let mut transcript = Transcript::new(b"batchdleqtest");
+        assert!(batch_proof
+            .verify_with_info::<Sha512>(
+                &mut transcript,
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key,
+                b"epoch=1"
+            )
+            .is_ok());
+
+        let mut transcript = Transcript::new(b"batchdleqtest");
+        assert!(batch_proof
+            .verify_with_info::<Sha512>(
+                &mut transcript,
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key,
+                b"epoch=2"
+            )
+            .is_err());
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
 
-        transcript.dleq_domain_sep();
+    #[test]
+    fn batch_dleq_proof_matches_known_answer() {
+        // Produced by `BatchDLEQProof::new` of the merlin feature in version 1.0.1, before
+        // the proofs were built on `ProofTranscript`.
+        let key = SigningKey::from_bytes(&hex(
+            "bc44e5f70efca1749c4f164639b102dd202b20e1c286109af686266ed1361009",
+        ))
+        .unwrap();
+        let blinded_tokens: Vec<BlindedToken> = [
+            "26161820bae2b897a0bc98c1839054fdc67d531918565065b6fe4390f894195a",
+            "02ffe9be1ce77d8f5362490df0705e58376ff73f1826f0f452fb558c1a9b7047",
+            "c48952ab100625d989428bb183ebd010a5b6cde1509d0d1e9d722a363740424d",
+        ]
+        .iter()
+        .map(|t| BlindedToken::from_bytes(&hex(t)).unwrap())
+        .collect();
+        let expected = hex(
+            "96d0c3a47c66f97742471e94cb5123d2aa5046cc08aba4e1277ce0df78cff106\
+             e886ba58f0df9f6f5570048791bd2e967dccc0f6ce070ae58ac2b8f0551bff05",
+        );
+
+        let (signed_tokens, proof) = key
+            .issue_merlin(&mut Transcript::new(b"kat"), &blinded_tokens)
+            .unwrap();
+        assert_eq!(&proof.to_bytes()[..], &expected[..]);
+
+        let proof = MerlinBatchDLEQProof::from_bytes(&expected).unwrap();
+        assert!(proof
+            .verify(
+                &mut Transcript::new(b"kat"),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_ok());
+    }
 
-        transcript.commit_point(b"X", &X);
-        transcript.commit_point(b"Y", &Y);
-        transcript.commit_point(b"P", &P.compress());
-        transcript.commit_point(b"Q", &Q.compress());
+    #[test]
+    fn issue_matches_two_step() {
+        use std::vec::Vec;
 
-        let mut rng = transcript
-            .build_rng()
-            .rekey_with_witness_bytes(b"k", secret_key.k.as_bytes())
-            .finalize(&mut ChaChaRng::from_seed([0; 32]));
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..10)
+            .map(|_| Token::random::<Sha512, OsRng>(&mut rng).blind())
+            .collect();
+
+        let mut transcript = Transcript::new(b"issuetest");
+        let (signed_tokens, batch_proof) =
+            key.issue_merlin(&mut transcript, &blinded_tokens).unwrap();
 
-        let t = Scalar::random(&mut rng);
+        let expected_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .map(|t| key.sign(t).unwrap())
+            .collect();
+        let mut transcript = Transcript::new(b"issuetest");
+        let expected_proof =
+            MerlinBatchDLEQProof::new(&mut transcript, &blinded_tokens, &expected_tokens, &key)
+                .unwrap();
+
+        for (signed_token, expected) in signed_tokens.iter().zip(expected_tokens.iter()) {
+            assert_eq!(signed_token.to_bytes(), expected.to_bytes());
+        }
+        assert_eq!(batch_proof.to_bytes(), expected_proof.to_bytes());
 
-        let A = t * X
-            .decompress()
-            .ok_or(TokenError(InternalError::PointDecompressionError))?;
-        let B = t * P;
+        let mut transcript = Transcript::new(b"issuetest");
+        assert!(batch_proof
+            .verify(
+                &mut transcript,
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_ok());
+    }
+}
 
-        transcript.commit_point(b"A", &A.compress());
-        transcript.commit_point(b"B", &B.compress());
+/// A `MerlinDLEQProof` is a proof of the equivalence of the discrete logarithm between two pairs
+/// of points, with the challenge computed from a merlin `Transcript`.
+#[derive(Debug)]
+pub struct MerlinDLEQProof(DLEQProof);
 
-        let c = transcript.challenge_scalar(b"c");
+#[cfg(feature = "base64")]
+impl_base64!(MerlinDLEQProof);
 
-        let s = t - c * secret_key.k;
+#[cfg(feature = "serde")]
+impl_serde!(MerlinDLEQProof);
 
-        Ok(DLEQProof { c, s })
+#[allow(non_snake_case)]
+impl MerlinDLEQProof {
+    /// Construct a new `MerlinDLEQProof`
+    ///
+    /// The nonce is derived from the transcript and the secret key by
+    /// `ProofTranscript::witness_nonce`, with a fixed seed in place of external randomness.
+    fn _new(
+        transcript: &mut Transcript,
+        P: RistrettoPoint,
+        Q: RistrettoPoint,
+        secret_key: &SigningKey,
+    ) -> Self {
+        MerlinDLEQProof(DLEQProof::_new(
+            transcript,
+            &mut ChaChaRng::from_seed([0; 32]),
+            P,
+            Q,
+            secret_key,
+        ))
     }
 
-    /// Verify the `DLEQProof`
+    /// Verify the `MerlinDLEQProof`
     fn _verify(
         &self,
         transcript: &mut Transcript,
@@ -184,185 +256,180 @@ impl DLEQProof {
         Q: RistrettoPoint,
         public_key: &PublicKey,
     ) -> Result<(), TokenError> {
-        let X = constants::RISTRETTO_BASEPOINT_COMPRESSED;
-        let Y = public_key.0;
-
-        transcript.dleq_domain_sep();
-
-        let A = (&self.s * &constants::RISTRETTO_BASEPOINT_TABLE)
-            + (self.c
-                * Y.decompress()
-                    .ok_or(TokenError(InternalError::PointDecompressionError))?);
-        let B = (self.s * P) + (self.c * Q);
-
-        let P = P.compress();
-        let Q = Q.compress();
-        let A = A.compress();
-        let B = B.compress();
-
-        transcript.commit_point(b"X", &X);
-        transcript.commit_point(b"Y", &Y);
-        transcript.commit_point(b"P", &P);
-        transcript.commit_point(b"Q", &Q);
-        transcript.commit_point(b"A", &A);
-        transcript.commit_point(b"B", &B);
-
-        let c = transcript.challenge_scalar(b"c");
-
-        if c == self.c {
-            Ok(())
-        } else {
-            Err(TokenError(InternalError::VerifyError))
-        }
+        self.0._verify(transcript, P, Q, public_key)
     }
 }
 
-impl DLEQProof {
-    /// Convert this `DLEQProof` to a byte array.
+impl MerlinDLEQProof {
+    /// Convert this `MerlinDLEQProof` to a byte array.
     pub fn to_bytes(&self) -> [u8; DLEQ_PROOF_LENGTH] {
-        let mut proof_bytes: [u8; DLEQ_PROOF_LENGTH] = [0u8; DLEQ_PROOF_LENGTH];
-
-        proof_bytes[..32].copy_from_slice(&self.c.to_bytes());
-        proof_bytes[32..].copy_from_slice(&self.s.to_bytes());
-        proof_bytes
+        self.0.to_bytes()
     }
 
     fn bytes_length_error() -> TokenError {
         TokenError(InternalError::BytesLengthError {
-            name: "DLEQProof",
+            name: "MerlinDLEQProof",
             length: DLEQ_PROOF_LENGTH,
         })
     }
 
-    /// Construct a `DLEQProof` from a slice of bytes.
-    pub fn from_bytes(bytes: &[u8]) -> Result<DLEQProof, TokenError> {
+    /// Construct a `MerlinDLEQProof` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerlinDLEQProof, TokenError> {
         if bytes.len() != DLEQ_PROOF_LENGTH {
-            return Err(DLEQProof::bytes_length_error());
+            return Err(MerlinDLEQProof::bytes_length_error());
         }
 
-        let mut c_bits: [u8; 32] = [0u8; 32];
-        let mut s_bits: [u8; 32] = [0u8; 32];
-
-        c_bits.copy_from_slice(&bytes[..32]);
-        s_bits.copy_from_slice(&bytes[32..]);
-
-        let c = Scalar::from_canonical_bytes(c_bits)
-            .ok_or(TokenError(InternalError::ScalarFormatError))?;
-        let s = Scalar::from_canonical_bytes(s_bits)
-            .ok_or(TokenError(InternalError::ScalarFormatError))?;
-
-        Ok(DLEQProof { c, s })
+        DLEQProof::from_bytes(bytes).map(MerlinDLEQProof)
     }
 }
 
-/// A `BatchDLEQProof` is a proof of the equivalence of the discrete logarithm between a common
-/// pair of points and one or more other pairs of points.
-#[allow(non_snake_case)]
-pub struct BatchDLEQProof(DLEQProof);
+/// A `MerlinBatchDLEQProof` is a proof of the equivalence of the discrete logarithm between a
+/// common pair of points and one or more other pairs of points, with the challenge computed from
+/// a merlin `Transcript`.
+///
+/// It is a `BatchDLEQProof` constructed with `BatchDLEQProof::new_with_transcript` over a merlin
+/// `Transcript`.
+#[derive(Debug)]
+pub struct MerlinBatchDLEQProof(MerlinDLEQProof);
 
 #[cfg(feature = "base64")]
-impl_base64!(BatchDLEQProof);
+impl_base64!(MerlinBatchDLEQProof);
 
 #[cfg(feature = "serde")]
-impl_serde!(BatchDLEQProof);
+impl_serde!(MerlinBatchDLEQProof);
+
+impl From<BatchDLEQProof> for MerlinBatchDLEQProof {
+    fn from(proof: BatchDLEQProof) -> Self {
+        MerlinBatchDLEQProof(MerlinDLEQProof(proof.0))
+    }
+}
 
 #[allow(non_snake_case)]
-impl BatchDLEQProof {
-    fn calculate_composites(
+impl MerlinBatchDLEQProof {
+    /// Construct a new `MerlinBatchDLEQProof`
+    pub fn new(
         transcript: &mut Transcript,
         blinded_tokens: &[BlindedToken],
         signed_tokens: &[SignedToken],
-        public_key: &PublicKey,
-    ) -> Result<(RistrettoPoint, RistrettoPoint), TokenError> {
-        if blinded_tokens.len() != signed_tokens.len() {
-            return Err(TokenError(InternalError::LengthMismatchError));
-        }
-
-        transcript.commit_point(b"X", &constants::RISTRETTO_BASEPOINT_COMPRESSED);
-        transcript.commit_point(b"Y", &public_key.0);
-
-        for (Pi, Qi) in blinded_tokens.iter().zip(signed_tokens.iter()) {
-            transcript.commit_point(b"Pi", &Pi.0);
-            transcript.commit_point(b"Qi", &Qi.0);
-        }
-
-        let c_m: Vec<Scalar> = iter::repeat_with(|| transcript.challenge_scalar(b"c_i"))
-            .take(blinded_tokens.len())
-            .collect();
-
-        let M = RistrettoPoint::optional_multiscalar_mul(
-            &c_m,
-            blinded_tokens.iter().map(|Pi| Pi.0.decompress()),
+        signing_key: &SigningKey,
+    ) -> Result<Self, TokenError> {
+        BatchDLEQProof::new_with_transcript(
+            transcript,
+            &mut ChaChaRng::from_seed([0; 32]),
+            blinded_tokens,
+            signed_tokens,
+            signing_key,
         )
-        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        .map(MerlinBatchDLEQProof::from)
+    }
 
-        let Z = RistrettoPoint::optional_multiscalar_mul(
-            &c_m,
-            signed_tokens.iter().map(|Qi| Qi.0.decompress()),
+    /// Verify a `MerlinBatchDLEQProof`
+    pub fn verify(
+        &self,
+        transcript: &mut Transcript,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError> {
+        let MerlinDLEQProof(DLEQProof { c, s }) = self.0;
+        BatchDLEQProof(DLEQProof { c, s }).verify_with_transcript(
+            transcript,
+            blinded_tokens,
+            signed_tokens,
+            public_key,
         )
-        .ok_or(TokenError(InternalError::PointDecompressionError))?;
-
-        Ok((M, Z))
     }
 
-    /// Construct a new `BatchDLEQProof`
-    pub fn new(
+    /// Construct a new `MerlinBatchDLEQProof` for `SignedToken`s produced by `SigningKey::sign_with_info`
+    pub fn new_with_info<D>(
         transcript: &mut Transcript,
         blinded_tokens: &[BlindedToken],
         signed_tokens: &[SignedToken],
         signing_key: &SigningKey,
-    ) -> Result<Self, TokenError> {
-        transcript.dleq_domain_sep();
-
+        info: &[u8],
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        transcript.domain_separator(b"dleq");
+        transcript.append_context(b"info", info);
+
+        let tweaked_key = signing_key.tweak::<D>(info)?;
         let (M, Z) = BatchDLEQProof::calculate_composites(
             transcript,
             blinded_tokens,
             signed_tokens,
-            &signing_key.public_key,
+            &tweaked_key.public_key,
         )?;
 
-        Ok(BatchDLEQProof(DLEQProof::_new(
+        // The tokens were signed with the inverse of the tweaked key, so M = Z^{k'}
+        Ok(MerlinBatchDLEQProof(MerlinDLEQProof::_new(
             transcript,
-            M,
             Z,
-            signing_key,
-        )?))
+            M,
+            &tweaked_key,
+        )))
     }
 
-    /// Verify a `BatchDLEQProof`
-    pub fn verify(
+    /// Verify a `MerlinBatchDLEQProof` constructed with `MerlinBatchDLEQProof::new_with_info`
+    pub fn verify_with_info<D>(
         &self,
         transcript: &mut Transcript,
         blinded_tokens: &[BlindedToken],
         signed_tokens: &[SignedToken],
         public_key: &PublicKey,
-    ) -> Result<(), TokenError> {
-        transcript.dleq_domain_sep();
-
+        info: &[u8],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        transcript.domain_separator(b"dleq");
+        transcript.append_context(b"info", info);
+
+        let tweaked_public_key = public_key.tweak::<D>(info)?;
         let (M, Z) = BatchDLEQProof::calculate_composites(
             transcript,
             blinded_tokens,
             signed_tokens,
-            public_key,
+            &tweaked_public_key,
         )?;
 
-        self.0._verify(transcript, M, Z, public_key)
+        self.0._verify(transcript, Z, M, &tweaked_public_key)
     }
 }
 
-impl BatchDLEQProof {
-    /// Convert this `BatchDLEQProof` to a byte array.
+impl MerlinBatchDLEQProof {
+    /// Convert this `MerlinBatchDLEQProof` to a byte array.
     pub fn to_bytes(&self) -> [u8; DLEQ_PROOF_LENGTH] {
         self.0.to_bytes()
     }
 
     #[cfg(feature = "serde")]
     fn bytes_length_error() -> TokenError {
-        DLEQProof::bytes_length_error()
+        MerlinDLEQProof::bytes_length_error()
     }
 
-    /// Construct a `BatchDLEQProof` from a slice of bytes.
-    pub fn from_bytes(bytes: &[u8]) -> Result<BatchDLEQProof, TokenError> {
-        DLEQProof::from_bytes(bytes).map(BatchDLEQProof)
+    /// Construct a `MerlinBatchDLEQProof` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerlinBatchDLEQProof, TokenError> {
+        MerlinDLEQProof::from_bytes(bytes).map(MerlinBatchDLEQProof)
+    }
+}
+
+impl SigningKey {
+    /// Sign each of the provided `BlindedToken`s and construct a `MerlinBatchDLEQProof` over them.
+    ///
+    /// This is `SigningKey::issue_with_transcript` over a merlin `Transcript`, and is equivalent
+    /// to calling `SigningKey::sign` for each token followed by `MerlinBatchDLEQProof::new`.
+    pub fn issue_merlin(
+        &self,
+        transcript: &mut Transcript,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<(Vec<SignedToken>, MerlinBatchDLEQProof), TokenError> {
+        self.issue_with_transcript(
+            transcript,
+            &mut ChaChaRng::from_seed([0; 32]),
+            blinded_tokens,
+        )
+        .map(|(signed_tokens, proof)| (signed_tokens, MerlinBatchDLEQProof::from(proof)))
     }
 }
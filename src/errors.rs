@@ -28,6 +28,19 @@ pub enum InternalError {
     LengthMismatchError,
     /// Decoding failed
     DecodingError,
+    /// The input was rejected, for example because it hashed to the identity element
+    InvalidInputError,
+    /// No usable key was found for a key identifier
+    UnknownKeyError,
+    /// The token has already been redeemed
+    DoubleSpendError,
+    /// The public key differs from the one the tokens were requested for
+    KeyMismatchError,
+    /// The contribution of a threshold share-holder failed verification
+    InvalidShareError {
+        /// The index of the share
+        index: u32,
+    },
 }
 
 impl Display for InternalError {
@@ -43,6 +56,18 @@ impl Display for InternalError {
             InternalError::VerifyError => write!(f, "Verification failed"),
             InternalError::LengthMismatchError => write!(f, "Inputs differed in length"),
             InternalError::DecodingError => write!(f, "Decoding failed"),
+            InternalError::InvalidInputError => write!(f, "Invalid input"),
+            InternalError::UnknownKeyError => write!(f, "No usable key for the key identifier"),
+            InternalError::DoubleSpendError => write!(f, "Token has already been spent"),
+            InternalError::KeyMismatchError => {
+                write!(
+                    f,
+                    "Public key differs from the one tokens were requested for"
+                )
+            }
+            InternalError::InvalidShareError { index: i } => {
+                write!(f, "Share {} failed verification", i)
+            }
         }
     }
 }
@@ -0,0 +1,664 @@
+//! A C ABI for use from other languages.
+//!
+//! Every type is exposed as an opaque handle, allocated by a constructor such as
+//! `cbr_token_random` or `cbr_token_from_bytes` and released with the matching destructor
+//! such as `cbr_token_destroy`. Fallible functions return a `CbrError`, with outputs
+//! written through pointer arguments, and `CBR_OK` on success.
+//!
+//! The hash and MAC used for token operations are fixed to SHA-512 and HMAC-SHA512.
+//! A C header is generated with `cbindgen --config cbindgen.toml`, see
+//! `include/challenge_bypass_ristretto.h`.
+
+use std::boxed::Box;
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+use std::vec::Vec;
+
+use hmac::Hmac;
+use rand::rngs::OsRng;
+use sha2::Sha512;
+
+use crate::errors::{InternalError, TokenError};
+use crate::voprf::*;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// The result of a fallible FFI call.
+pub type CbrError = c_int;
+
+/// The call succeeded
+pub const CBR_OK: CbrError = 0;
+/// A required pointer argument was null
+pub const CBR_NULL_POINTER: CbrError = -1;
+/// See `InternalError::PointDecompressionError`
+pub const CBR_POINT_DECOMPRESSION_ERROR: CbrError = 1;
+/// See `InternalError::ScalarFormatError`
+pub const CBR_SCALAR_FORMAT_ERROR: CbrError = 2;
+/// See `InternalError::BytesLengthError`
+pub const CBR_BYTES_LENGTH_ERROR: CbrError = 3;
+/// See `InternalError::VerifyError`
+pub const CBR_VERIFY_ERROR: CbrError = 4;
+/// See `InternalError::LengthMismatchError`
+pub const CBR_LENGTH_MISMATCH_ERROR: CbrError = 5;
+/// See `InternalError::DecodingError`
+pub const CBR_DECODING_ERROR: CbrError = 6;
+/// See `InternalError::InvalidInputError`
+pub const CBR_INVALID_INPUT_ERROR: CbrError = 7;
+/// See `InternalError::UnknownKeyError`
+pub const CBR_UNKNOWN_KEY_ERROR: CbrError = 8;
+/// See `InternalError::DoubleSpendError`
+pub const CBR_DOUBLE_SPEND_ERROR: CbrError = 9;
+/// See `InternalError::KeyMismatchError`
+pub const CBR_KEY_MISMATCH_ERROR: CbrError = 10;
+/// See `InternalError::InvalidShareError`
+pub const CBR_INVALID_SHARE_ERROR: CbrError = 11;
+
+/// The length of an encoded `TokenPreimage`, in bytes.
+pub const CBR_TOKEN_PREIMAGE_LENGTH: usize = 64;
+/// The length of an encoded `Token`, in bytes.
+pub const CBR_TOKEN_LENGTH: usize = 96;
+/// The length of an encoded `BlindedToken`, in bytes.
+pub const CBR_BLINDED_TOKEN_LENGTH: usize = 32;
+/// The length of an encoded `SignedToken`, in bytes.
+pub const CBR_SIGNED_TOKEN_LENGTH: usize = 32;
+/// The length of an encoded `UnblindedToken`, in bytes.
+pub const CBR_UNBLINDED_TOKEN_LENGTH: usize = 96;
+/// The length of an encoded `PublicKey`, in bytes.
+pub const CBR_PUBLIC_KEY_LENGTH: usize = 32;
+/// The length of an encoded `SigningKey`, in bytes.
+pub const CBR_SIGNING_KEY_LENGTH: usize = 32;
+/// The length of an encoded `VerificationSignature`, in bytes.
+pub const CBR_VERIFICATION_SIGNATURE_LENGTH: usize = 64;
+/// The length of an encoded `BatchDLEQProof`, in bytes.
+pub const CBR_BATCH_DLEQ_PROOF_LENGTH: usize = 64;
+
+fn error_code(error: TokenError) -> CbrError {
+    match error.0 {
+        InternalError::PointDecompressionError => CBR_POINT_DECOMPRESSION_ERROR,
+        InternalError::ScalarFormatError => CBR_SCALAR_FORMAT_ERROR,
+        InternalError::BytesLengthError { .. } => CBR_BYTES_LENGTH_ERROR,
+        InternalError::VerifyError => CBR_VERIFY_ERROR,
+        InternalError::LengthMismatchError => CBR_LENGTH_MISMATCH_ERROR,
+        InternalError::DecodingError => CBR_DECODING_ERROR,
+        InternalError::InvalidInputError => CBR_INVALID_INPUT_ERROR,
+        InternalError::UnknownKeyError => CBR_UNKNOWN_KEY_ERROR,
+        InternalError::DoubleSpendError => CBR_DOUBLE_SPEND_ERROR,
+        InternalError::KeyMismatchError => CBR_KEY_MISMATCH_ERROR,
+        InternalError::InvalidShareError { .. } => CBR_INVALID_SHARE_ERROR,
+    }
+}
+
+/// Move `result` into a new handle written to `out`.
+unsafe fn write_handle<T>(result: Result<T, TokenError>, out: *mut *mut T) -> CbrError {
+    if out.is_null() {
+        return CBR_NULL_POINTER;
+    }
+    match result {
+        Ok(value) => {
+            *out = Box::into_raw(Box::new(value));
+            CBR_OK
+        }
+        Err(error) => {
+            *out = ptr::null_mut();
+            error_code(error)
+        }
+    }
+}
+
+/// Collect an array of `n` handles into references, failing if any is null.
+unsafe fn handles<'a, T>(array: *const *const T, n: usize) -> Option<Vec<&'a T>> {
+    if n == 0 {
+        return Some(Vec::new());
+    }
+    if array.is_null() {
+        return None;
+    }
+    slice::from_raw_parts(array, n)
+        .iter()
+        .map(|handle| handle.as_ref())
+        .collect()
+}
+
+/// View `len` bytes at `bytes` as a slice, allowing null for an empty slice.
+unsafe fn bytes<'a>(bytes: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if bytes.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(bytes, len))
+    }
+}
+
+/// Define the destructor and byte conversions for a handle type.
+macro_rules! ffi_handle {
+    ($t:ident, $length:expr, $destroy:ident, $to_bytes:ident, $from_bytes:ident) => {
+        #[doc = concat!(" Destroy a `", stringify!($t), "` handle. Passing null is a no-op.")]
+        ///
+        /// # Safety
+        ///
+        /// The handle must have been returned by this library and not already destroyed.
+        #[no_mangle]
+        pub unsafe extern "C" fn $destroy(handle: *mut $t) {
+            if !handle.is_null() {
+                drop(Box::from_raw(handle));
+            }
+        }
+
+        #[doc = concat!(" Write the ", stringify!($length), " byte encoding of a `", stringify!($t), "` to `out`.")]
+        ///
+        /// # Safety
+        ///
+        /// `handle` must be a valid handle and `out` must point to a buffer of at least
+        /// that many bytes.
+        #[no_mangle]
+        pub unsafe extern "C" fn $to_bytes(handle: *const $t, out: *mut u8) -> CbrError {
+            match handle.as_ref() {
+                Some(value) if !out.is_null() => {
+                    let encoded = value.to_bytes();
+                    ptr::copy_nonoverlapping(encoded.as_ptr(), out, encoded.len());
+                    CBR_OK
+                }
+                _ => CBR_NULL_POINTER,
+            }
+        }
+
+        #[doc = concat!(" Decode a `", stringify!($t), "` from `len` bytes, writing a new handle to `out`.")]
+        ///
+        /// # Safety
+        ///
+        /// `bytes` must point to `len` readable bytes and `out` must be a valid pointer.
+        #[no_mangle]
+        pub unsafe extern "C" fn $from_bytes(
+            bytes: *const u8,
+            len: usize,
+            out: *mut *mut $t,
+        ) -> CbrError {
+            match self::bytes(bytes, len) {
+                Some(bytes) => write_handle($t::from_bytes(bytes), out),
+                None => CBR_NULL_POINTER,
+            }
+        }
+    };
+}
+
+ffi_handle!(
+    TokenPreimage,
+    CBR_TOKEN_PREIMAGE_LENGTH,
+    cbr_token_preimage_destroy,
+    cbr_token_preimage_to_bytes,
+    cbr_token_preimage_from_bytes
+);
+ffi_handle!(
+    Token,
+    CBR_TOKEN_LENGTH,
+    cbr_token_destroy,
+    cbr_token_to_bytes,
+    cbr_token_from_bytes
+);
+ffi_handle!(
+    BlindedToken,
+    CBR_BLINDED_TOKEN_LENGTH,
+    cbr_blinded_token_destroy,
+    cbr_blinded_token_to_bytes,
+    cbr_blinded_token_from_bytes
+);
+ffi_handle!(
+    SignedToken,
+    CBR_SIGNED_TOKEN_LENGTH,
+    cbr_signed_token_destroy,
+    cbr_signed_token_to_bytes,
+    cbr_signed_token_from_bytes
+);
+ffi_handle!(
+    UnblindedToken,
+    CBR_UNBLINDED_TOKEN_LENGTH,
+    cbr_unblinded_token_destroy,
+    cbr_unblinded_token_to_bytes,
+    cbr_unblinded_token_from_bytes
+);
+ffi_handle!(
+    PublicKey,
+    CBR_PUBLIC_KEY_LENGTH,
+    cbr_public_key_destroy,
+    cbr_public_key_to_bytes,
+    cbr_public_key_from_bytes
+);
+ffi_handle!(
+    SigningKey,
+    CBR_SIGNING_KEY_LENGTH,
+    cbr_signing_key_destroy,
+    cbr_signing_key_to_bytes,
+    cbr_signing_key_from_bytes
+);
+ffi_handle!(
+    VerificationSignature,
+    CBR_VERIFICATION_SIGNATURE_LENGTH,
+    cbr_verification_signature_destroy,
+    cbr_verification_signature_to_bytes,
+    cbr_verification_signature_from_bytes
+);
+ffi_handle!(
+    BatchDLEQProof,
+    CBR_BATCH_DLEQ_PROOF_LENGTH,
+    cbr_batch_dleq_proof_destroy,
+    cbr_batch_dleq_proof_to_bytes,
+    cbr_batch_dleq_proof_from_bytes
+);
+
+/// Generate a new random `Token`.
+#[no_mangle]
+pub extern "C" fn cbr_token_random() -> *mut Token {
+    Box::into_raw(Box::new(Token::random::<Sha512, _>(&mut OsRng)))
+}
+
+/// Blind a `Token`, returning a new `BlindedToken` handle, or null if `token` is null.
+///
+/// # Safety
+///
+/// `token` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_token_blind(token: *const Token) -> *mut BlindedToken {
+    match token.as_ref() {
+        Some(token) => Box::into_raw(Box::new(token.blind())),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Generate a new random `SigningKey`.
+#[no_mangle]
+pub extern "C" fn cbr_signing_key_random() -> *mut SigningKey {
+    Box::into_raw(Box::new(SigningKey::random(&mut OsRng)))
+}
+
+/// Return a new handle to the `PublicKey` of a `SigningKey`, or null if `key` is null.
+///
+/// # Safety
+///
+/// `key` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_signing_key_public_key(key: *const SigningKey) -> *mut PublicKey {
+    match key.as_ref() {
+        Some(key) => Box::into_raw(Box::new(key.public_key)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Sign a `BlindedToken`, writing a new `SignedToken` handle to `out`.
+///
+/// # Safety
+///
+/// `key` and `blinded_token` must be valid handles and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_signing_key_sign(
+    key: *const SigningKey,
+    blinded_token: *const BlindedToken,
+    out: *mut *mut SignedToken,
+) -> CbrError {
+    match (key.as_ref(), blinded_token.as_ref()) {
+        (Some(key), Some(blinded_token)) => write_handle(key.sign(blinded_token), out),
+        _ => CBR_NULL_POINTER,
+    }
+}
+
+/// Check a redemption: rederive the `UnblindedToken` for `preimage` and verify the client's
+/// `VerificationSignature` over `message`.
+///
+/// Returns `CBR_OK` if the signature is valid and `CBR_VERIFY_ERROR` otherwise. Checking
+/// that the token has not been spent before is left to the caller.
+///
+/// # Safety
+///
+/// All handles must be valid and `message` must point to `message_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_signing_key_redeem(
+    key: *const SigningKey,
+    preimage: *const TokenPreimage,
+    signature: *const VerificationSignature,
+    message: *const u8,
+    message_len: usize,
+) -> CbrError {
+    match (
+        key.as_ref(),
+        preimage.as_ref(),
+        signature.as_ref(),
+        bytes(message, message_len),
+    ) {
+        (Some(key), Some(preimage), Some(signature), Some(message)) => {
+            let verification_key = key
+                .rederive_unblinded_token(preimage)
+                .derive_verification_key::<Sha512>();
+            if verification_key.verify::<HmacSha512>(signature, message) {
+                CBR_OK
+            } else {
+                CBR_VERIFY_ERROR
+            }
+        }
+        _ => CBR_NULL_POINTER,
+    }
+}
+
+/// Return a new handle to the `TokenPreimage` of an `UnblindedToken`, or null if
+/// `token` is null.
+///
+/// # Safety
+///
+/// `token` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_unblinded_token_preimage(
+    token: *const UnblindedToken,
+) -> *mut TokenPreimage {
+    match token.as_ref() {
+        Some(token) => Box::into_raw(Box::new(token.t)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Sign `message` with the `VerificationKey` derived from an `UnblindedToken`, writing a
+/// new `VerificationSignature` handle to `out`.
+///
+/// # Safety
+///
+/// `token` must be a valid handle, `message` must point to `message_len` readable bytes
+/// and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_unblinded_token_sign(
+    token: *const UnblindedToken,
+    message: *const u8,
+    message_len: usize,
+    out: *mut *mut VerificationSignature,
+) -> CbrError {
+    match (token.as_ref(), bytes(message, message_len)) {
+        (Some(token), Some(message)) => write_handle(
+            Ok(token
+                .derive_verification_key::<Sha512>()
+                .sign::<HmacSha512>(message)),
+            out,
+        ),
+        _ => CBR_NULL_POINTER,
+    }
+}
+
+/// Construct a `BatchDLEQProof` over `n` `BlindedToken`s and the corresponding
+/// `SignedToken`s, writing a new handle to `out`.
+///
+/// # Safety
+///
+/// `blinded_tokens` and `signed_tokens` must each point to `n` valid handles, `key` must
+/// be a valid handle and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_batch_dleq_proof_new(
+    key: *const SigningKey,
+    blinded_tokens: *const *const BlindedToken,
+    signed_tokens: *const *const SignedToken,
+    n: usize,
+    out: *mut *mut BatchDLEQProof,
+) -> CbrError {
+    match (
+        key.as_ref(),
+        handles(blinded_tokens, n),
+        handles(signed_tokens, n),
+    ) {
+        (Some(key), Some(blinded_tokens), Some(signed_tokens)) => {
+            let blinded_tokens: Vec<BlindedToken> = blinded_tokens.into_iter().copied().collect();
+            let signed_tokens: Vec<SignedToken> = signed_tokens.into_iter().copied().collect();
+            write_handle(
+                BatchDLEQProof::new::<Sha512, _>(&mut OsRng, &blinded_tokens, &signed_tokens, key),
+                out,
+            )
+        }
+        _ => CBR_NULL_POINTER,
+    }
+}
+
+/// Verify a `BatchDLEQProof` over `n` `BlindedToken`s and `SignedToken`s.
+///
+/// # Safety
+///
+/// `blinded_tokens` and `signed_tokens` must each point to `n` valid handles, and `proof`
+/// and `public_key` must be valid handles.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_batch_dleq_proof_verify(
+    proof: *const BatchDLEQProof,
+    blinded_tokens: *const *const BlindedToken,
+    signed_tokens: *const *const SignedToken,
+    n: usize,
+    public_key: *const PublicKey,
+) -> CbrError {
+    match (
+        proof.as_ref(),
+        handles(blinded_tokens, n),
+        handles(signed_tokens, n),
+        public_key.as_ref(),
+    ) {
+        (Some(proof), Some(blinded_tokens), Some(signed_tokens), Some(public_key)) => {
+            let blinded_tokens: Vec<BlindedToken> = blinded_tokens.into_iter().copied().collect();
+            let signed_tokens: Vec<SignedToken> = signed_tokens.into_iter().copied().collect();
+            match proof.verify::<Sha512>(&blinded_tokens, &signed_tokens, public_key) {
+                Ok(()) => CBR_OK,
+                Err(error) => error_code(error),
+            }
+        }
+        _ => CBR_NULL_POINTER,
+    }
+}
+
+/// Verify a `BatchDLEQProof` and unblind the `SignedToken`s, writing `n` new
+/// `UnblindedToken` handles to the array `out`.
+///
+/// On failure every element of `out` is set to null.
+///
+/// # Safety
+///
+/// `tokens`, `blinded_tokens` and `signed_tokens` must each point to `n` valid handles,
+/// `proof` and `public_key` must be valid handles and `out` must point to space for `n`
+/// pointers.
+#[no_mangle]
+pub unsafe extern "C" fn cbr_batch_dleq_proof_verify_and_unblind(
+    proof: *const BatchDLEQProof,
+    tokens: *const *const Token,
+    blinded_tokens: *const *const BlindedToken,
+    signed_tokens: *const *const SignedToken,
+    n: usize,
+    public_key: *const PublicKey,
+    out: *mut *mut UnblindedToken,
+) -> CbrError {
+    if out.is_null() && n > 0 {
+        return CBR_NULL_POINTER;
+    }
+    let result = match (
+        proof.as_ref(),
+        handles(tokens, n),
+        handles(blinded_tokens, n),
+        handles(signed_tokens, n),
+        public_key.as_ref(),
+    ) {
+        (
+            Some(proof),
+            Some(tokens),
+            Some(blinded_tokens),
+            Some(signed_tokens),
+            Some(public_key),
+        ) => {
+            let blinded_tokens: Vec<BlindedToken> = blinded_tokens.into_iter().copied().collect();
+            let signed_tokens: Vec<SignedToken> = signed_tokens.into_iter().copied().collect();
+            proof
+                .verify_and_unblind::<Sha512, _>(
+                    tokens,
+                    &blinded_tokens,
+                    &signed_tokens,
+                    public_key,
+                )
+                .map_err(error_code)
+        }
+        _ => Err(CBR_NULL_POINTER),
+    };
+
+    match result {
+        Ok(unblinded_tokens) => {
+            for (i, unblinded_token) in unblinded_tokens.into_iter().enumerate() {
+                *out.add(i) = Box::into_raw(Box::new(unblinded_token));
+            }
+            CBR_OK
+        }
+        Err(code) => {
+            for i in 0..n {
+                *out.add(i) = ptr::null_mut();
+            }
+            code
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        unsafe {
+            let key = cbr_signing_key_random();
+            let public_key = cbr_signing_key_public_key(key);
+
+            // client
+            let token = cbr_token_random();
+            let blinded_token = cbr_token_blind(token);
+
+            // the blinded token is sent as bytes
+            let mut encoded = [0u8; CBR_BLINDED_TOKEN_LENGTH];
+            assert_eq!(
+                cbr_blinded_token_to_bytes(blinded_token, encoded.as_mut_ptr()),
+                CBR_OK
+            );
+            let mut received = ptr::null_mut();
+            assert_eq!(
+                cbr_blinded_token_from_bytes(encoded.as_ptr(), encoded.len(), &mut received),
+                CBR_OK
+            );
+
+            // server
+            let mut signed_token = ptr::null_mut();
+            assert_eq!(
+                cbr_signing_key_sign(key, received, &mut signed_token),
+                CBR_OK
+            );
+            let mut proof = ptr::null_mut();
+            let blinded_tokens = [received as *const BlindedToken];
+            let signed_tokens = [signed_token as *const SignedToken];
+            assert_eq!(
+                cbr_batch_dleq_proof_new(
+                    key,
+                    blinded_tokens.as_ptr(),
+                    signed_tokens.as_ptr(),
+                    1,
+                    &mut proof
+                ),
+                CBR_OK
+            );
+
+            // client
+            let tokens = [token as *const Token];
+            let mut unblinded_tokens = [ptr::null_mut(); 1];
+            assert_eq!(
+                cbr_batch_dleq_proof_verify_and_unblind(
+                    proof,
+                    tokens.as_ptr(),
+                    blinded_tokens.as_ptr(),
+                    signed_tokens.as_ptr(),
+                    1,
+                    public_key,
+                    unblinded_tokens.as_mut_ptr()
+                ),
+                CBR_OK
+            );
+            let message = b"test message";
+            let mut signature = ptr::null_mut();
+            assert_eq!(
+                cbr_unblinded_token_sign(
+                    unblinded_tokens[0],
+                    message.as_ptr(),
+                    message.len(),
+                    &mut signature
+                ),
+                CBR_OK
+            );
+            let preimage = cbr_unblinded_token_preimage(unblinded_tokens[0]);
+
+            // server
+            assert_eq!(
+                cbr_signing_key_redeem(key, preimage, signature, message.as_ptr(), message.len()),
+                CBR_OK
+            );
+            assert_eq!(
+                cbr_signing_key_redeem(key, preimage, signature, b"other".as_ptr(), 5),
+                CBR_VERIFY_ERROR
+            );
+
+            // a proof from a different key is rejected
+            let other_key = cbr_signing_key_random();
+            let other_public_key = cbr_signing_key_public_key(other_key);
+            assert_eq!(
+                cbr_batch_dleq_proof_verify(
+                    proof,
+                    blinded_tokens.as_ptr(),
+                    signed_tokens.as_ptr(),
+                    1,
+                    other_public_key
+                ),
+                CBR_VERIFY_ERROR
+            );
+
+            cbr_public_key_destroy(other_public_key);
+            cbr_signing_key_destroy(other_key);
+            cbr_token_preimage_destroy(preimage);
+            cbr_verification_signature_destroy(signature);
+            cbr_unblinded_token_destroy(unblinded_tokens[0]);
+            cbr_batch_dleq_proof_destroy(proof);
+            cbr_signed_token_destroy(signed_token);
+            cbr_blinded_token_destroy(received);
+            cbr_blinded_token_destroy(blinded_token);
+            cbr_token_destroy(token);
+            cbr_public_key_destroy(public_key);
+            cbr_signing_key_destroy(key);
+        }
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(CBR_TOKEN_PREIMAGE_LENGTH, TOKEN_PREIMAGE_LENGTH);
+        assert_eq!(CBR_TOKEN_LENGTH, TOKEN_LENGTH);
+        assert_eq!(CBR_BLINDED_TOKEN_LENGTH, BLINDED_TOKEN_LENGTH);
+        assert_eq!(CBR_SIGNED_TOKEN_LENGTH, SIGNED_TOKEN_LENGTH);
+        assert_eq!(CBR_UNBLINDED_TOKEN_LENGTH, UNBLINDED_TOKEN_LENGTH);
+        assert_eq!(CBR_PUBLIC_KEY_LENGTH, PUBLIC_KEY_LENGTH);
+        assert_eq!(CBR_SIGNING_KEY_LENGTH, SIGNING_KEY_LENGTH);
+        assert_eq!(
+            CBR_VERIFICATION_SIGNATURE_LENGTH,
+            VERIFICATION_SIGNATURE_LENGTH
+        );
+        assert_eq!(CBR_BATCH_DLEQ_PROOF_LENGTH, DLEQ_PROOF_LENGTH);
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let mut out = ptr::null_mut();
+            assert_eq!(
+                cbr_public_key_from_bytes([0u8; 3].as_ptr(), 3, &mut out),
+                CBR_BYTES_LENGTH_ERROR
+            );
+            assert!(out.is_null());
+            assert_eq!(
+                cbr_public_key_from_bytes(ptr::null(), 32, &mut out),
+                CBR_NULL_POINTER
+            );
+            assert_eq!(
+                cbr_signing_key_sign(ptr::null(), ptr::null(), &mut ptr::null_mut()),
+                CBR_NULL_POINTER
+            );
+            assert!(cbr_token_blind(ptr::null()).is_null());
+            cbr_token_destroy(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Probabilistic double-spend detection for memory bounded redeemers.
+//!
+//! A `SpentTokenFilter` is a keyed Bloom filter over `TokenPreimage`s. It never reports a
+//! spent token as unspent, but with a tunable false positive rate it may reject a fresh
+//! token as already spent. The filter is keyed with a secret so that clients, who choose
+//! their own preimages, cannot craft tokens which collide with or saturate it.
+//!
+//! `FilterSpentTokenStore` keeps one filter per `PublicKey`, so that the filter for a
+//! retired key can be dropped and each key epoch starts afresh.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{PublicKey, TokenPreimage, PUBLIC_KEY_LENGTH};
+use crate::redemption::SpentTokenStore;
+
+const FILTER_KEY_LENGTH: usize = 32;
+const FILTER_HEADER_LENGTH: usize = FILTER_KEY_LENGTH + 8 + 4 + 8;
+
+/// The largest number of bits in a `SpentTokenFilter`, 4 GiB.
+pub const MAX_FILTER_BITS: u64 = 1 << 35;
+/// The largest number of hashes per token in a `SpentTokenFilter`, enough for a false
+/// positive rate of \\(2^{-32}\\).
+pub const MAX_FILTER_HASHES: u32 = 32;
+
+/// The number of bits and hashes of a filter holding `capacity` tokens with the given
+/// false positive rate.
+///
+/// Returns a `TokenError` if the filter would exceed `MAX_FILTER_BITS` or
+/// `MAX_FILTER_HASHES`.
+fn filter_parameters(capacity: u64, false_positive_rate: f64) -> Result<(u64, u32), TokenError> {
+    if capacity == 0 || !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+
+    // m = -n ln(p) / ln(2)^2, k = (m / n) ln(2)
+    let ln2 = core::f64::consts::LN_2;
+    let num_bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+    if num_bits > MAX_FILTER_BITS as f64 {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+    let num_bits = (num_bits / 8.0).ceil() as u64 * 8;
+    let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0);
+    if num_hashes > MAX_FILTER_HASHES as f64 {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+    Ok((num_bits, num_hashes as u32))
+}
+
+/// A keyed Bloom filter recording spent `TokenPreimage`s.
+pub struct SpentTokenFilter {
+    key: [u8; FILTER_KEY_LENGTH],
+    num_bits: u64,
+    num_hashes: u32,
+    count: u64,
+    bits: Vec<u8>,
+}
+
+/// Overwrite the filter key with null when it goes out of scope.
+impl Drop for SpentTokenFilter {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl core::fmt::Debug for SpentTokenFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("SpentTokenFilter")
+            .field("num_bits", &self.num_bits)
+            .field("num_hashes", &self.num_hashes)
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+impl SpentTokenFilter {
+    /// Construct an empty filter sized to hold `capacity` tokens with the given false
+    /// positive rate, using a random key.
+    ///
+    /// Returns a `TokenError` if the filter would exceed `MAX_FILTER_BITS` or
+    /// `MAX_FILTER_HASHES`.
+    pub fn new<T: Rng + CryptoRng>(
+        rng: &mut T,
+        capacity: u64,
+        false_positive_rate: f64,
+    ) -> Result<Self, TokenError> {
+        let mut key = [0u8; FILTER_KEY_LENGTH];
+        rng.fill(&mut key);
+        SpentTokenFilter::with_key(key, capacity, false_positive_rate)
+    }
+
+    fn with_key(
+        key: [u8; FILTER_KEY_LENGTH],
+        capacity: u64,
+        false_positive_rate: f64,
+    ) -> Result<Self, TokenError> {
+        let (num_bits, num_hashes) = filter_parameters(capacity, false_positive_rate)?;
+
+        Ok(SpentTokenFilter {
+            key,
+            num_bits,
+            num_hashes,
+            count: 0,
+            bits: vec![0u8; (num_bits / 8) as usize],
+        })
+    }
+
+    /// The bit positions for `preimage`, using double hashing over a keyed digest.
+    fn indexes<D>(&self, preimage: &TokenPreimage) -> impl Iterator<Item = u64>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut h = D::default();
+        h.update(b"spent_token_filter");
+        h.update(self.key);
+        h.update(preimage.to_bytes());
+        let output = h.finalize();
+
+        let mut h1 = [0u8; 8];
+        let mut h2 = [0u8; 8];
+        h1.copy_from_slice(&output[..8]);
+        h2.copy_from_slice(&output[8..16]);
+        let h1 = u64::from_le_bytes(h1);
+        let h2 = u64::from_le_bytes(h2) | 1;
+
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    fn bit(&self, index: u64) -> bool {
+        self.bits[(index / 8) as usize] & (1 << (index % 8)) != 0
+    }
+
+    /// Check whether `preimage` may have been spent.
+    ///
+    /// A `false` result is definitive, a `true` result may be a false positive.
+    pub fn contains<D>(&self, preimage: &TokenPreimage) -> bool
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        self.indexes::<D>(preimage).all(|index| self.bit(index))
+    }
+
+    /// Record `preimage` as spent.
+    ///
+    /// Returns `true` if the token was not already (possibly falsely) present.
+    pub fn insert<D>(&mut self, preimage: &TokenPreimage) -> bool
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut inserted = false;
+        let indexes: Vec<u64> = self.indexes::<D>(preimage).collect();
+        for index in indexes {
+            if !self.bit(index) {
+                self.bits[(index / 8) as usize] |= 1 << (index % 8);
+                inserted = true;
+            }
+        }
+        if inserted {
+            self.count += 1;
+        }
+        inserted
+    }
+
+    /// The number of tokens inserted since the filter was created or cleared.
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether no tokens have been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Remove every token from the filter, for example at the start of a new key epoch.
+    pub fn clear(&mut self) {
+        for byte in self.bits.iter_mut() {
+            *byte = 0;
+        }
+        self.count = 0;
+    }
+
+    /// Convert this `SpentTokenFilter` to bytes, for checkpointing.
+    ///
+    /// The encoding includes the filter key, so it must be stored securely.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FILTER_HEADER_LENGTH + self.bits.len());
+        bytes.extend_from_slice(&self.key);
+        bytes.extend_from_slice(&self.num_bits.to_be_bytes());
+        bytes.extend_from_slice(&self.num_hashes.to_be_bytes());
+        bytes.extend_from_slice(&self.count.to_be_bytes());
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    /// Construct a `SpentTokenFilter` from bytes produced by `to_bytes`.
+    ///
+    /// Returns a `TokenError` if the filter exceeds `MAX_FILTER_BITS` or
+    /// `MAX_FILTER_HASHES`, or its size disagrees with the length of `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SpentTokenFilter, TokenError> {
+        if bytes.len() < FILTER_HEADER_LENGTH {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+
+        let mut key = [0u8; FILTER_KEY_LENGTH];
+        let mut num_bits = [0u8; 8];
+        let mut num_hashes = [0u8; 4];
+        let mut count = [0u8; 8];
+        key.copy_from_slice(&bytes[..32]);
+        num_bits.copy_from_slice(&bytes[32..40]);
+        num_hashes.copy_from_slice(&bytes[40..44]);
+        count.copy_from_slice(&bytes[44..FILTER_HEADER_LENGTH]);
+
+        let num_bits = u64::from_be_bytes(num_bits);
+        let num_hashes = u32::from_be_bytes(num_hashes);
+        let bits = &bytes[FILTER_HEADER_LENGTH..];
+        if num_bits > MAX_FILTER_BITS || num_hashes > MAX_FILTER_HASHES {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        if num_bits == 0 || num_hashes == 0 || num_bits != bits.len() as u64 * 8 {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+
+        Ok(SpentTokenFilter {
+            key,
+            num_bits,
+            num_hashes,
+            count: u64::from_be_bytes(count),
+            bits: bits.to_vec(),
+        })
+    }
+}
+
+/// A `SpentTokenStore` which uses a `SpentTokenFilter` per `PublicKey`.
+///
+/// Memory use is fixed by the configured capacity of each filter, at the cost of
+/// occasionally rejecting an unspent token.
+pub struct FilterSpentTokenStore<D> {
+    key: [u8; FILTER_KEY_LENGTH],
+    capacity: u64,
+    false_positive_rate: f64,
+    filters: Mutex<HashMap<[u8; PUBLIC_KEY_LENGTH], SpentTokenFilter>>,
+    digest: PhantomData<D>,
+}
+
+/// Overwrite the master filter key with null when it goes out of scope.
+impl<D> Drop for FilterSpentTokenStore<D> {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl<D> core::fmt::Debug for FilterSpentTokenStore<D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("FilterSpentTokenStore")
+            .field("capacity", &self.capacity)
+            .field("false_positive_rate", &self.false_positive_rate)
+            .finish()
+    }
+}
+
+impl<D> FilterSpentTokenStore<D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    /// Construct a store whose filters each hold `capacity` tokens with the given false
+    /// positive rate.
+    pub fn new<T: Rng + CryptoRng>(
+        rng: &mut T,
+        capacity: u64,
+        false_positive_rate: f64,
+    ) -> Result<Self, TokenError> {
+        // Check the parameters up front rather than on first use
+        filter_parameters(capacity, false_positive_rate)?;
+
+        let mut key = [0u8; FILTER_KEY_LENGTH];
+        rng.fill(&mut key);
+        Ok(FilterSpentTokenStore {
+            key,
+            capacity,
+            false_positive_rate,
+            filters: Mutex::new(HashMap::new()),
+            digest: PhantomData,
+        })
+    }
+
+    /// Derive the key of the filter for `public_key` from the master key.
+    fn filter_key(&self, public_key: &PublicKey) -> [u8; FILTER_KEY_LENGTH] {
+        let mut h = D::default();
+        h.update(b"spent_token_filter_key");
+        h.update(self.key);
+        h.update(public_key.to_bytes());
+
+        let mut key = [0u8; FILTER_KEY_LENGTH];
+        key.copy_from_slice(&h.finalize()[..FILTER_KEY_LENGTH]);
+        key
+    }
+
+    /// Lock the filters, recovering them if another thread panicked while holding the lock.
+    ///
+    /// Setting bits in a filter can at worst leave a token marked as spent, so it is safe to
+    /// keep using them.
+    fn lock(&self) -> MutexGuard<'_, HashMap<[u8; PUBLIC_KEY_LENGTH], SpentTokenFilter>> {
+        self.filters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drop the filter for `public_key`, once the key has been retired.
+    pub fn forget_key(&self, public_key: &PublicKey) {
+        self.lock().remove(&public_key.to_bytes());
+    }
+
+    /// Clear the filter for `public_key`, starting a new epoch for that key.
+    pub fn reset_key(&self, public_key: &PublicKey) {
+        if let Some(filter) = self.lock().get_mut(&public_key.to_bytes()) {
+            filter.clear();
+        }
+    }
+
+    /// Serialize the filter for `public_key`, if any tokens have been spent under it.
+    pub fn checkpoint(&self, public_key: &PublicKey) -> Option<Vec<u8>> {
+        self.lock()
+            .get(&public_key.to_bytes())
+            .map(SpentTokenFilter::to_bytes)
+    }
+
+    /// Restore the filter for `public_key` from a checkpoint, replacing any existing one.
+    pub fn restore(&self, public_key: &PublicKey, bytes: &[u8]) -> Result<(), TokenError> {
+        let filter = SpentTokenFilter::from_bytes(bytes)?;
+        self.lock().insert(public_key.to_bytes(), filter);
+        Ok(())
+    }
+}
+
+impl<D> SpentTokenStore for FilterSpentTokenStore<D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    type Error = TokenError;
+
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> Result<bool, TokenError> {
+        let mut filters = self.lock();
+        let filter = match filters.entry(public_key.to_bytes()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(SpentTokenFilter::with_key(
+                    self.filter_key(public_key),
+                    self.capacity,
+                    self.false_positive_rate,
+                )?)
+            }
+        };
+        Ok(filter.insert::<D>(preimage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::{SigningKey, Token};
+
+    fn preimage() -> TokenPreimage {
+        Token::random::<Sha512, _>(&mut OsRng).t
+    }
+
+    #[test]
+    fn filter_works() {
+        let mut rng = OsRng;
+
+        let mut filter = SpentTokenFilter::new(&mut rng, 1000, 0.001).unwrap();
+        let spent: Vec<TokenPreimage> = (0..1000).map(|_| preimage()).collect();
+        // an unspent token is occasionally a false positive even while filling the filter
+        let inserted = spent.iter().filter(|t| filter.insert::<Sha512>(t)).count() as u64;
+        assert!(inserted > 990);
+        for t in spent.iter() {
+            assert!(filter.contains::<Sha512>(t));
+            assert!(!filter.insert::<Sha512>(t));
+        }
+
+        // at capacity the false positive rate should be close to the target
+        let false_positives = (0..10000)
+            .filter(|_| filter.contains::<Sha512>(&preimage()))
+            .count();
+        assert!(false_positives < 50);
+
+        let restored = SpentTokenFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert_eq!(restored.len(), inserted);
+        assert!(spent.iter().all(|t| restored.contains::<Sha512>(t)));
+
+        filter.clear();
+        assert!(filter.is_empty());
+        assert!(!filter.contains::<Sha512>(&spent[0]));
+
+        assert!(SpentTokenFilter::new(&mut rng, 1000, 1.5).is_err());
+        assert!(SpentTokenFilter::from_bytes(&[0u8; 10]).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_filters() {
+        let mut rng = OsRng;
+        let invalid = Err(TokenError(InternalError::InvalidInputError));
+
+        // Too many bits
+        assert_eq!(
+            SpentTokenFilter::new(&mut rng, u64::MAX, 0.01).map(|_| ()),
+            invalid
+        );
+        assert!(FilterSpentTokenStore::<Sha512>::new(&mut rng, 1 << 40, 0.01).is_err());
+        // Too many hashes
+        assert_eq!(
+            SpentTokenFilter::new(&mut rng, 10, 1e-20).map(|_| ()),
+            invalid
+        );
+        assert!(FilterSpentTokenStore::<Sha512>::new(&mut rng, 10, 1e-20).is_err());
+
+        let bytes = SpentTokenFilter::new(&mut rng, 10, 0.01)
+            .unwrap()
+            .to_bytes();
+        assert!(SpentTokenFilter::from_bytes(&bytes).is_ok());
+
+        let mut hostile = bytes.clone();
+        hostile[40..44].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(SpentTokenFilter::from_bytes(&hostile).map(|_| ()), invalid);
+
+        let mut hostile = bytes.clone();
+        hostile[32..40].copy_from_slice(&(MAX_FILTER_BITS * 2).to_be_bytes());
+        assert_eq!(SpentTokenFilter::from_bytes(&hostile).map(|_| ()), invalid);
+
+        // More bits than the checkpoint holds
+        let mut hostile = bytes;
+        hostile.pop();
+        assert!(SpentTokenFilter::from_bytes(&hostile).is_err());
+    }
+
+    #[test]
+    fn store_works() {
+        let mut rng = OsRng;
+
+        let store = FilterSpentTokenStore::<Sha512>::new(&mut rng, 100, 0.0001).unwrap();
+        let key1 = SigningKey::random(&mut rng).public_key;
+        let key2 = SigningKey::random(&mut rng).public_key;
+        let t = preimage();
+
+        assert!(store.check_and_insert(&key1, &t).unwrap());
+        assert!(!store.check_and_insert(&key1, &t).unwrap());
+        assert!(store.check_and_insert(&key2, &t).unwrap());
+
+        let checkpoint = store.checkpoint(&key1).unwrap();
+        store.reset_key(&key1);
+        assert!(store.check_and_insert(&key1, &t).unwrap());
+
+        store.forget_key(&key1);
+        assert!(store.checkpoint(&key1).is_none());
+        store.restore(&key1, &checkpoint).unwrap();
+        assert!(!store.check_and_insert(&key1, &t).unwrap());
+    }
+
+    #[test]
+    fn store_survives_poisoning() {
+        let mut rng = OsRng;
+
+        let store = std::sync::Arc::new(
+            FilterSpentTokenStore::<Sha512>::new(&mut rng, 100, 0.0001).unwrap(),
+        );
+        let key = SigningKey::random(&mut rng).public_key;
+        let t = preimage();
+
+        let poisoner = std::sync::Arc::clone(&store);
+        assert!(std::thread::spawn(move || {
+            let _guard = poisoner.filters.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .is_err());
+
+        assert!(store.check_and_insert(&key, &t).unwrap());
+        assert!(!store.check_and_insert(&key, &t).unwrap());
+    }
+}
@@ -0,0 +1,242 @@
+//! A collection of issuer keys for use across key rotation.
+//!
+//! Each `SigningKey` in a `KeySet` is identified by the `KeyId` of its `PublicKey` and
+//! moves through the `KeyState`s `Active` \\(\rightarrow\\) `VerifyOnly` \\(\rightarrow\\)
+//! `Retired`. New tokens are only signed with active keys, while tokens issued under
+//! either active or verify-only keys can still be redeemed.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use digest::generic_array::typenum::U32;
+use digest::Digest;
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{KeyId, PublicKey, SigningKey, TokenPreimage, UnblindedToken};
+
+/// The lifecycle state of a key within a `KeySet`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum KeyState {
+    /// The key is used to sign new tokens and to redeem them
+    Active,
+    /// The key is no longer used for signing, but tokens it issued may still be redeemed
+    VerifyOnly,
+    /// The key may not be used for signing or redemption
+    Retired,
+}
+
+impl KeyState {
+    fn can_sign(self) -> bool {
+        self == KeyState::Active
+    }
+
+    fn can_redeem(self) -> bool {
+        self != KeyState::Retired
+    }
+}
+
+#[derive(Debug)]
+struct KeySetEntry {
+    id: KeyId,
+    state: KeyState,
+    signing_key: SigningKey,
+}
+
+/// A `KeySet` holds the `SigningKey`s of an issuer, indexed by `KeyId`.
+#[derive(Debug, Default)]
+pub struct KeySet {
+    entries: Vec<KeySetEntry>,
+}
+
+fn unknown_key_error() -> TokenError {
+    TokenError(InternalError::UnknownKeyError)
+}
+
+impl KeySet {
+    /// Construct an empty `KeySet`.
+    pub fn new() -> Self {
+        KeySet {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Add a `SigningKey` in the given `KeyState`, returning its `KeyId`.
+    ///
+    /// If the key is already present its state is updated instead.
+    pub fn insert<H>(&mut self, signing_key: SigningKey, state: KeyState) -> KeyId
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let id = signing_key.public_key.key_id::<H>();
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => entry.state = state,
+            None => self.entries.push(KeySetEntry {
+                id,
+                state,
+                signing_key,
+            }),
+        }
+        id
+    }
+
+    /// Remove the key identified by `id`, returning it if it was present.
+    pub fn remove(&mut self, id: &KeyId) -> Option<SigningKey> {
+        let index = self.entries.iter().position(|entry| entry.id == *id)?;
+        Some(self.entries.remove(index).signing_key)
+    }
+
+    /// Move the key identified by `id` to a new `KeyState`.
+    pub fn set_state(&mut self, id: &KeyId, state: KeyState) -> Result<(), TokenError> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.id == *id)
+            .ok_or_else(unknown_key_error)?;
+        entry.state = state;
+        Ok(())
+    }
+
+    /// Look up the current `KeyState` of the key identified by `id`.
+    pub fn state(&self, id: &KeyId) -> Option<KeyState> {
+        self.entry(id).map(|entry| entry.state)
+    }
+
+    /// Look up the `PublicKey` identified by `id`, in any state.
+    pub fn public_key(&self, id: &KeyId) -> Option<&PublicKey> {
+        self.entry(id).map(|entry| &entry.signing_key.public_key)
+    }
+
+    /// The most recently added `Active` key, which should be used to sign new tokens.
+    pub fn active(&self) -> Option<(KeyId, &SigningKey)> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.state.can_sign())
+            .map(|entry| (entry.id, &entry.signing_key))
+    }
+
+    /// The `KeyId`s and `PublicKey`s of every key which is not `Retired`, for publication
+    /// to clients.
+    pub fn public_keys(&self) -> impl Iterator<Item = (KeyId, &PublicKey, KeyState)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.state.can_redeem())
+            .map(|entry| (entry.id, &entry.signing_key.public_key, entry.state))
+    }
+
+    /// Look up the key identified by `id` for signing new tokens.
+    ///
+    /// Returns a `TokenError` unless the key is present and `Active`.
+    pub fn signing_key(&self, id: &KeyId) -> Result<&SigningKey, TokenError> {
+        self.entry(id)
+            .filter(|entry| entry.state.can_sign())
+            .map(|entry| &entry.signing_key)
+            .ok_or_else(unknown_key_error)
+    }
+
+    /// Look up the key identified by `id` for redeeming tokens.
+    ///
+    /// Returns a `TokenError` if the key is not present or is `Retired`.
+    pub fn redemption_key(&self, id: &KeyId) -> Result<&SigningKey, TokenError> {
+        self.entry(id)
+            .filter(|entry| entry.state.can_redeem())
+            .map(|entry| &entry.signing_key)
+            .ok_or_else(unknown_key_error)
+    }
+
+    /// Rederives an `UnblindedToken` using the key identified by `id`, which should be
+    /// the key the token was issued under.
+    pub fn rederive_unblinded_token(
+        &self,
+        id: &KeyId,
+        t: &TokenPreimage,
+    ) -> Result<UnblindedToken, TokenError> {
+        Ok(self.redemption_key(id)?.rederive_unblinded_token(t))
+    }
+
+    fn entry(&self, id: &KeyId) -> Option<&KeySetEntry> {
+        self.entries.iter().find(|entry| entry.id == *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::Hmac;
+    use rand::rngs::OsRng;
+    use sha2::{Sha256, Sha512};
+
+    use super::*;
+    use crate::oprf::Token;
+
+    type HmacSha512 = Hmac<Sha512>;
+
+    #[test]
+    fn key_id_roundtrip() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let id = key.public_key.key_id::<Sha256>();
+
+        assert_eq!(KeyId::decode_base64(&id.encode_base64()).unwrap(), id);
+        assert_eq!(
+            &id.to_bytes()[..],
+            &Sha256::digest(&key.public_key.to_bytes())[..8]
+        );
+    }
+
+    #[test]
+    fn rotation() {
+        let mut rng = OsRng;
+
+        let mut keys = KeySet::new();
+        let old_id = keys.insert::<Sha256>(SigningKey::random(&mut rng), KeyState::Active);
+
+        // issue a token under the first key
+        let token = Token::random::<Sha512, _>(&mut rng);
+        let blinded_token = token.blind();
+        let (issuer_id, issuer_key) = keys.active().unwrap();
+        assert_eq!(issuer_id, old_id);
+        let signed_token = issuer_key.sign(&blinded_token).unwrap();
+        let unblinded_token = token.unblind(&signed_token).unwrap();
+        let client_sig = unblinded_token
+            .derive_verification_key::<Sha512>()
+            .sign::<HmacSha512>(b"test message");
+
+        // rotate to a new key
+        let new_id = keys.insert::<Sha256>(SigningKey::random(&mut rng), KeyState::Active);
+        keys.set_state(&old_id, KeyState::VerifyOnly).unwrap();
+        assert_eq!(keys.active().unwrap().0, new_id);
+        assert!(keys.signing_key(&old_id).is_err());
+        assert_eq!(keys.public_keys().count(), 2);
+
+        // the token still redeems against the key which issued it
+        let server_sig = keys
+            .rederive_unblinded_token(&old_id, &unblinded_token.t)
+            .unwrap()
+            .derive_verification_key::<Sha512>()
+            .sign::<HmacSha512>(b"test message");
+        assert!(client_sig == server_sig);
+
+        let wrong_sig = keys
+            .rederive_unblinded_token(&new_id, &unblinded_token.t)
+            .unwrap()
+            .derive_verification_key::<Sha512>()
+            .sign::<HmacSha512>(b"test message");
+        assert!(!(client_sig == wrong_sig));
+
+        // but not once the key is retired
+        keys.set_state(&old_id, KeyState::Retired).unwrap();
+        assert_eq!(
+            keys.rederive_unblinded_token(&old_id, &unblinded_token.t)
+                .unwrap_err(),
+            TokenError(InternalError::UnknownKeyError)
+        );
+        assert_eq!(keys.public_keys().count(), 1);
+
+        assert!(keys.remove(&old_id).is_some());
+        assert_eq!(keys.state(&old_id), None);
+    }
+}
@@ -30,12 +30,33 @@ extern crate std;
 #[macro_use]
 mod macros;
 
+mod codec;
+
 mod oprf;
 
-#[cfg(not(feature = "merlin"))]
 mod dleq;
 #[cfg(feature = "merlin")]
 mod dleq_merlin;
 
+pub mod commitment;
+pub mod dkg;
 pub mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod filter;
+pub mod keyset;
+pub mod private_metadata;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod redemption;
+pub mod rfc9497;
+pub mod rfc9578;
+pub mod schnorr;
+pub mod threshold;
+pub mod transcript;
 pub mod voprf;
+pub mod wallet;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,6 +1,8 @@
 #[cfg(any(test, feature = "base64"))]
 #[macro_export]
 /// Implement the encode_base64 / decode_base64 functions for a struct which implements to_bytes / from_bytes
+///
+/// If `from_bytes` is generic over a hash function, its parameter and bounds follow the type.
 macro_rules! impl_base64 {
     ($t:ident) => {
         impl $t {
@@ -24,6 +26,31 @@ macro_rules! impl_base64 {
             }
         }
     };
+    ($t:ident, $h:ident: $($bound:tt)+) => {
+        impl $t {
+            #[cfg(all(feature = "alloc", not(feature = "std")))]
+            /// Encode to a base64 string
+            pub fn encode_base64(&self) -> ::alloc::string::String {
+                ::base64::encode(&self.to_bytes()[..])
+            }
+
+            #[cfg(all(feature = "std"))]
+            /// Encode to a base64 string
+            pub fn encode_base64(&self) -> ::std::string::String {
+                ::base64::encode(&self.to_bytes()[..])
+            }
+
+            /// Decode from a base64 string
+            pub fn decode_base64<$h>(s: &str) -> Result<Self, TokenError>
+            where
+                $h: $($bound)+,
+            {
+                let bytes =
+                    ::base64::decode(s).or(Err(TokenError(InternalError::DecodingError)))?;
+                $t::from_bytes::<$h>(&bytes)
+            }
+        }
+    };
 }
 
 #[cfg(all(feature = "serde", not(feature = "serde_base64")))]
@@ -1,13 +1,19 @@
 use core::fmt::Debug;
 
+#[cfg(feature = "rayon")]
+use std::vec::Vec;
+
 use curve25519_dalek::constants;
 use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
 use curve25519_dalek::scalar::Scalar;
-use digest::generic_array::typenum::U64;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
+use digest::generic_array::typenum::{U32, U64};
 use digest::Digest;
 use hmac::digest::generic_array::GenericArray;
 use hmac::{Mac, NewMac};
 use rand::{CryptoRng, Rng};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use subtle::{Choice, ConstantTimeEq};
 use zeroize::Zeroize;
 
@@ -29,6 +35,53 @@ pub const SIGNED_TOKEN_LENGTH: usize = 32;
 pub const UNBLINDED_TOKEN_LENGTH: usize = 96;
 /// The length of a `VerificationSignature`, in bytes.
 pub const VERIFICATION_SIGNATURE_LENGTH: usize = 64;
+/// The length of a `KeyId`, in bytes.
+pub const KEY_ID_LENGTH: usize = 8;
+
+/// Hash public metadata to the `Scalar` used to tweak a key.
+///
+/// \\(m = H_4(info)\\)
+fn info_tweak<D>(info: &[u8]) -> Scalar
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    let mut hash = D::default();
+    hash.update(b"hash_info_tweak");
+    hash.update(info);
+    Scalar::from_hash(hash)
+}
+
+/// The number of points handled by each thread when computing a composite in parallel.
+#[cfg(feature = "rayon")]
+const COMPOSITE_CHUNK_SIZE: usize = 256;
+
+/// Decompress `points` and compute the composite \\(\\sum c_i P_i\\) for the scalars `c_m`.
+///
+/// With the `rayon` feature the points are split into chunks whose partial sums are
+/// computed on separate threads, which yields the same point as the serial computation.
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn composite<P, F>(c_m: &[Scalar], points: &[P], decompress: F) -> Option<RistrettoPoint>
+where
+    F: Fn(&P) -> Option<RistrettoPoint>,
+{
+    RistrettoPoint::optional_multiscalar_mul(c_m, points.iter().map(decompress))
+}
+
+/// Decompress `points` and compute the composite \\(\\sum c_i P_i\\) for the scalars `c_m`.
+///
+/// With the `rayon` feature the points are split into chunks whose partial sums are
+/// computed on separate threads, which yields the same point as the serial computation.
+#[cfg(feature = "rayon")]
+pub(crate) fn composite<P, F>(c_m: &[Scalar], points: &[P], decompress: F) -> Option<RistrettoPoint>
+where
+    P: Sync,
+    F: Fn(&P) -> Option<RistrettoPoint> + Sync,
+{
+    c_m.par_chunks(COMPOSITE_CHUNK_SIZE)
+        .zip(points.par_chunks(COMPOSITE_CHUNK_SIZE))
+        .map(|(c, p)| RistrettoPoint::optional_multiscalar_mul(c, p.iter().map(&decompress)))
+        .try_reduce(RistrettoPoint::default, |a, b| Some(a + b))
+}
 
 /// A `TokenPreimage` is a slice of bytes which can be hashed to a `RistrettoPoint`.
 ///
@@ -96,7 +149,7 @@ pub struct Token {
     /// `t` is a `TokenPreimage`
     pub(crate) t: TokenPreimage,
     /// `r` is a `Scalar` which is the blinding factor
-    r: Scalar,
+    pub(crate) r: Scalar,
 }
 
 /// Overwrite the token blinding factor with null when it goes out of scope.
@@ -287,6 +340,81 @@ impl PublicKey {
 
         Ok(PublicKey(CompressedRistretto(bits)))
     }
+
+    /// Derive the `KeyId` identifying this `PublicKey`.
+    ///
+    /// As in Privacy Pass, this is a digest of the encoded key, here truncated to
+    /// `KEY_ID_LENGTH` bytes.
+    pub fn key_id<H>(&self) -> KeyId
+    where
+        H: Digest<OutputSize = U32> + Default,
+    {
+        let mut h = H::default();
+        h.update(self.to_bytes());
+
+        let mut id = [0u8; KEY_ID_LENGTH];
+        id.copy_from_slice(&h.finalize()[..KEY_ID_LENGTH]);
+        KeyId(id)
+    }
+
+    /// Derive the tweaked `PublicKey` for tokens signed with the public metadata `info`.
+    ///
+    /// \\(Y' = Y X^{H_4(info)}\\)
+    ///
+    /// Returns a `TokenError` if the tweaked key is the identity.
+    #[allow(non_snake_case)]
+    pub fn tweak<D>(&self, info: &[u8]) -> Result<PublicKey, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let Y = self
+            .0
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let m = info_tweak::<D>(info);
+        let Y = Y + &m * &constants::RISTRETTO_BASEPOINT_TABLE;
+        if Y.is_identity() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(PublicKey(Y.compress()))
+    }
+}
+
+/// A `KeyId` identifies a particular `PublicKey`, and so the `SigningKey` it commits to.
+///
+/// It is sent alongside tokens so the server can select the right key without trying each.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct KeyId([u8; KEY_ID_LENGTH]);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(KeyId);
+
+#[cfg(feature = "serde")]
+impl_serde!(KeyId);
+
+impl KeyId {
+    /// Convert this `KeyId` to a byte array.
+    pub fn to_bytes(&self) -> [u8; KEY_ID_LENGTH] {
+        self.0
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "KeyId",
+            length: KEY_ID_LENGTH,
+        })
+    }
+
+    /// Construct a `KeyId` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<KeyId, TokenError> {
+        if bytes.len() != KEY_ID_LENGTH {
+            return Err(KeyId::bytes_length_error());
+        }
+
+        let mut bits: [u8; KEY_ID_LENGTH] = [0u8; KEY_ID_LENGTH];
+        bits.copy_from_slice(bytes);
+        Ok(KeyId(bits))
+    }
 }
 
 /// A `SigningKey` is used to sign a `BlindedToken` and verify an `UnblindedToken`.
@@ -339,6 +467,17 @@ impl SigningKey {
         ))
     }
 
+    /// Signs each of the provided `BlindedToken`s, spreading the work across threads.
+    ///
+    /// Returns a `TokenError` if any `BlindedToken` point is not valid.
+    #[cfg(feature = "rayon")]
+    pub fn sign_batch(
+        &self,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<Vec<SignedToken>, TokenError> {
+        blinded_tokens.par_iter().map(|P| self.sign(P)).collect()
+    }
+
     /// Rederives an `UnblindedToken` via the token preimage of the provided `UnblindedToken`
     ///
     /// W' = T^k = H_1(t)^k
@@ -349,6 +488,71 @@ impl SigningKey {
         }
     }
 
+    /// Derive the tweaked key for the public metadata `info`.
+    ///
+    /// \\(k' = k + H_4(info)\\)
+    ///
+    /// Returns a `TokenError` if the tweaked key is zero, since it could not be inverted.
+    pub(crate) fn tweak<D>(&self, info: &[u8]) -> Result<SigningKey, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let k = self.k + info_tweak::<D>(info);
+        if k == Scalar::zero() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let Y = &k * &constants::RISTRETTO_BASEPOINT_TABLE;
+        Ok(SigningKey {
+            public_key: PublicKey(Y.compress()),
+            k,
+        })
+    }
+
+    /// Signs the provided `BlindedToken`, binding it to the public metadata `info`
+    ///
+    /// The token is evaluated with the inverse of the tweaked key, so that tokens signed
+    /// for one `info` cannot be transformed into tokens for another.
+    ///
+    /// \\(Q = P^{1/k'}\\)
+    ///
+    /// Returns a `TokenError` if the `BlindedToken` point is not valid, or if the tweaked key
+    /// is zero.
+    pub fn sign_with_info<D>(
+        &self,
+        P: &BlindedToken,
+        info: &[u8],
+    ) -> Result<SignedToken, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        Ok(SignedToken(
+            (self.tweak::<D>(info)?.k.invert()
+                * P.0
+                    .decompress()
+                    .ok_or(TokenError(InternalError::PointDecompressionError))?)
+            .compress(),
+        ))
+    }
+
+    /// Rederives an `UnblindedToken` which was signed with the public metadata `info`
+    ///
+    /// W' = T^{1/k'} = H_1(t)^{1/k'}
+    ///
+    /// Returns a `TokenError` if the tweaked key is zero.
+    pub fn rederive_unblinded_token_with_info<D>(
+        &self,
+        t: &TokenPreimage,
+        info: &[u8],
+    ) -> Result<UnblindedToken, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        Ok(UnblindedToken {
+            t: *t,
+            W: (self.tweak::<D>(info)?.k.invert() * t.T()).compress(),
+        })
+    }
+
     /// Convert this `SigningKey` to a byte array.
     pub fn to_bytes(&self) -> [u8; SIGNING_KEY_LENGTH] {
         self.k.to_bytes()
@@ -432,7 +636,15 @@ pub struct UnblindedToken {
     /// `W` is the unblinded signed `CompressedRistretto` point
     ///
     /// \\(W = Q^{1/r} = P^{k(1/r)} = T^{rk(1/r)} = T^k\\)
-    W: CompressedRistretto,
+    pub(crate) W: CompressedRistretto,
+}
+
+/// Overwrite the unblinded token with null when it goes out of scope.
+impl Drop for UnblindedToken {
+    fn drop(&mut self) {
+        self.t.0.zeroize();
+        self.W.zeroize();
+    }
 }
 
 #[cfg(any(test, feature = "base64"))]
@@ -460,6 +672,25 @@ impl UnblindedToken {
         VerificationKey(output_bytes)
     }
 
+    /// Derive the `VerificationKey` for an `UnblindedToken` signed with the public metadata `info`
+    pub fn derive_verification_key_with_info<D>(&self, info: &[u8]) -> VerificationKey
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut hash = D::default();
+        hash.update(b"hash_derive_key_with_info");
+
+        hash.update(self.t.0.as_ref());
+        hash.update(self.W.as_bytes());
+        hash.update(info);
+
+        let output = hash.finalize();
+        let mut output_bytes = [0u8; 64];
+        output_bytes.copy_from_slice(&output);
+
+        VerificationKey(output_bytes)
+    }
+
     /// Convert this `UnblindedToken` to a byte array.
     pub fn to_bytes(&self) -> [u8; UNBLINDED_TOKEN_LENGTH] {
         let mut unblinded_token_bytes: [u8; UNBLINDED_TOKEN_LENGTH] = [0u8; UNBLINDED_TOKEN_LENGTH];
@@ -548,11 +779,12 @@ impl PartialEq for VerificationSignature {
     }
 }
 
-#[cfg(any(test, feature = "serde", feature = "base64"))]
 impl VerificationSignature {
     /// Convert this `VerificationSignature` to a byte array.
-    /// We intentionally keep this private to avoid accidental non constant time comparisons
-    fn to_bytes(&self) -> [u8; VERIFICATION_SIGNATURE_LENGTH] {
+    /// This is kept out of the public Rust API to avoid accidental non constant time
+    /// comparisons. It is exposed over FFI as `cbr_verification_signature_to_bytes`, and
+    /// encodes the authenticator of an `rfc9578::Token`.
+    pub(crate) fn to_bytes(&self) -> [u8; VERIFICATION_SIGNATURE_LENGTH] {
         let mut bytes: [u8; VERIFICATION_SIGNATURE_LENGTH] = [0u8; VERIFICATION_SIGNATURE_LENGTH];
         bytes.copy_from_slice(self.0.as_slice());
         bytes
@@ -566,7 +798,7 @@ impl VerificationSignature {
     }
 
     /// Construct a `VerificationSignature` from a slice of bytes.
-    fn from_bytes(bytes: &[u8]) -> Result<VerificationSignature, TokenError> {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<VerificationSignature, TokenError> {
         if bytes.len() != VERIFICATION_SIGNATURE_LENGTH {
             return Err(VerificationSignature::bytes_length_error());
         }
@@ -682,4 +914,119 @@ mod tests {
         let server_sig_fail = server_verification_key.sign::<HmacSha512>(b"failing test message");
         assert!(!(client_sig == server_sig_fail));
     }
+
+    #[test]
+    fn works_with_info() {
+        let mut rng = OsRng;
+
+        let server_key = SigningKey::random(&mut rng);
+        let info = b"epoch=42";
+
+        let token = Token::random::<Sha512, _>(&mut rng);
+        let blinded_token = token.blind();
+
+        // server signs the blinded token under the tweaked key for this info
+        let signed_token = server_key
+            .sign_with_info::<Sha512>(&blinded_token, info)
+            .unwrap();
+        let unblinded_token = token.unblind(&signed_token).unwrap();
+
+        let client_sig = unblinded_token
+            .derive_verification_key_with_info::<Sha512>(info)
+            .sign::<HmacSha512>(b"test message");
+
+        let server_sig = server_key
+            .rederive_unblinded_token_with_info::<Sha512>(&unblinded_token.t, info)
+            .unwrap()
+            .derive_verification_key_with_info::<Sha512>(info)
+            .sign::<HmacSha512>(b"test message");
+        assert!(client_sig == server_sig);
+
+        // the token is not valid for other metadata or without any
+        let other_sig = server_key
+            .rederive_unblinded_token_with_info::<Sha512>(&unblinded_token.t, b"epoch=43")
+            .unwrap()
+            .derive_verification_key_with_info::<Sha512>(b"epoch=43")
+            .sign::<HmacSha512>(b"test message");
+        assert!(!(client_sig == other_sig));
+
+        let untweaked_sig = server_key
+            .rederive_unblinded_token(&unblinded_token.t)
+            .derive_verification_key::<Sha512>()
+            .sign::<HmacSha512>(b"test message");
+        assert!(!(client_sig == untweaked_sig));
+
+        // the tweaked public key can be derived without the signing key
+        assert_eq!(
+            server_key
+                .public_key
+                .tweak::<Sha512>(info)
+                .unwrap()
+                .to_bytes(),
+            server_key
+                .tweak::<Sha512>(info)
+                .unwrap()
+                .public_key
+                .to_bytes()
+        );
+    }
+
+    #[test]
+    fn rejects_zero_tweaked_key() {
+        let info = b"epoch=42";
+
+        // a key which the tweak for `info` cancels out
+        let server_key = SigningKey::from_bytes(&(-info_tweak::<Sha512>(info)).to_bytes()).unwrap();
+        let blinded_token = Token::random::<Sha512, _>(&mut OsRng).blind();
+
+        assert_eq!(
+            server_key
+                .sign_with_info::<Sha512>(&blinded_token, info)
+                .unwrap_err(),
+            TokenError(InternalError::InvalidInputError)
+        );
+        assert!(server_key
+            .rederive_unblinded_token_with_info::<Sha512>(&TokenPreimage([0u8; 64]), info)
+            .is_err());
+        assert!(server_key.public_key.tweak::<Sha512>(info).is_err());
+
+        assert!(server_key
+            .sign_with_info::<Sha512>(&blinded_token, b"epoch=43")
+            .is_ok());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[allow(non_snake_case)]
+    #[test]
+    fn sign_batch_matches_serial() {
+        let mut rng = OsRng;
+
+        let server_key = SigningKey::random(&mut rng);
+        let tokens: Vec<Token> = (0..1000)
+            .map(|_| Token::random::<Sha512, _>(&mut rng))
+            .collect();
+        let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(Token::blind).collect();
+
+        let signed_tokens = server_key.sign_batch(&blinded_tokens).unwrap();
+        for (P, Q) in blinded_tokens.iter().zip(signed_tokens.iter()) {
+            assert_eq!(server_key.sign(P).unwrap().to_bytes(), Q.to_bytes());
+        }
+
+        // the chunked composite is the same point as the serial multiscalar multiplication
+        let c_m: Vec<Scalar> = (0..blinded_tokens.len())
+            .map(|_| Scalar::random(&mut rng))
+            .collect();
+        let serial = RistrettoPoint::optional_multiscalar_mul(
+            &c_m,
+            blinded_tokens.iter().map(|P| P.0.decompress()),
+        )
+        .unwrap();
+        let parallel = composite(&c_m, &blinded_tokens, |P| P.0.decompress()).unwrap();
+        assert_eq!(serial.compress(), parallel.compress());
+
+        let mut invalid = blinded_tokens.clone();
+        invalid[500] = BlindedToken::from_bytes(&[0xffu8; 32]).unwrap();
+        assert!(server_key.sign_batch(&invalid).is_err());
+        assert!(composite(&c_m, &invalid, |P| P.0.decompress()).is_none());
+    }
 }
@@ -0,0 +1,901 @@
+//! Issuance with a private metadata bit, following PMBTokens from [Anonymous Tokens with
+//! Private Metadata Bit](https://eprint.iacr.org/2020/072).
+//!
+//! The issuer holds a `MetadataSigningKey` made up of two key pairs \\((x_b, y_b)\\), one for
+//! each value of a metadata bit \\(b\\), for example to mark a suspicious client. Each is
+//! committed to in the `MetadataPublicKey` as \\(X_b = x_b G + y_b H\\), where \\(H\\) is a
+//! second generator whose discrete log with respect to \\(G\\) is unknown.
+//!
+//! For each `BlindedToken` \\(T'\\) the issuer draws a fresh nonce \\(s\\), derives the point
+//! \\(S' = H_s(T', s)\\) and returns the `MetadataSignedToken` \\((s, W')\\), where
+//! \\(W' = x_b T' + y_b S'\\). In place of a `BatchDLEQProof` it returns a `DLEQOrProof`,
+//! which shows that every \\(W'\\) was formed with one of the two key pairs of the
+//! `MetadataPublicKey` without revealing which.
+//!
+//! The client unblinds both \\(S'\\) and \\(W'\\) into a `MetadataUnblindedToken`, and
+//! redeems it by sending the `TokenPreimage` and `MetadataNonce` \\(S\\) together with a
+//! `VerificationSignature` from its `UnblindedToken` as usual. At redemption the issuer
+//! recomputes \\(W = x_b T + y_b S\\) under both key pairs with
+//! `MetadataSigningKey::rederive_unblinded_token`, and learns the bit from the one that
+//! verifies the signature.
+//!
+//! Since \\(X_b\\) has many openings and each \\(S'\\) is fresh, \\(W'\\) is independent of
+//! the bit from the client's point of view. Unlike signing with a single key per bit, a
+//! client cannot learn the bit by resubmitting a re-randomized `BlindedToken` from an
+//! earlier issuance, as the \\(y_b S'\\) term differs between the two responses.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use curve25519_dalek::constants;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+use hmac::{Mac, NewMac};
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::*;
+use crate::transcript::{DigestTranscript, ProofTranscript};
+
+/// The length of a `MetadataPublicKey`, in bytes.
+pub const METADATA_PUBLIC_KEY_LENGTH: usize = 64;
+/// The length of a `MetadataSigningKey`, in bytes.
+pub const METADATA_SIGNING_KEY_LENGTH: usize = 128;
+/// The length of the nonce \\(s\\) of a `MetadataSignedToken`, in bytes.
+pub const METADATA_NONCE_SEED_LENGTH: usize = 32;
+/// The length of a `MetadataSignedToken`, in bytes.
+pub const METADATA_SIGNED_TOKEN_LENGTH: usize = METADATA_NONCE_SEED_LENGTH + 32;
+/// The length of a `MetadataNonce`, in bytes.
+pub const METADATA_NONCE_LENGTH: usize = 32;
+/// The length of a `MetadataUnblindedToken`, in bytes.
+pub const METADATA_UNBLINDED_TOKEN_LENGTH: usize = UNBLINDED_TOKEN_LENGTH + METADATA_NONCE_LENGTH;
+/// The length of a `DLEQOrProof`, in bytes.
+pub const DLEQ_OR_PROOF_LENGTH: usize = 192;
+
+/// The uniform bytes from which the generator \\(H\\) is derived, the SHA-512 hash of
+/// `"challenge-bypass-ristretto private metadata generator"`.
+const GENERATOR_H_BYTES: [u8; 64] = [
+    0x09, 0xc6, 0x5e, 0x19, 0x74, 0x2b, 0x20, 0xaf, 0x3e, 0x5c, 0xd6, 0x53, 0x18, 0x7a, 0xcc, 0x8f,
+    0x23, 0x76, 0xed, 0x1f, 0x90, 0x99, 0x29, 0x6f, 0x72, 0x3a, 0x67, 0xfa, 0xa3, 0xa9, 0xec, 0x99,
+    0x66, 0xc8, 0x72, 0x5d, 0xcf, 0xcf, 0xa3, 0x5b, 0x50, 0xfc, 0x00, 0xa8, 0x04, 0xe4, 0x6c, 0x3b,
+    0x95, 0xe2, 0xe2, 0xad, 0xff, 0x80, 0x0b, 0x5d, 0x9d, 0x6d, 0x76, 0x54, 0xee, 0xdb, 0x41, 0xdd,
+];
+
+/// The second generator \\(H\\), whose discrete log with respect to \\(G\\) is unknown.
+#[allow(non_snake_case)]
+fn generator_H() -> RistrettoPoint {
+    RistrettoPoint::from_uniform_bytes(&GENERATOR_H_BYTES)
+}
+
+/// The pair of commitments \\(X_b = x_b G + y_b H\\) to the key pairs of a
+/// `MetadataSigningKey`.
+#[derive(Copy, Clone, Debug)]
+pub struct MetadataPublicKey([CompressedRistretto; 2]);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(MetadataPublicKey);
+
+#[cfg(feature = "serde")]
+impl_serde!(MetadataPublicKey);
+
+impl MetadataPublicKey {
+    /// Convert this `MetadataPublicKey` to a byte array.
+    pub fn to_bytes(&self) -> [u8; METADATA_PUBLIC_KEY_LENGTH] {
+        let mut bytes: [u8; METADATA_PUBLIC_KEY_LENGTH] = [0u8; METADATA_PUBLIC_KEY_LENGTH];
+        bytes[..32].copy_from_slice(self.0[0].as_bytes());
+        bytes[32..].copy_from_slice(self.0[1].as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "MetadataPublicKey",
+            length: METADATA_PUBLIC_KEY_LENGTH,
+        })
+    }
+
+    /// Construct a `MetadataPublicKey` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetadataPublicKey, TokenError> {
+        if bytes.len() != METADATA_PUBLIC_KEY_LENGTH {
+            return Err(MetadataPublicKey::bytes_length_error());
+        }
+
+        let mut public_key = MetadataPublicKey([CompressedRistretto::default(); 2]);
+        for (point, chunk) in public_key.0.iter_mut().zip(bytes.chunks(32)) {
+            *point = CompressedRistretto::from_slice(chunk);
+            point
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        }
+        Ok(public_key)
+    }
+}
+
+/// A pair of key pairs \\((x_b, y_b)\\), one for each value of the private metadata bit.
+///
+/// This is a server secret and should NEVER be revealed to the client.
+#[derive(Debug)]
+pub struct MetadataSigningKey {
+    /// The `MetadataPublicKey` committing to the key pairs
+    public_key: MetadataPublicKey,
+    /// `x` is the key applied to the `BlindedToken` for each bit
+    x: [Scalar; 2],
+    /// `y` is the key applied to the nonce point for each bit
+    y: [Scalar; 2],
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(MetadataSigningKey);
+
+#[cfg(feature = "serde")]
+impl_serde!(MetadataSigningKey);
+
+/// Overwrite the key pairs with null when they go out of scope.
+impl Drop for MetadataSigningKey {
+    fn drop(&mut self) {
+        self.x.zeroize();
+        self.y.zeroize();
+    }
+}
+
+#[allow(non_snake_case)]
+impl MetadataSigningKey {
+    fn from_scalars(x: [Scalar; 2], y: [Scalar; 2]) -> Self {
+        let H = generator_H();
+        let commit =
+            |b: usize| (&x[b] * &constants::RISTRETTO_BASEPOINT_TABLE + y[b] * H).compress();
+        MetadataSigningKey {
+            public_key: MetadataPublicKey([commit(0), commit(1)]),
+            x,
+            y,
+        }
+    }
+
+    /// Generates a new random `MetadataSigningKey` using the provided random number generator.
+    pub fn random<T: Rng + CryptoRng>(rng: &mut T) -> Self {
+        let x = [Scalar::random(rng), Scalar::random(rng)];
+        let y = [Scalar::random(rng), Scalar::random(rng)];
+        MetadataSigningKey::from_scalars(x, y)
+    }
+
+    /// The `MetadataPublicKey` against which issuance is verified.
+    pub fn public_key(&self) -> MetadataPublicKey {
+        self.public_key
+    }
+
+    /// Sign each of the provided `BlindedToken`s with the key pair selected by `bit`, and
+    /// construct a `DLEQOrProof` over them.
+    ///
+    /// A fresh nonce is drawn from `rng` for each token, so signing the same `BlindedToken`
+    /// twice yields unrelated `MetadataSignedToken`s.
+    pub fn issue<D, T>(
+        &self,
+        rng: &mut T,
+        bit: bool,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<(Vec<MetadataSignedToken>, DLEQOrProof), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let b = bit as usize;
+        let P: Vec<RistrettoPoint> = blinded_tokens
+            .iter()
+            .map(|Pi| {
+                Pi.0.decompress()
+                    .ok_or(TokenError(InternalError::PointDecompressionError))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut S = Vec::with_capacity(P.len());
+        let mut signed_tokens = Vec::with_capacity(P.len());
+        for (Pi, blinded_token) in P.iter().zip(blinded_tokens.iter()) {
+            let mut s = [0u8; METADATA_NONCE_SEED_LENGTH];
+            rng.fill_bytes(&mut s);
+            let Si = nonce_point::<D>(blinded_token, &s);
+            signed_tokens.push(MetadataSignedToken {
+                s,
+                W: (self.x[b] * Pi + self.y[b] * Si).compress(),
+            });
+            S.push(Si);
+        }
+
+        let c_m = composite_scalars::<D>(&self.public_key, blinded_tokens, &S, &signed_tokens)?;
+        let M_T = composite(&c_m, &P, |Pi| Some(*Pi))
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let M_S = composite(&c_m, &S, |Si| Some(*Si))
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let M_W = self.x[b] * M_T + self.y[b] * M_S;
+
+        let proof = DLEQOrProof::_new::<D, T>(rng, [M_T, M_S, M_W], self, bit);
+        Ok((signed_tokens, proof))
+    }
+
+    /// Rederives the `UnblindedToken` for the token preimage `t` and the client's
+    /// `MetadataNonce` under both key pairs, and checks the client's `VerificationSignature`
+    /// over `message` against each.
+    ///
+    /// Returns the `UnblindedToken` together with the private metadata bit it was issued
+    /// with, or a `TokenError` if the signature verifies under neither key pair.
+    pub fn rederive_unblinded_token<D, M>(
+        &self,
+        t: &TokenPreimage,
+        nonce: &MetadataNonce,
+        signature: &VerificationSignature,
+        message: &[u8],
+    ) -> Result<(UnblindedToken, bool), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        M: Mac<OutputSize = U64> + NewMac,
+    {
+        let T = t.T();
+        let S = nonce
+            .0
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let rederive = |b: usize| UnblindedToken {
+            t: *t,
+            W: (self.x[b] * T + self.y[b] * S).compress(),
+        };
+
+        let [unblinded_token_0, unblinded_token_1] = [rederive(0), rederive(1)];
+        let verified_0 = unblinded_token_0
+            .derive_verification_key::<D>()
+            .verify::<M>(signature, message);
+        let verified_1 = unblinded_token_1
+            .derive_verification_key::<D>()
+            .verify::<M>(signature, message);
+
+        match (verified_0, verified_1) {
+            (true, false) => Ok((unblinded_token_0, false)),
+            (false, true) => Ok((unblinded_token_1, true)),
+            _ => Err(TokenError(InternalError::VerifyError)),
+        }
+    }
+
+    /// Convert this `MetadataSigningKey` to a byte array.
+    pub fn to_bytes(&self) -> [u8; METADATA_SIGNING_KEY_LENGTH] {
+        let mut bytes: [u8; METADATA_SIGNING_KEY_LENGTH] = [0u8; METADATA_SIGNING_KEY_LENGTH];
+        for (chunk, scalar) in bytes
+            .chunks_mut(32)
+            .zip([self.x[0], self.y[0], self.x[1], self.y[1]].iter())
+        {
+            chunk.copy_from_slice(scalar.as_bytes());
+        }
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "MetadataSigningKey",
+            length: METADATA_SIGNING_KEY_LENGTH,
+        })
+    }
+
+    /// Construct a `MetadataSigningKey` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetadataSigningKey, TokenError> {
+        if bytes.len() != METADATA_SIGNING_KEY_LENGTH {
+            return Err(MetadataSigningKey::bytes_length_error());
+        }
+
+        let mut scalars = [Scalar::zero(); 4];
+        for (scalar, chunk) in scalars.iter_mut().zip(bytes.chunks(32)) {
+            let mut bits: [u8; 32] = [0u8; 32];
+            bits.copy_from_slice(chunk);
+            *scalar = Scalar::from_canonical_bytes(bits)
+                .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        }
+
+        let key =
+            MetadataSigningKey::from_scalars([scalars[0], scalars[2]], [scalars[1], scalars[3]]);
+        scalars.zeroize();
+        Ok(key)
+    }
+}
+
+/// Derive the nonce point \\(S' = H_s(T', s)\\) for the `BlindedToken` \\(T'\\) and the
+/// nonce \\(s\\).
+fn nonce_point<D>(
+    blinded_token: &BlindedToken,
+    s: &[u8; METADATA_NONCE_SEED_LENGTH],
+) -> RistrettoPoint
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    RistrettoPoint::from_hash(
+        D::default()
+            .chain(b"hash_metadata_nonce")
+            .chain(blinded_token.0.as_bytes())
+            .chain(s),
+    )
+}
+
+/// Compute the weights of the composites \\(M_T, M_S, M_W\\) over the tokens of a batch.
+#[allow(non_snake_case)]
+fn composite_scalars<D>(
+    public_key: &MetadataPublicKey,
+    blinded_tokens: &[BlindedToken],
+    S: &[RistrettoPoint],
+    signed_tokens: &[MetadataSignedToken],
+) -> Result<Vec<Scalar>, TokenError>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    if blinded_tokens.len() != signed_tokens.len() {
+        return Err(TokenError(InternalError::LengthMismatchError));
+    }
+
+    let mut transcript = DigestTranscript::<D>::new();
+    transcript.append_point(b"G", &constants::RISTRETTO_BASEPOINT_COMPRESSED);
+    transcript.append_point(b"H", &generator_H().compress());
+    transcript.append_point(b"X0", &public_key.0[0]);
+    transcript.append_point(b"X1", &public_key.0[1]);
+
+    for ((Ti, Si), Wi) in blinded_tokens
+        .iter()
+        .zip(S.iter())
+        .zip(signed_tokens.iter())
+    {
+        transcript.append_point(b"Ti", &Ti.0);
+        transcript.append_point(b"Si", &Si.compress());
+        transcript.append_point(b"Wi", &Wi.W);
+    }
+
+    Ok(transcript.challenge_scalars(b"c_i", blinded_tokens.len()))
+}
+
+/// A `MetadataSignedToken` is the result of signing a `BlindedToken` with a
+/// `MetadataSigningKey`.
+///
+/// \\(W' = x_b T' + y_b S'\\), where \\(S' = H_s(T', s)\\)
+#[allow(non_snake_case)]
+#[derive(Copy, Clone, Debug)]
+pub struct MetadataSignedToken {
+    /// `s` is the nonce chosen by the issuer for this token
+    s: [u8; METADATA_NONCE_SEED_LENGTH],
+    /// `W` is the signed `CompressedRistretto` point
+    W: CompressedRistretto,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(MetadataSignedToken);
+
+#[cfg(feature = "serde")]
+impl_serde!(MetadataSignedToken);
+
+impl MetadataSignedToken {
+    /// Convert this `MetadataSignedToken` to a byte array.
+    pub fn to_bytes(&self) -> [u8; METADATA_SIGNED_TOKEN_LENGTH] {
+        let mut bytes: [u8; METADATA_SIGNED_TOKEN_LENGTH] = [0u8; METADATA_SIGNED_TOKEN_LENGTH];
+        bytes[..METADATA_NONCE_SEED_LENGTH].copy_from_slice(&self.s);
+        bytes[METADATA_NONCE_SEED_LENGTH..].copy_from_slice(self.W.as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "MetadataSignedToken",
+            length: METADATA_SIGNED_TOKEN_LENGTH,
+        })
+    }
+
+    /// Construct a `MetadataSignedToken` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetadataSignedToken, TokenError> {
+        if bytes.len() != METADATA_SIGNED_TOKEN_LENGTH {
+            return Err(MetadataSignedToken::bytes_length_error());
+        }
+
+        let mut s = [0u8; METADATA_NONCE_SEED_LENGTH];
+        s.copy_from_slice(&bytes[..METADATA_NONCE_SEED_LENGTH]);
+        Ok(MetadataSignedToken {
+            s,
+            W: CompressedRistretto::from_slice(&bytes[METADATA_NONCE_SEED_LENGTH..]),
+        })
+    }
+}
+
+/// A `MetadataNonce` is the unblinded nonce point \\(S = S'^{1/r}\\) of a
+/// `MetadataUnblindedToken`, which the client sends along with the `TokenPreimage` on
+/// redemption.
+#[derive(Copy, Clone, Debug)]
+pub struct MetadataNonce(CompressedRistretto);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(MetadataNonce);
+
+#[cfg(feature = "serde")]
+impl_serde!(MetadataNonce);
+
+impl MetadataNonce {
+    /// Convert this `MetadataNonce` to a byte array.
+    pub fn to_bytes(&self) -> [u8; METADATA_NONCE_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "MetadataNonce",
+            length: METADATA_NONCE_LENGTH,
+        })
+    }
+
+    /// Construct a `MetadataNonce` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetadataNonce, TokenError> {
+        if bytes.len() != METADATA_NONCE_LENGTH {
+            return Err(MetadataNonce::bytes_length_error());
+        }
+
+        Ok(MetadataNonce(CompressedRistretto::from_slice(bytes)))
+    }
+}
+
+/// A `MetadataUnblindedToken` is the result of unblinding a `MetadataSignedToken`.
+///
+/// \\(W = W'^{1/r} = x_b T + y_b S\\)
+#[derive(Debug)]
+pub struct MetadataUnblindedToken {
+    /// The `UnblindedToken` \\((t, W)\\), from which the `VerificationKey` is derived
+    pub unblinded_token: UnblindedToken,
+    /// The `MetadataNonce` \\(S\\), which is sent with \\(t\\) on redemption
+    pub nonce: MetadataNonce,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(MetadataUnblindedToken);
+
+#[cfg(feature = "serde")]
+impl_serde!(MetadataUnblindedToken);
+
+impl MetadataUnblindedToken {
+    /// Convert this `MetadataUnblindedToken` to a byte array.
+    pub fn to_bytes(&self) -> [u8; METADATA_UNBLINDED_TOKEN_LENGTH] {
+        let mut bytes: [u8; METADATA_UNBLINDED_TOKEN_LENGTH] =
+            [0u8; METADATA_UNBLINDED_TOKEN_LENGTH];
+        bytes[..UNBLINDED_TOKEN_LENGTH].copy_from_slice(&self.unblinded_token.to_bytes());
+        bytes[UNBLINDED_TOKEN_LENGTH..].copy_from_slice(&self.nonce.to_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "MetadataUnblindedToken",
+            length: METADATA_UNBLINDED_TOKEN_LENGTH,
+        })
+    }
+
+    /// Construct a `MetadataUnblindedToken` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MetadataUnblindedToken, TokenError> {
+        if bytes.len() != METADATA_UNBLINDED_TOKEN_LENGTH {
+            return Err(MetadataUnblindedToken::bytes_length_error());
+        }
+
+        Ok(MetadataUnblindedToken {
+            unblinded_token: UnblindedToken::from_bytes(&bytes[..UNBLINDED_TOKEN_LENGTH])?,
+            nonce: MetadataNonce::from_bytes(&bytes[UNBLINDED_TOKEN_LENGTH..])?,
+        })
+    }
+}
+
+/// A `DLEQOrProof` is a proof that a batch of `MetadataSignedToken`s was signed under one
+/// of the two key pairs of a `MetadataPublicKey`, without revealing which.
+///
+/// Over the composites \\(M_T, M_S, M_W\\) of the batch, it is a disjunction of proofs of
+/// knowledge of \\((x, y)\\) such that \\(X_b = xG + yH\\) and \\(M_W = xM_T + yM_S\\), in
+/// which the branch for the key pair that was not used is simulated.
+#[allow(non_snake_case)]
+#[derive(Debug)]
+pub struct DLEQOrProof {
+    /// `c` is a `Scalar` for each branch, where
+    /// \\(c_0 + c_1 = H_3(G,H,X_0,X_1,M_T,M_S,M_W,K_0,L_0,K_1,L_1)\\)
+    c: [Scalar; 2],
+    /// `s_x` is a `Scalar` for each branch, where
+    /// \\(K_b = s_x G + s_y H + cX_b\\) and \\(L_b = s_x M_T + s_y M_S + cM_W\\)
+    s_x: [Scalar; 2],
+    /// `s_y` is a `Scalar` for each branch
+    s_y: [Scalar; 2],
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(DLEQOrProof);
+
+#[cfg(feature = "serde")]
+impl_serde!(DLEQOrProof);
+
+#[allow(non_snake_case)]
+impl DLEQOrProof {
+    /// Construct a new `DLEQOrProof` that \\(M_W = x_b M_T + y_b M_S\\) for the key pair of
+    /// `signing_key` selected by `bit`.
+    fn _new<D, T>(
+        rng: &mut T,
+        M: [RistrettoPoint; 3],
+        signing_key: &MetadataSigningKey,
+        bit: bool,
+    ) -> Self
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let [M_T, M_S, M_W] = M;
+        let H = generator_H();
+        let real = bit as usize;
+        let simulated = 1 - real;
+
+        let mut c = [Scalar::zero(); 2];
+        let mut s_x = [Scalar::zero(); 2];
+        let mut s_y = [Scalar::zero(); 2];
+        let mut K = [RistrettoPoint::default(); 2];
+        let mut L = [RistrettoPoint::default(); 2];
+
+        c[simulated] = Scalar::random(rng);
+        s_x[simulated] = Scalar::random(rng);
+        s_y[simulated] = Scalar::random(rng);
+        let X = signing_key.public_key.0[simulated]
+            .decompress()
+            .expect("public key of a signing key is valid");
+        K[simulated] = &s_x[simulated] * &constants::RISTRETTO_BASEPOINT_TABLE
+            + s_y[simulated] * H
+            + c[simulated] * X;
+        L[simulated] = s_x[simulated] * M_T + s_y[simulated] * M_S + c[simulated] * M_W;
+
+        let t_x = Scalar::random(rng);
+        let t_y = Scalar::random(rng);
+        K[real] = &t_x * &constants::RISTRETTO_BASEPOINT_TABLE + t_y * H;
+        L[real] = t_x * M_T + t_y * M_S;
+
+        let challenge = DLEQOrProof::challenge::<D>(&signing_key.public_key, &M, &K, &L);
+        c[real] = challenge - c[simulated];
+        s_x[real] = t_x - c[real] * signing_key.x[real];
+        s_y[real] = t_y - c[real] * signing_key.y[real];
+
+        DLEQOrProof { c, s_x, s_y }
+    }
+
+    /// Compute the challenge \\(H_3(G,H,X_0,X_1,M_T,M_S,M_W,K_0,L_0,K_1,L_1)\\)
+    fn challenge<D>(
+        public_key: &MetadataPublicKey,
+        M: &[RistrettoPoint; 3],
+        K: &[RistrettoPoint; 2],
+        L: &[RistrettoPoint; 2],
+    ) -> Scalar
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut h = D::default();
+
+        h.update(constants::RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
+        h.update(generator_H().compress().as_bytes());
+        h.update(public_key.0[0].as_bytes());
+        h.update(public_key.0[1].as_bytes());
+        for Mi in M.iter() {
+            h.update(Mi.compress().as_bytes());
+        }
+        for (Ki, Li) in K.iter().zip(L.iter()) {
+            h.update(Ki.compress().as_bytes());
+            h.update(Li.compress().as_bytes());
+        }
+
+        Scalar::from_hash(h)
+    }
+
+    /// Compute the composites \\(M_T, M_S, M_W\\) of a batch, returning them along with the
+    /// nonce points \\(S'\\).
+    fn composites<D>(
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[MetadataSignedToken],
+        public_key: &MetadataPublicKey,
+    ) -> Result<([RistrettoPoint; 3], Vec<RistrettoPoint>), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        if blinded_tokens.len() != signed_tokens.len() {
+            return Err(TokenError(InternalError::LengthMismatchError));
+        }
+
+        let S: Vec<RistrettoPoint> = blinded_tokens
+            .iter()
+            .zip(signed_tokens.iter())
+            .map(|(Ti, Wi)| nonce_point::<D>(Ti, &Wi.s))
+            .collect();
+
+        let c_m = composite_scalars::<D>(public_key, blinded_tokens, &S, signed_tokens)?;
+        let M_T = composite(&c_m, blinded_tokens, |Ti| Ti.0.decompress())
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let M_S = composite(&c_m, &S, |Si| Some(*Si))
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let M_W = composite(&c_m, signed_tokens, |Wi| Wi.W.decompress())
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+
+        Ok(([M_T, M_S, M_W], S))
+    }
+
+    fn _verify<D>(
+        &self,
+        M: &[RistrettoPoint; 3],
+        public_key: &MetadataPublicKey,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let [M_T, M_S, M_W] = *M;
+        let H = generator_H();
+
+        let mut K = [RistrettoPoint::default(); 2];
+        let mut L = [RistrettoPoint::default(); 2];
+        for b in 0..2 {
+            let X = public_key.0[b]
+                .decompress()
+                .ok_or(TokenError(InternalError::PointDecompressionError))?;
+            K[b] = &self.s_x[b] * &constants::RISTRETTO_BASEPOINT_TABLE
+                + self.s_y[b] * H
+                + self.c[b] * X;
+            L[b] = self.s_x[b] * M_T + self.s_y[b] * M_S + self.c[b] * M_W;
+        }
+
+        let challenge = DLEQOrProof::challenge::<D>(public_key, M, &K, &L);
+
+        if challenge == self.c[0] + self.c[1] {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Verify the `DLEQOrProof` for the `MetadataSignedToken`s issued in response to
+    /// `blinded_tokens`.
+    pub fn verify<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[MetadataSignedToken],
+        public_key: &MetadataPublicKey,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let (M, _) = DLEQOrProof::composites::<D>(blinded_tokens, signed_tokens, public_key)?;
+        self._verify::<D>(&M, public_key)
+    }
+
+    /// Verify the `DLEQOrProof` then unblind the `MetadataSignedToken`s using each
+    /// corresponding `Token`
+    pub fn verify_and_unblind<'a, D, I>(
+        &self,
+        tokens: I,
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[MetadataSignedToken],
+        public_key: &MetadataPublicKey,
+    ) -> Result<Vec<MetadataUnblindedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        I: IntoIterator<Item = &'a Token>,
+    {
+        let (M, S) = DLEQOrProof::composites::<D>(blinded_tokens, signed_tokens, public_key)?;
+        self._verify::<D>(&M, public_key)?;
+
+        let unblinded_tokens = tokens
+            .into_iter()
+            .zip(S.iter().zip(signed_tokens.iter()))
+            .map(|(token, (Si, signed_token))| {
+                let r_inv = token.r.invert();
+                let W = signed_token
+                    .W
+                    .decompress()
+                    .ok_or(TokenError(InternalError::PointDecompressionError))?;
+                Ok(MetadataUnblindedToken {
+                    unblinded_token: UnblindedToken {
+                        t: token.t,
+                        W: (r_inv * W).compress(),
+                    },
+                    nonce: MetadataNonce((r_inv * Si).compress()),
+                })
+            })
+            .collect::<Result<Vec<MetadataUnblindedToken>, TokenError>>()?;
+        if unblinded_tokens.len() != signed_tokens.len() {
+            return Err(TokenError(InternalError::LengthMismatchError));
+        }
+        Ok(unblinded_tokens)
+    }
+}
+
+impl DLEQOrProof {
+    /// Convert this `DLEQOrProof` to a byte array.
+    pub fn to_bytes(&self) -> [u8; DLEQ_OR_PROOF_LENGTH] {
+        let mut bytes: [u8; DLEQ_OR_PROOF_LENGTH] = [0u8; DLEQ_OR_PROOF_LENGTH];
+        let scalars = self.c.iter().chain(self.s_x.iter()).chain(self.s_y.iter());
+        for (chunk, scalar) in bytes.chunks_mut(32).zip(scalars) {
+            chunk.copy_from_slice(scalar.as_bytes());
+        }
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "DLEQOrProof",
+            length: DLEQ_OR_PROOF_LENGTH,
+        })
+    }
+
+    /// Construct a `DLEQOrProof` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DLEQOrProof, TokenError> {
+        if bytes.len() != DLEQ_OR_PROOF_LENGTH {
+            return Err(DLEQOrProof::bytes_length_error());
+        }
+
+        let mut scalars = [Scalar::zero(); 6];
+        for (scalar, chunk) in scalars.iter_mut().zip(bytes.chunks(32)) {
+            let mut bits: [u8; 32] = [0u8; 32];
+            bits.copy_from_slice(chunk);
+            *scalar = Scalar::from_canonical_bytes(bits)
+                .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        }
+
+        Ok(DLEQOrProof {
+            c: [scalars[0], scalars[1]],
+            s_x: [scalars[2], scalars[3]],
+            s_y: [scalars[4], scalars[5]],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::Hmac;
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+
+    type HmacSha512 = Hmac<Sha512>;
+
+    #[test]
+    fn generator_matches_its_label() {
+        let expected = Sha512::digest(b"challenge-bypass-ristretto private metadata generator");
+        assert_eq!(&GENERATOR_H_BYTES[..], &expected[..]);
+    }
+
+    #[test]
+    fn private_metadata_works() {
+        let mut rng = OsRng;
+
+        let signing_key = MetadataSigningKey::random(&mut rng);
+        let public_key = signing_key.public_key();
+
+        for &bit in &[false, true] {
+            let tokens: Vec<Token> = (0..3)
+                .map(|_| Token::random::<Sha512, _>(&mut rng))
+                .collect();
+            let blinded_tokens: Vec<BlindedToken> = tokens.iter().map(|t| t.blind()).collect();
+
+            let (signed_tokens, proof) = signing_key
+                .issue::<Sha512, _>(&mut rng, bit, &blinded_tokens)
+                .unwrap();
+            let proof = DLEQOrProof::decode_base64(&proof.encode_base64()).unwrap();
+            let unblinded_tokens = proof
+                .verify_and_unblind::<Sha512, _>(
+                    &tokens,
+                    &blinded_tokens,
+                    &signed_tokens,
+                    &public_key,
+                )
+                .unwrap();
+
+            for unblinded_token in &unblinded_tokens {
+                let unblinded_token =
+                    MetadataUnblindedToken::decode_base64(&unblinded_token.encode_base64())
+                        .unwrap();
+                let message = b"test message";
+                let signature = unblinded_token
+                    .unblinded_token
+                    .derive_verification_key::<Sha512>()
+                    .sign::<HmacSha512>(message);
+
+                let (rederived, rederived_bit) = signing_key
+                    .rederive_unblinded_token::<Sha512, HmacSha512>(
+                        &unblinded_token.unblinded_token.t,
+                        &unblinded_token.nonce,
+                        &signature,
+                        message,
+                    )
+                    .unwrap();
+                assert_eq!(rederived_bit, bit);
+                assert_eq!(
+                    rederived.to_bytes()[..],
+                    unblinded_token.unblinded_token.to_bytes()[..]
+                );
+
+                assert!(signing_key
+                    .rederive_unblinded_token::<Sha512, HmacSha512>(
+                        &unblinded_token.unblinded_token.t,
+                        &unblinded_token.nonce,
+                        &signature,
+                        b"other message",
+                    )
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn resubmitted_tokens_do_not_reveal_the_bit() {
+        let mut rng = OsRng;
+
+        let signing_key = MetadataSigningKey::random(&mut rng);
+        let token = Token::random::<Sha512, _>(&mut rng);
+        let blinded_token = token.blind();
+
+        // Resubmitting \\(rT'\\) must not yield \\(rW'\\), as it would with a single key per
+        // bit, since the client could then test a later bit against an earlier one.
+        let r = Scalar::random(&mut rng);
+        let P = blinded_token.0.decompress().unwrap();
+        let resubmitted = BlindedToken((r * P).compress());
+
+        let (first, _) = signing_key
+            .issue::<Sha512, _>(&mut rng, true, &[blinded_token])
+            .unwrap();
+        let (second, _) = signing_key
+            .issue::<Sha512, _>(&mut rng, true, &[resubmitted])
+            .unwrap();
+        assert_ne!(
+            (r * first[0].W.decompress().unwrap()).compress(),
+            second[0].W
+        );
+
+        let (again, _) = signing_key
+            .issue::<Sha512, _>(&mut rng, true, &[blinded_token])
+            .unwrap();
+        assert_ne!(first[0].to_bytes()[..], again[0].to_bytes()[..]);
+    }
+
+    #[test]
+    fn rejects_other_keys() {
+        let mut rng = OsRng;
+
+        let signing_key = MetadataSigningKey::random(&mut rng);
+        let other_key = MetadataSigningKey::random(&mut rng);
+
+        let blinded_tokens = [Token::random::<Sha512, _>(&mut rng).blind()];
+        let (signed_tokens, proof) = signing_key
+            .issue::<Sha512, _>(&mut rng, true, &blinded_tokens)
+            .unwrap();
+
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &signing_key.public_key())
+            .is_ok());
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &other_key.public_key())
+            .is_err());
+
+        // Tokens signed by a key pair outside the pair are rejected, even if the other key
+        // pair of the public key is shared.
+        let mixed = MetadataSigningKey::from_bytes(
+            &[&other_key.to_bytes()[..64], &signing_key.to_bytes()[64..]].concat(),
+        )
+        .unwrap();
+        let (signed_tokens, proof) = other_key
+            .issue::<Sha512, _>(&mut rng, true, &blinded_tokens)
+            .unwrap();
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &other_key.public_key())
+            .is_ok());
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &signing_key.public_key())
+            .is_err());
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &mixed.public_key())
+            .is_err());
+
+        let public_key =
+            MetadataPublicKey::decode_base64(&signing_key.public_key().encode_base64()).unwrap();
+        assert_eq!(public_key.0, signing_key.public_key.0);
+        let signed_token =
+            MetadataSignedToken::decode_base64(&signed_tokens[0].encode_base64()).unwrap();
+        assert_eq!(signed_token.to_bytes()[..], signed_tokens[0].to_bytes()[..]);
+        assert!(DLEQOrProof::from_bytes(&[0xffu8; DLEQ_OR_PROOF_LENGTH]).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Python bindings built with PyO3.
+//!
+//! The module is importable as `challenge_bypass_ristretto` once built with
+//! [maturin](https://github.com/PyO3/maturin), e.g. `maturin develop --features python`. Each
+//! type is wrapped under its original name and round-trips through `bytes` or base64 strings
+//! using the existing `to_bytes` / `from_bytes` codecs. The hash and MAC are fixed to SHA-512
+//! and HMAC-SHA512, and randomness comes from the operating system.
+//!
+//! Errors are raised as subclasses of `challenge_bypass_ristretto.TokenError`, one per
+//! `InternalError` variant, so that callers can catch e.g. `VerifyError` specifically.
+
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use hmac::Hmac;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rand::rngs::OsRng;
+use sha2::Sha512;
+
+use crate::errors::{InternalError, TokenError};
+use crate::voprf::*;
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// Python exception types, one for each `InternalError` variant.
+pub mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyValueError;
+
+    create_exception!(
+        challenge_bypass_ristretto,
+        TokenError,
+        PyValueError,
+        "Base class of the errors raised by this module."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        PointDecompressionError,
+        TokenError,
+        "Raised for `InternalError::PointDecompressionError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        ScalarFormatError,
+        TokenError,
+        "Raised for `InternalError::ScalarFormatError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        BytesLengthError,
+        TokenError,
+        "Raised for `InternalError::BytesLengthError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        VerifyError,
+        TokenError,
+        "Raised for `InternalError::VerifyError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        LengthMismatchError,
+        TokenError,
+        "Raised for `InternalError::LengthMismatchError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        DecodingError,
+        TokenError,
+        "Raised for `InternalError::DecodingError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        InvalidInputError,
+        TokenError,
+        "Raised for `InternalError::InvalidInputError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        UnknownKeyError,
+        TokenError,
+        "Raised for `InternalError::UnknownKeyError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        DoubleSpendError,
+        TokenError,
+        "Raised for `InternalError::DoubleSpendError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        KeyMismatchError,
+        TokenError,
+        "Raised for `InternalError::KeyMismatchError`."
+    );
+    create_exception!(
+        challenge_bypass_ristretto,
+        InvalidShareError,
+        TokenError,
+        "Raised for `InternalError::InvalidShareError`."
+    );
+}
+
+impl From<TokenError> for PyErr {
+    fn from(error: TokenError) -> PyErr {
+        let message = error.to_string();
+        match error.0 {
+            InternalError::PointDecompressionError => {
+                exceptions::PointDecompressionError::new_err(message)
+            }
+            InternalError::ScalarFormatError => exceptions::ScalarFormatError::new_err(message),
+            InternalError::BytesLengthError { .. } => {
+                exceptions::BytesLengthError::new_err(message)
+            }
+            InternalError::VerifyError => exceptions::VerifyError::new_err(message),
+            InternalError::LengthMismatchError => exceptions::LengthMismatchError::new_err(message),
+            InternalError::DecodingError => exceptions::DecodingError::new_err(message),
+            InternalError::InvalidInputError => exceptions::InvalidInputError::new_err(message),
+            InternalError::UnknownKeyError => exceptions::UnknownKeyError::new_err(message),
+            InternalError::DoubleSpendError => exceptions::DoubleSpendError::new_err(message),
+            InternalError::KeyMismatchError => exceptions::KeyMismatchError::new_err(message),
+            InternalError::InvalidShareError { .. } => {
+                exceptions::InvalidShareError::new_err(message)
+            }
+        }
+    }
+}
+
+/// Define the `bytes` and base64 codecs for a wrapped type, along with any other methods.
+macro_rules! py_wrapper {
+    ($wrapper:ident, $t:ident { $($methods:tt)* }) => {
+        #[pymethods]
+        impl $wrapper {
+            /// Encode to bytes.
+            fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+                PyBytes::new(py, &self.0.to_bytes())
+            }
+
+            /// Decode from bytes.
+            #[staticmethod]
+            fn from_bytes(bytes: &[u8]) -> PyResult<$wrapper> {
+                Ok($wrapper($t::from_bytes(bytes)?))
+            }
+
+            /// Encode to a base64 string.
+            fn encode_base64(&self) -> String {
+                self.0.encode_base64()
+            }
+
+            /// Decode from a base64 string.
+            #[staticmethod]
+            fn decode_base64(s: &str) -> PyResult<$wrapper> {
+                Ok($wrapper($t::decode_base64(s)?))
+            }
+
+            fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+                self.to_bytes(py)
+            }
+
+            $($methods)*
+        }
+    };
+}
+
+/// A `SigningKey`, held by the server.
+#[pyclass(name = "SigningKey", module = "challenge_bypass_ristretto", frozen)]
+pub struct PySigningKey(SigningKey);
+
+py_wrapper!(PySigningKey, SigningKey {
+    /// Generate a new random `SigningKey`.
+    #[staticmethod]
+    fn random() -> PySigningKey {
+        PySigningKey(SigningKey::random(&mut OsRng))
+    }
+
+    /// The `PublicKey` corresponding to this `SigningKey`.
+    #[getter]
+    fn public_key(&self) -> PyPublicKey {
+        PyPublicKey(self.0.public_key)
+    }
+
+    /// Sign a `BlindedToken`, returning a `SignedToken`.
+    fn sign(&self, blinded_token: &PyBlindedToken) -> PyResult<PySignedToken> {
+        Ok(PySignedToken(self.0.sign(&blinded_token.0)?))
+    }
+
+    /// Rederive the `UnblindedToken` for a `TokenPreimage` presented at redemption.
+    fn rederive_unblinded_token(&self, preimage: &PyTokenPreimage) -> PyUnblindedToken {
+        PyUnblindedToken(self.0.rederive_unblinded_token(&preimage.0))
+    }
+});
+
+/// The `PublicKey` of the server.
+#[pyclass(name = "PublicKey", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyPublicKey(PublicKey);
+
+py_wrapper!(PyPublicKey, PublicKey {});
+
+/// A `Token`, which must be kept secret by the client.
+#[pyclass(name = "Token", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyToken(Token);
+
+py_wrapper!(PyToken, Token {
+    /// Generate a new random `Token`.
+    #[staticmethod]
+    fn random() -> PyToken {
+        PyToken(Token::random::<Sha512, _>(&mut OsRng))
+    }
+
+    /// Blind the `Token`, returning a `BlindedToken` to be sent to the server.
+    fn blind(&self) -> PyBlindedToken {
+        PyBlindedToken(self.0.blind())
+    }
+});
+
+/// A `BlindedToken`, sent to the server for signing.
+#[pyclass(name = "BlindedToken", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyBlindedToken(BlindedToken);
+
+py_wrapper!(PyBlindedToken, BlindedToken {});
+
+/// A `SignedToken`, returned by the server.
+#[pyclass(name = "SignedToken", module = "challenge_bypass_ristretto", frozen)]
+pub struct PySignedToken(SignedToken);
+
+py_wrapper!(PySignedToken, SignedToken {});
+
+/// A `TokenPreimage`, sent to the server on redemption.
+#[pyclass(name = "TokenPreimage", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyTokenPreimage(TokenPreimage);
+
+py_wrapper!(PyTokenPreimage, TokenPreimage {});
+
+/// An `UnblindedToken`, which the client redeems.
+#[pyclass(name = "UnblindedToken", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyUnblindedToken(UnblindedToken);
+
+py_wrapper!(PyUnblindedToken, UnblindedToken {
+    /// The `TokenPreimage` of this token.
+    fn preimage(&self) -> PyTokenPreimage {
+        PyTokenPreimage(self.0.t)
+    }
+
+    /// Derive the `VerificationKey` used to sign a redemption request.
+    fn derive_verification_key(&self) -> PyVerificationKey {
+        PyVerificationKey(self.0.derive_verification_key::<Sha512>())
+    }
+});
+
+/// A `VerificationKey` derived from an `UnblindedToken`.
+#[pyclass(
+    name = "VerificationKey",
+    module = "challenge_bypass_ristretto",
+    frozen
+)]
+pub struct PyVerificationKey(VerificationKey);
+
+#[pymethods]
+impl PyVerificationKey {
+    /// Sign `message`, producing a `VerificationSignature`.
+    fn sign(&self, message: &[u8]) -> PyVerificationSignature {
+        PyVerificationSignature(self.0.sign::<HmacSha512>(message))
+    }
+
+    /// Check that `signature` is valid for `message`.
+    fn verify(&self, signature: &PyVerificationSignature, message: &[u8]) -> bool {
+        self.0.verify::<HmacSha512>(&signature.0, message)
+    }
+}
+
+/// A `VerificationSignature`, sent to the server on redemption.
+#[pyclass(
+    name = "VerificationSignature",
+    module = "challenge_bypass_ristretto",
+    frozen
+)]
+pub struct PyVerificationSignature(VerificationSignature);
+
+py_wrapper!(PyVerificationSignature, VerificationSignature {});
+
+/// A `BatchDLEQProof`, returned by the server alongside the `SignedToken`s.
+#[pyclass(name = "BatchDLEQProof", module = "challenge_bypass_ristretto", frozen)]
+pub struct PyBatchDLEQProof(BatchDLEQProof);
+
+py_wrapper!(PyBatchDLEQProof, BatchDLEQProof {
+    /// Prove that the `SignedToken`s were produced from the `BlindedToken`s by `signing_key`.
+    #[staticmethod]
+    fn new(
+        blinded_tokens: Vec<PyRef<'_, PyBlindedToken>>,
+        signed_tokens: Vec<PyRef<'_, PySignedToken>>,
+        signing_key: &PySigningKey,
+    ) -> PyResult<PyBatchDLEQProof> {
+        Ok(PyBatchDLEQProof(BatchDLEQProof::new::<Sha512, _>(
+            &mut OsRng,
+            &blinded_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &signed_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &signing_key.0,
+        )?))
+    }
+
+    /// Verify the proof, raising `VerifyError` if it is invalid.
+    fn verify(
+        &self,
+        blinded_tokens: Vec<PyRef<'_, PyBlindedToken>>,
+        signed_tokens: Vec<PyRef<'_, PySignedToken>>,
+        public_key: &PyPublicKey,
+    ) -> PyResult<()> {
+        Ok(self.0.verify::<Sha512>(
+            &blinded_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &signed_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &public_key.0,
+        )?)
+    }
+
+    /// Verify the proof and unblind the `SignedToken`s using the corresponding `Token`s.
+    fn verify_and_unblind(
+        &self,
+        tokens: Vec<PyRef<'_, PyToken>>,
+        blinded_tokens: Vec<PyRef<'_, PyBlindedToken>>,
+        signed_tokens: Vec<PyRef<'_, PySignedToken>>,
+        public_key: &PyPublicKey,
+    ) -> PyResult<Vec<PyUnblindedToken>> {
+        if tokens.len() != blinded_tokens.len() {
+            return Err(TokenError(InternalError::LengthMismatchError).into());
+        }
+
+        let unblinded_tokens = self.0.verify_and_unblind::<Sha512, _>(
+            tokens.iter().map(|t| &t.0),
+            &blinded_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &signed_tokens.iter().map(|t| t.0).collect::<Vec<_>>(),
+            &public_key.0,
+        )?;
+        Ok(unblinded_tokens.into_iter().map(PyUnblindedToken).collect())
+    }
+});
+
+/// The `challenge_bypass_ristretto` Python module.
+#[pymodule]
+fn challenge_bypass_ristretto(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySigningKey>()?;
+    m.add_class::<PyPublicKey>()?;
+    m.add_class::<PyToken>()?;
+    m.add_class::<PyBlindedToken>()?;
+    m.add_class::<PySignedToken>()?;
+    m.add_class::<PyTokenPreimage>()?;
+    m.add_class::<PyUnblindedToken>()?;
+    m.add_class::<PyVerificationKey>()?;
+    m.add_class::<PyVerificationSignature>()?;
+    m.add_class::<PyBatchDLEQProof>()?;
+
+    let py = m.py();
+    m.add("TokenError", py.get_type::<exceptions::TokenError>())?;
+    m.add(
+        "PointDecompressionError",
+        py.get_type::<exceptions::PointDecompressionError>(),
+    )?;
+    m.add(
+        "ScalarFormatError",
+        py.get_type::<exceptions::ScalarFormatError>(),
+    )?;
+    m.add(
+        "BytesLengthError",
+        py.get_type::<exceptions::BytesLengthError>(),
+    )?;
+    m.add("VerifyError", py.get_type::<exceptions::VerifyError>())?;
+    m.add(
+        "LengthMismatchError",
+        py.get_type::<exceptions::LengthMismatchError>(),
+    )?;
+    m.add("DecodingError", py.get_type::<exceptions::DecodingError>())?;
+    m.add(
+        "InvalidInputError",
+        py.get_type::<exceptions::InvalidInputError>(),
+    )?;
+    m.add(
+        "UnknownKeyError",
+        py.get_type::<exceptions::UnknownKeyError>(),
+    )?;
+    m.add(
+        "DoubleSpendError",
+        py.get_type::<exceptions::DoubleSpendError>(),
+    )?;
+    m.add(
+        "KeyMismatchError",
+        py.get_type::<exceptions::KeyMismatchError>(),
+    )?;
+    m.add(
+        "InvalidShareError",
+        py.get_type::<exceptions::InvalidShareError>(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn round_trip() {
+        pyo3::append_to_inittab!(challenge_bypass_ristretto);
+        pyo3::prepare_freethreaded_python();
+
+        Python::with_gil(|py| {
+            let locals = PyDict::new(py);
+            py.run(
+                pyo3::ffi::c_str!(
+                    r#"
+import challenge_bypass_ristretto as cbr
+
+key = cbr.SigningKey.decode_base64(cbr.SigningKey.random().encode_base64())
+token = cbr.Token.random()
+blinded = cbr.BlindedToken.from_bytes(bytes(token.blind()))
+signed = key.sign(blinded)
+proof = cbr.BatchDLEQProof.new([blinded], [signed], key)
+proof = cbr.BatchDLEQProof.from_bytes(proof.to_bytes())
+public_key = cbr.PublicKey.from_bytes(key.public_key.to_bytes())
+
+proof.verify([blinded], [signed], public_key)
+unblinded = proof.verify_and_unblind([token], [blinded], [signed], public_key)[0]
+
+signature = unblinded.derive_verification_key().sign(b"test message")
+signature = cbr.VerificationSignature.from_bytes(signature.to_bytes())
+preimage = cbr.TokenPreimage.decode_base64(unblinded.preimage().encode_base64())
+assert key.rederive_unblinded_token(preimage).derive_verification_key().verify(
+    signature, b"test message"
+)
+
+other = cbr.SigningKey.random()
+try:
+    proof.verify([blinded], [signed], other.public_key)
+    raise AssertionError("proof verified under the wrong key")
+except cbr.VerifyError as e:
+    assert isinstance(e, cbr.TokenError)
+    assert isinstance(e, ValueError)
+
+try:
+    cbr.Token.from_bytes(b"short")
+    raise AssertionError("decoded a short token")
+except cbr.BytesLengthError:
+    pass
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
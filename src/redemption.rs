@@ -0,0 +1,579 @@
+//! Double-spend prevention for token redemption.
+//!
+//! A `SpentTokenStore` records which `TokenPreimage`s have been redeemed under each
+//! `PublicKey`. The `Redeemer` combines such a store with a `SigningKey` so that every
+//! redemption verifies the client's `VerificationSignature` and atomically marks the
+//! token as spent.
+//!
+//! Two stores are provided: `MemorySpentTokenStore`, which keeps everything in memory,
+//! and `FileSpentTokenStore`, which additionally persists spent tokens to an append-only
+//! log file.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+use hmac::{Mac, NewMac};
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{
+    PublicKey, SigningKey, TokenPreimage, VerificationSignature, PUBLIC_KEY_LENGTH,
+    TOKEN_PREIMAGE_LENGTH,
+};
+
+const RECORD_LENGTH: usize = PUBLIC_KEY_LENGTH + TOKEN_PREIMAGE_LENGTH;
+
+/// A `SpentTokenStore` tracks which tokens have been redeemed under each `PublicKey`.
+pub trait SpentTokenStore {
+    /// The error returned when the backing storage fails
+    type Error;
+
+    /// Atomically mark `preimage` as spent under `public_key`.
+    ///
+    /// Returns `true` if the token had not been spent before, or `false` if it had, in
+    /// which case the store is left unchanged.
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> Result<bool, Self::Error>;
+}
+
+impl<S: SpentTokenStore + ?Sized> SpentTokenStore for &S {
+    type Error = S::Error;
+
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> Result<bool, Self::Error> {
+        (**self).check_and_insert(public_key, preimage)
+    }
+}
+
+impl<S: SpentTokenStore + ?Sized> SpentTokenStore for Arc<S> {
+    type Error = S::Error;
+
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> Result<bool, Self::Error> {
+        (**self).check_and_insert(public_key, preimage)
+    }
+}
+
+type SpentTokens = HashMap<[u8; PUBLIC_KEY_LENGTH], HashSet<[u8; TOKEN_PREIMAGE_LENGTH]>>;
+
+/// Insert a spent token into an in memory index, returning `false` if it was already present.
+fn insert_spent(spent: &mut SpentTokens, public_key: &PublicKey, preimage: &TokenPreimage) -> bool {
+    spent
+        .entry(public_key.to_bytes())
+        .or_default()
+        .insert(preimage.to_bytes())
+}
+
+/// A `SpentTokenStore` which is held entirely in memory.
+#[derive(Debug, Default)]
+pub struct MemorySpentTokenStore {
+    spent: Mutex<SpentTokens>,
+}
+
+impl MemorySpentTokenStore {
+    /// Construct an empty `MemorySpentTokenStore`.
+    pub fn new() -> Self {
+        MemorySpentTokenStore::default()
+    }
+
+    /// Lock the index, recovering it if another thread panicked while holding the lock.
+    ///
+    /// A single insert or removal cannot leave the index inconsistent, so it is safe to keep
+    /// using it.
+    fn lock(&self) -> MutexGuard<'_, SpentTokens> {
+        self.spent.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drop every spent token recorded under `public_key`, for use once the key has been
+    /// retired and its tokens can no longer be redeemed.
+    pub fn forget_key(&self, public_key: &PublicKey) {
+        self.lock().remove(&public_key.to_bytes());
+    }
+}
+
+impl SpentTokenStore for MemorySpentTokenStore {
+    type Error = TokenError;
+
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> Result<bool, TokenError> {
+        Ok(insert_spent(&mut self.lock(), public_key, preimage))
+    }
+}
+
+/// The storage backing a `SpentTokenLog`.
+trait LogFile: Write {
+    /// Flush written records to durable storage.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Truncate the log to `length` bytes.
+    fn truncate(&mut self, length: u64) -> io::Result<()>;
+}
+
+impl LogFile for File {
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+
+    fn truncate(&mut self, length: u64) -> io::Result<()> {
+        self.set_len(length)
+    }
+}
+
+#[derive(Debug)]
+struct SpentTokenLog<F = File> {
+    file: F,
+    /// The length of the complete records in the log
+    length: u64,
+    /// Set when a failed append could not be rolled back, leaving the log in an unknown state
+    failed: bool,
+    spent: SpentTokens,
+}
+
+impl<F: LogFile> SpentTokenLog<F> {
+    /// Append and sync `record`.
+    ///
+    /// If the record cannot be written completely the log is truncated back to its previous
+    /// length, so that a partial record never misaligns later appends. If that also fails
+    /// every later append is refused.
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "spent token log could not be restored after a failed write",
+            ));
+        }
+
+        let result = self.file.write_all(record).and_then(|()| self.file.sync());
+        match result {
+            Ok(()) => {
+                self.length += record.len() as u64;
+                Ok(())
+            }
+            Err(e) => {
+                if self.file.truncate(self.length).is_err() {
+                    self.failed = true;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// A `SpentTokenStore` which persists spent tokens to an append-only log file.
+///
+/// Each record in the log is the 32 byte `PublicKey` followed by the 64 byte
+/// `TokenPreimage`. The log is read into memory when opened, and every new record is
+/// synced to disk before `check_and_insert` returns. A record which fails to be written is
+/// removed from the log again.
+#[derive(Debug)]
+pub struct FileSpentTokenStore {
+    log: Mutex<SpentTokenLog>,
+}
+
+impl FileSpentTokenStore {
+    /// Open the log at `path`, creating it if it does not exist.
+    ///
+    /// A partially written trailing record, left by a crash during an append, is discarded.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut contents)?;
+
+        let complete = contents.len() - contents.len() % RECORD_LENGTH;
+        if complete != contents.len() {
+            file.set_len(complete as u64)?;
+        }
+
+        let mut spent = SpentTokens::new();
+        for record in contents[..complete].chunks(RECORD_LENGTH) {
+            let mut public_key = [0u8; PUBLIC_KEY_LENGTH];
+            let mut preimage = [0u8; TOKEN_PREIMAGE_LENGTH];
+            public_key.copy_from_slice(&record[..PUBLIC_KEY_LENGTH]);
+            preimage.copy_from_slice(&record[PUBLIC_KEY_LENGTH..]);
+            spent.entry(public_key).or_default().insert(preimage);
+        }
+
+        Ok(FileSpentTokenStore {
+            log: Mutex::new(SpentTokenLog {
+                file,
+                length: complete as u64,
+                failed: false,
+                spent,
+            }),
+        })
+    }
+}
+
+impl SpentTokenStore for FileSpentTokenStore {
+    type Error = io::Error;
+
+    fn check_and_insert(
+        &self,
+        public_key: &PublicKey,
+        preimage: &TokenPreimage,
+    ) -> io::Result<bool> {
+        // A failed append is rolled back before the lock is released, so the log is
+        // consistent even if another thread panicked while holding it
+        let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if log
+            .spent
+            .get(&public_key.to_bytes())
+            .map_or(false, |spent| spent.contains(&preimage.to_bytes()))
+        {
+            return Ok(false);
+        }
+
+        let mut record = [0u8; RECORD_LENGTH];
+        record[..PUBLIC_KEY_LENGTH].copy_from_slice(&public_key.to_bytes());
+        record[PUBLIC_KEY_LENGTH..].copy_from_slice(&preimage.to_bytes());
+        log.append(&record)?;
+
+        Ok(insert_spent(&mut log.spent, public_key, preimage))
+    }
+}
+
+/// Errors which may occur when redeeming a token.
+#[derive(Debug)]
+pub enum RedeemError<E> {
+    /// The token failed verification or has already been spent
+    Token(TokenError),
+    /// The `SpentTokenStore` failed
+    Store(E),
+}
+
+impl<E: fmt::Display> fmt::Display for RedeemError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedeemError::Token(e) => write!(f, "{}", e),
+            RedeemError::Store(e) => write!(f, "Spent token store failed: {}", e),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RedeemError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RedeemError::Token(e) => Some(e),
+            RedeemError::Store(e) => Some(e),
+        }
+    }
+}
+
+impl<E> From<TokenError> for RedeemError<E> {
+    fn from(e: TokenError) -> Self {
+        RedeemError::Token(e)
+    }
+}
+
+/// A `Redeemer` verifies redeemed tokens against a `SigningKey` and records them as spent.
+#[derive(Debug)]
+pub struct Redeemer<S: SpentTokenStore> {
+    signing_key: SigningKey,
+    store: S,
+}
+
+impl<S: SpentTokenStore> Redeemer<S> {
+    /// Construct a new `Redeemer` for tokens issued by `signing_key`.
+    ///
+    /// The same store may be shared between `Redeemer`s for different keys, as spent
+    /// tokens are tracked per `PublicKey`.
+    pub fn new(signing_key: SigningKey, store: S) -> Self {
+        Redeemer { signing_key, store }
+    }
+
+    /// The `SigningKey` tokens are verified against.
+    pub fn signing_key(&self) -> &SigningKey {
+        &self.signing_key
+    }
+
+    /// Verify the client's `VerificationSignature` over `message` then mark the token as
+    /// spent.
+    ///
+    /// Tokens which fail verification are not recorded, so an invalid redemption
+    /// cannot be used to burn another client's token.
+    pub fn redeem<D, M>(
+        &self,
+        preimage: &TokenPreimage,
+        signature: &VerificationSignature,
+        message: &[u8],
+    ) -> Result<(), RedeemError<S::Error>>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        M: Mac<OutputSize = U64> + NewMac,
+    {
+        let verification_key = self
+            .signing_key
+            .rederive_unblinded_token(preimage)
+            .derive_verification_key::<D>();
+
+        if !verification_key.verify::<M>(signature, message) {
+            return Err(TokenError(InternalError::VerifyError).into());
+        }
+
+        if self
+            .store
+            .check_and_insert(&self.signing_key.public_key, preimage)
+            .map_err(RedeemError::Store)?
+        {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::DoubleSpendError).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::Hmac;
+    use rand::rngs::OsRng;
+    use rand::Rng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::Token;
+
+    type HmacSha512 = Hmac<Sha512>;
+
+    fn client_redemption(
+        signing_key: &SigningKey,
+        message: &[u8],
+    ) -> (TokenPreimage, VerificationSignature) {
+        let mut rng = OsRng;
+
+        let token = Token::random::<Sha512, _>(&mut rng);
+        let unblinded_token = token
+            .unblind(&signing_key.sign(&token.blind()).unwrap())
+            .unwrap();
+        let signature = unblinded_token
+            .derive_verification_key::<Sha512>()
+            .sign::<HmacSha512>(message);
+        (unblinded_token.t, signature)
+    }
+
+    #[test]
+    fn memory_store_works() {
+        let mut rng = OsRng;
+
+        let store = MemorySpentTokenStore::new();
+        let redeemer = Redeemer::new(SigningKey::random(&mut rng), &store);
+        let other_redeemer = Redeemer::new(SigningKey::random(&mut rng), &store);
+
+        let (preimage, signature) = client_redemption(redeemer.signing_key(), b"test message");
+
+        // a bad signature does not spend the token
+        assert!(matches!(
+            redeemer.redeem::<Sha512, HmacSha512>(&preimage, &signature, b"other message"),
+            Err(RedeemError::Token(TokenError(InternalError::VerifyError)))
+        ));
+        assert!(other_redeemer
+            .redeem::<Sha512, HmacSha512>(&preimage, &signature, b"test message")
+            .is_err());
+
+        assert!(redeemer
+            .redeem::<Sha512, HmacSha512>(&preimage, &signature, b"test message")
+            .is_ok());
+        assert!(matches!(
+            redeemer.redeem::<Sha512, HmacSha512>(&preimage, &signature, b"test message"),
+            Err(RedeemError::Token(TokenError(
+                InternalError::DoubleSpendError
+            )))
+        ));
+
+        // spent tokens are scoped per key
+        assert!(store
+            .check_and_insert(&other_redeemer.signing_key().public_key, &preimage)
+            .unwrap());
+
+        store.forget_key(&redeemer.signing_key().public_key);
+        assert!(store
+            .check_and_insert(&redeemer.signing_key().public_key, &preimage)
+            .unwrap());
+    }
+
+    #[test]
+    fn memory_store_survives_poisoning() {
+        let mut rng = OsRng;
+
+        let store = Arc::new(MemorySpentTokenStore::new());
+        let signing_key = SigningKey::random(&mut rng);
+        let (preimage, _) = client_redemption(&signing_key, b"test message");
+
+        let poisoner = Arc::clone(&store);
+        assert!(std::thread::spawn(move || {
+            let _guard = poisoner.spent.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .is_err());
+
+        assert!(store
+            .check_and_insert(&signing_key.public_key, &preimage)
+            .unwrap());
+        assert!(!store
+            .check_and_insert(&signing_key.public_key, &preimage)
+            .unwrap());
+    }
+
+    #[test]
+    fn file_store_survives_poisoning() {
+        let mut rng = OsRng;
+
+        let path = std::env::temp_dir().join(format!("spent-tokens-{:x}.log", rng.gen::<u64>()));
+        let store = Arc::new(FileSpentTokenStore::open(&path).unwrap());
+        let signing_key = SigningKey::random(&mut rng);
+        let (preimage, _) = client_redemption(&signing_key, b"test message");
+
+        let poisoner = Arc::clone(&store);
+        assert!(std::thread::spawn(move || {
+            let _guard = poisoner.log.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .is_err());
+
+        assert!(store
+            .check_and_insert(&signing_key.public_key, &preimage)
+            .unwrap());
+        assert!(!store
+            .check_and_insert(&signing_key.public_key, &preimage)
+            .unwrap());
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// A `LogFile` which writes only part of a record when told to fail.
+    #[derive(Default)]
+    struct ShortWriter {
+        contents: Vec<u8>,
+        fail_writes: bool,
+        fail_truncate: bool,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.fail_writes {
+                if buf.len() == RECORD_LENGTH {
+                    self.contents.extend_from_slice(&buf[..10]);
+                    return Ok(10);
+                }
+                return Err(io::Error::new(io::ErrorKind::Other, "disk full"));
+            }
+            self.contents.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogFile for ShortWriter {
+        fn sync(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn truncate(&mut self, length: u64) -> io::Result<()> {
+            if self.fail_truncate {
+                return Err(io::Error::new(io::ErrorKind::Other, "read-only"));
+            }
+            self.contents.truncate(length as usize);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_writes_are_rolled_back() {
+        let mut log = SpentTokenLog {
+            file: ShortWriter::default(),
+            length: 0,
+            failed: false,
+            spent: SpentTokens::new(),
+        };
+
+        assert!(log.append(&[1u8; RECORD_LENGTH]).is_ok());
+
+        log.file.fail_writes = true;
+        assert!(log.append(&[2u8; RECORD_LENGTH]).is_err());
+        assert_eq!(log.file.contents.len(), RECORD_LENGTH);
+
+        // later records stay aligned
+        log.file.fail_writes = false;
+        assert!(log.append(&[3u8; RECORD_LENGTH]).is_ok());
+        assert_eq!(log.file.contents[..RECORD_LENGTH], [1u8; RECORD_LENGTH]);
+        assert_eq!(log.file.contents[RECORD_LENGTH..], [3u8; RECORD_LENGTH]);
+
+        // a log which cannot be rolled back refuses further appends
+        log.file.fail_writes = true;
+        log.file.fail_truncate = true;
+        assert!(log.append(&[4u8; RECORD_LENGTH]).is_err());
+        log.file.fail_writes = false;
+        log.file.fail_truncate = false;
+        assert!(log.append(&[5u8; RECORD_LENGTH]).is_err());
+        assert_eq!(log.file.contents.len(), 2 * RECORD_LENGTH + 10);
+    }
+
+    #[test]
+    fn file_store_persists() {
+        let mut rng = OsRng;
+
+        let path = std::env::temp_dir().join(format!("spent-tokens-{:x}.log", rng.gen::<u64>()));
+        let signing_key = SigningKey::random(&mut rng);
+        let (preimage, signature) = client_redemption(&signing_key, b"test message");
+        let (other_preimage, _) = client_redemption(&signing_key, b"test message");
+        let public_key = signing_key.public_key;
+
+        {
+            let redeemer = Redeemer::new(signing_key, FileSpentTokenStore::open(&path).unwrap());
+            assert!(redeemer
+                .redeem::<Sha512, HmacSha512>(&preimage, &signature, b"test message")
+                .is_ok());
+        }
+
+        // simulate a torn write at the end of the log
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[0u8; 10])
+            .unwrap();
+
+        let store = FileSpentTokenStore::open(&path).unwrap();
+        assert!(!store.check_and_insert(&public_key, &preimage).unwrap());
+        assert!(store
+            .check_and_insert(&public_key, &other_preimage)
+            .unwrap());
+        drop(store);
+
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            2 * RECORD_LENGTH as u64
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
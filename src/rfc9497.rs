@@ -0,0 +1,856 @@
+//! An implementation of the `ristretto255-SHA512` VOPRF ciphersuite from
+//! [RFC 9497](https://www.rfc-editor.org/rfc/rfc9497.html).
+//!
+//! Unlike the types in `voprf`, which implement the pre-standard protocol, every hash
+//! used here is domain separated by the RFC context string and the proof uses the
+//! standardized composite transcript. A server may use the same `SigningKey` for both
+//! protocols, though the outputs of the two are not interchangeable.
+//!
+//! The ciphersuite is defined over SHA-512, so `D` should always be `Sha512` in order
+//! to interoperate with other implementations.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::fmt::Debug;
+
+use curve25519_dalek::constants;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{Identity, VartimeMultiscalarMul};
+use digest::generic_array::typenum::{Unsigned, U64};
+use digest::{BlockInput, Digest};
+use rand::{CryptoRng, Rng};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::Zeroize;
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{PublicKey, SigningKey};
+
+/// The context string for the VOPRF mode of the `ristretto255-SHA512` ciphersuite.
+///
+/// \\(contextString = \texttt{"OPRFV1-"} \Vert I2OSP(mode, 1) \Vert \texttt{"-"} \Vert identifier\\)
+pub const CONTEXT_STRING: &[u8] = b"OPRFV1-\x01-ristretto255-SHA512";
+
+/// The length of a `Blind`, in bytes.
+pub const BLIND_LENGTH: usize = 32;
+/// The length of a `BlindedElement`, in bytes.
+pub const BLINDED_ELEMENT_LENGTH: usize = 32;
+/// The length of an `EvaluationElement`, in bytes.
+pub const EVALUATION_ELEMENT_LENGTH: usize = 32;
+/// The length of a `Proof`, in bytes.
+pub const PROOF_LENGTH: usize = 64;
+/// The length of an `Output`, in bytes.
+pub const OUTPUT_LENGTH: usize = 64;
+
+const ELEMENT_LENGTH_PREFIX: [u8; 2] = [0, 32];
+
+/// `expand_message_xmd` from RFC 9380, specialised to a single 64 byte output.
+///
+/// `msg` and `dst` are given as lists of slices which are concatenated.
+fn expand_message_xmd<D>(msg: &[&[u8]], dst: &[&[u8]]) -> [u8; 64]
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    let dst_len: usize = dst.iter().map(|part| part.len()).sum();
+    debug_assert!(dst_len <= 255);
+
+    let mut h = D::default();
+    for _ in 0..D::BlockSize::to_usize() {
+        h.update([0u8]);
+    }
+    for part in msg {
+        h.update(part);
+    }
+    // I2OSP(len_in_bytes, 2) || I2OSP(0, 1)
+    h.update([0u8, 64, 0]);
+    for part in dst {
+        h.update(part);
+    }
+    h.update([dst_len as u8]);
+    let b_0 = h.finalize();
+
+    let mut h = D::default();
+    h.update(b_0);
+    h.update([1u8]);
+    for part in dst {
+        h.update(part);
+    }
+    h.update([dst_len as u8]);
+
+    let mut b_1 = [0u8; 64];
+    b_1.copy_from_slice(&h.finalize());
+    b_1
+}
+
+/// Encode the length of `bytes` as a two byte big endian integer.
+fn i2osp_2(bytes: &[u8]) -> Result<[u8; 2], TokenError> {
+    if bytes.len() > u16::MAX as usize {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+    Ok((bytes.len() as u16).to_be_bytes())
+}
+
+/// Deterministically map `input` to a `RistrettoPoint`.
+///
+/// \\(HashToGroup(x) = \texttt{hash\\_to\\_ristretto255}(x, \texttt{"HashToGroup-"} \Vert contextString)\\)
+pub(crate) fn hash_to_group<D>(input: &[u8]) -> RistrettoPoint
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    let uniform_bytes = expand_message_xmd::<D>(&[input], &[b"HashToGroup-", CONTEXT_STRING]);
+    RistrettoPoint::from_uniform_bytes(&uniform_bytes)
+}
+
+/// Deterministically map the concatenation of `input` to a `Scalar`.
+///
+/// \\(HashToScalar(x) = \texttt{expand\\_message\\_xmd}(x, \texttt{"HashToScalar-"} \Vert contextString, 64) \mod q\\)
+pub(crate) fn hash_to_scalar<D>(input: &[&[u8]]) -> Scalar
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    hash_to_scalar_with_dst::<D>(input, &[b"HashToScalar-", CONTEXT_STRING])
+}
+
+pub(crate) fn hash_to_scalar_with_dst<D>(input: &[&[u8]], dst: &[&[u8]]) -> Scalar
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    Scalar::from_bytes_mod_order_wide(&expand_message_xmd::<D>(input, dst))
+}
+
+/// Hash an input and its unblinded evaluation to produce the final PRF `Output`.
+fn finalize_hash<D>(
+    input: &[u8],
+    unblinded_element: &CompressedRistretto,
+) -> Result<Output, TokenError>
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    let mut h = D::default();
+    h.update(i2osp_2(input)?);
+    h.update(input);
+    h.update(ELEMENT_LENGTH_PREFIX);
+    h.update(unblinded_element.as_bytes());
+    h.update(b"Finalize");
+
+    let mut output = [0u8; OUTPUT_LENGTH];
+    output.copy_from_slice(&h.finalize());
+    Ok(Output(output))
+}
+
+/// Decompress a point, rejecting invalid encodings and the identity as RFC 9497 requires.
+fn deserialize_element(point: &CompressedRistretto) -> Result<RistrettoPoint, TokenError> {
+    let point = point
+        .decompress()
+        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+    if point == RistrettoPoint::identity() {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+    Ok(point)
+}
+
+#[allow(non_snake_case)]
+impl SigningKey {
+    /// Deterministically derive a `SigningKey` from a high entropy `seed` and public `info`.
+    ///
+    /// This is `DeriveKeyPair` from RFC 9497.
+    pub fn derive_key_pair<D>(seed: &[u8; 32], info: &[u8]) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        let info_len = i2osp_2(info)?;
+        for counter in 0..=u8::MAX {
+            let k = hash_to_scalar_with_dst::<D>(
+                &[seed, &info_len, info, &[counter]],
+                &[b"DeriveKeyPair", CONTEXT_STRING],
+            );
+            if k != Scalar::zero() {
+                let Y = &k * &constants::RISTRETTO_BASEPOINT_TABLE;
+                return Ok(SigningKey {
+                    public_key: PublicKey(Y.compress()),
+                    k,
+                });
+            }
+        }
+        Err(TokenError(InternalError::InvalidInputError))
+    }
+
+    /// Evaluates the provided `BlindedElement`.
+    ///
+    /// This is the evaluation half of `BlindEvaluate` from RFC 9497, the accompanying
+    /// proof is produced with `Proof::new`.
+    pub fn blind_evaluate(&self, P: &BlindedElement) -> Result<EvaluationElement, TokenError> {
+        Ok(EvaluationElement(
+            (self.k * deserialize_element(&P.0)?).compress(),
+        ))
+    }
+
+    /// Directly computes the PRF `Output` for `input`, without any blinding.
+    ///
+    /// A server can compare this with the `Output` presented by a client at redemption.
+    pub fn evaluate<D>(&self, input: &[u8]) -> Result<Output, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        let T = hash_to_group::<D>(input);
+        if T == RistrettoPoint::identity() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        finalize_hash::<D>(input, &(self.k * T).compress())
+    }
+}
+
+/// A `Blind` is the random scalar a client uses to hide its input from the server.
+///
+/// It must be kept by the client until the server's response is finalized and
+/// should NEVER be revealed to the server.
+pub struct Blind(Scalar);
+
+/// Overwrite the blinding factor with null when it goes out of scope.
+impl Drop for Blind {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for Blind {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "Blind")
+    }
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Blind);
+
+#[cfg(feature = "serde")]
+impl_serde!(Blind);
+
+#[allow(non_snake_case)]
+impl Blind {
+    /// Generates a new random `Blind` using the provided random number generator.
+    pub fn random<T: Rng + CryptoRng>(rng: &mut T) -> Self {
+        loop {
+            let r = Scalar::random(rng);
+            if r != Scalar::zero() {
+                return Blind(r);
+            }
+        }
+    }
+
+    /// Blinds `input`, returning a `BlindedElement` to be sent to the server.
+    ///
+    /// \\(P = r \cdot HashToGroup(input)\\)
+    pub fn blind<D>(&self, input: &[u8]) -> Result<BlindedElement, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        i2osp_2(input)?;
+        let T = hash_to_group::<D>(input);
+        if T == RistrettoPoint::identity() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(BlindedElement((self.0 * T).compress()))
+    }
+
+    /// Using this `Blind`, unblind an `EvaluationElement` and hash it together with
+    /// `input` to produce the PRF `Output`.
+    ///
+    /// This does not check the server's proof, see `Proof::verify_and_finalize`.
+    pub fn finalize<D>(
+        &self,
+        input: &[u8],
+        evaluation_element: &EvaluationElement,
+    ) -> Result<Output, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        let Q = deserialize_element(&evaluation_element.0)?;
+        finalize_hash::<D>(input, &(self.0.invert() * Q).compress())
+    }
+
+    /// Convert this `Blind` to a byte array.
+    pub fn to_bytes(&self) -> [u8; BLIND_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Blind",
+            length: BLIND_LENGTH,
+        })
+    }
+
+    /// Construct a `Blind` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Blind, TokenError> {
+        if bytes.len() != BLIND_LENGTH {
+            return Err(Blind::bytes_length_error());
+        }
+
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(bytes);
+        let r = Scalar::from_canonical_bytes(bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        if r == Scalar::zero() {
+            return Err(TokenError(InternalError::ScalarFormatError));
+        }
+        Ok(Blind(r))
+    }
+}
+
+/// A `BlindedElement` is sent to the server for evaluation.
+///
+/// \\(P = r \cdot HashToGroup(input)\\)
+#[derive(Copy, Clone, Debug)]
+pub struct BlindedElement(pub(crate) CompressedRistretto);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(BlindedElement);
+
+#[cfg(feature = "serde")]
+impl_serde!(BlindedElement);
+
+impl BlindedElement {
+    /// Convert this `BlindedElement` to a byte array.
+    pub fn to_bytes(&self) -> [u8; BLINDED_ELEMENT_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "BlindedElement",
+            length: BLINDED_ELEMENT_LENGTH,
+        })
+    }
+
+    /// Construct a `BlindedElement` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlindedElement, TokenError> {
+        if bytes.len() != BLINDED_ELEMENT_LENGTH {
+            return Err(BlindedElement::bytes_length_error());
+        }
+
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(bytes);
+        Ok(BlindedElement(CompressedRistretto(bits)))
+    }
+}
+
+/// An `EvaluationElement` is the result of the server evaluating a `BlindedElement`.
+///
+/// \\(Q = k \cdot P\\)
+#[derive(Copy, Clone, Debug)]
+pub struct EvaluationElement(pub(crate) CompressedRistretto);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(EvaluationElement);
+
+#[cfg(feature = "serde")]
+impl_serde!(EvaluationElement);
+
+impl EvaluationElement {
+    /// Convert this `EvaluationElement` to a byte array.
+    pub fn to_bytes(&self) -> [u8; EVALUATION_ELEMENT_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "EvaluationElement",
+            length: EVALUATION_ELEMENT_LENGTH,
+        })
+    }
+
+    /// Construct an `EvaluationElement` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<EvaluationElement, TokenError> {
+        if bytes.len() != EVALUATION_ELEMENT_LENGTH {
+            return Err(EvaluationElement::bytes_length_error());
+        }
+
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(bytes);
+        Ok(EvaluationElement(CompressedRistretto(bits)))
+    }
+}
+
+/// The `Output` of the PRF for a particular input.
+///
+/// \\(Output = H(I2OSP(len(input), 2) \Vert input \Vert I2OSP(32, 2) \Vert N \Vert \texttt{"Finalize"})\\)
+#[derive(Copy, Clone)]
+pub struct Output([u8; OUTPUT_LENGTH]);
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "Output: {:?}", &self.0[..])
+    }
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Output);
+
+#[cfg(feature = "serde")]
+impl_serde!(Output);
+
+impl ConstantTimeEq for Output {
+    fn ct_eq(&self, other: &Output) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for Output {
+    fn eq(&self, other: &Output) -> bool {
+        self.ct_eq(other).unwrap_u8() == 1
+    }
+}
+
+impl Output {
+    /// Convert this `Output` to a byte array.
+    pub fn to_bytes(&self) -> [u8; OUTPUT_LENGTH] {
+        self.0
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Output",
+            length: OUTPUT_LENGTH,
+        })
+    }
+
+    /// Construct an `Output` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Output, TokenError> {
+        if bytes.len() != OUTPUT_LENGTH {
+            return Err(Output::bytes_length_error());
+        }
+
+        let mut bits: [u8; OUTPUT_LENGTH] = [0u8; OUTPUT_LENGTH];
+        bits.copy_from_slice(bytes);
+        Ok(Output(bits))
+    }
+}
+
+/// A `Proof` that one or more `EvaluationElement`s were produced with the `SigningKey`
+/// committed to by a `PublicKey`.
+///
+/// This is the composite DLEQ proof from RFC 9497 section 2.2.
+#[allow(non_snake_case)]
+#[derive(Debug)]
+pub struct Proof {
+    /// `c` is a `Scalar`
+    /// \\(c = HashToScalar(Y, M, Z, t_2, t_3, \texttt{"Challenge"})\\)
+    pub(crate) c: Scalar,
+    /// `s` is a `Scalar`
+    /// \\(s = (r - ck) \mod q\\)
+    pub(crate) s: Scalar,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Proof);
+
+#[cfg(feature = "serde")]
+impl_serde!(Proof);
+
+#[allow(non_snake_case)]
+impl Proof {
+    /// Compute the composite elements `M` and `Z` for a batch.
+    ///
+    /// When the `SigningKey` is available `Z` is computed as `k * M`, otherwise it is
+    /// computed from the `EvaluationElement`s.
+    fn compute_composites<D>(
+        k: Option<&Scalar>,
+        public_key: &PublicKey,
+        blinded_elements: &[BlindedElement],
+        evaluation_elements: &[EvaluationElement],
+    ) -> Result<(RistrettoPoint, RistrettoPoint), TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        if blinded_elements.len() != evaluation_elements.len() {
+            return Err(TokenError(InternalError::LengthMismatchError));
+        }
+        if blinded_elements.len() > u16::MAX as usize {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+
+        let mut h = D::default();
+        h.update(ELEMENT_LENGTH_PREFIX);
+        h.update(public_key.0.as_bytes());
+        h.update(((b"Seed-".len() + CONTEXT_STRING.len()) as u16).to_be_bytes());
+        h.update(b"Seed-");
+        h.update(CONTEXT_STRING);
+        let seed = h.finalize();
+
+        let d_i: Vec<Scalar> = blinded_elements
+            .iter()
+            .zip(evaluation_elements.iter())
+            .enumerate()
+            .map(|(i, (C_i, D_i))| {
+                hash_to_scalar::<D>(&[
+                    &(seed.len() as u16).to_be_bytes(),
+                    &seed,
+                    &(i as u16).to_be_bytes(),
+                    &ELEMENT_LENGTH_PREFIX,
+                    C_i.0.as_bytes(),
+                    &ELEMENT_LENGTH_PREFIX,
+                    D_i.0.as_bytes(),
+                    b"Composite",
+                ])
+            })
+            .collect();
+
+        let M = RistrettoPoint::optional_multiscalar_mul(
+            &d_i,
+            blinded_elements.iter().map(|C_i| C_i.0.decompress()),
+        )
+        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+
+        let Z = match k {
+            Some(k) => k * M,
+            None => RistrettoPoint::optional_multiscalar_mul(
+                &d_i,
+                evaluation_elements.iter().map(|D_i| D_i.0.decompress()),
+            )
+            .ok_or(TokenError(InternalError::PointDecompressionError))?,
+        };
+
+        Ok((M, Z))
+    }
+
+    fn challenge<D>(
+        public_key: &PublicKey,
+        M: &RistrettoPoint,
+        Z: &RistrettoPoint,
+        t2: &RistrettoPoint,
+        t3: &RistrettoPoint,
+    ) -> Scalar
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        hash_to_scalar::<D>(&[
+            &ELEMENT_LENGTH_PREFIX,
+            public_key.0.as_bytes(),
+            &ELEMENT_LENGTH_PREFIX,
+            M.compress().as_bytes(),
+            &ELEMENT_LENGTH_PREFIX,
+            Z.compress().as_bytes(),
+            &ELEMENT_LENGTH_PREFIX,
+            t2.compress().as_bytes(),
+            &ELEMENT_LENGTH_PREFIX,
+            t3.compress().as_bytes(),
+            b"Challenge",
+        ])
+    }
+
+    /// Construct a new `Proof` using the provided proof randomness `r`.
+    fn _new<D>(
+        r: Scalar,
+        blinded_elements: &[BlindedElement],
+        evaluation_elements: &[EvaluationElement],
+        signing_key: &SigningKey,
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        let (M, Z) = Proof::compute_composites::<D>(
+            Some(&signing_key.k),
+            &signing_key.public_key,
+            blinded_elements,
+            evaluation_elements,
+        )?;
+
+        let t2 = &r * &constants::RISTRETTO_BASEPOINT_TABLE;
+        let t3 = r * M;
+
+        let c = Proof::challenge::<D>(&signing_key.public_key, &M, &Z, &t2, &t3);
+        let s = r - c * signing_key.k;
+
+        Ok(Proof { c, s })
+    }
+
+    /// Construct a new `Proof` covering every `EvaluationElement` in a batch.
+    pub fn new<D, T>(
+        rng: &mut T,
+        blinded_elements: &[BlindedElement],
+        evaluation_elements: &[EvaluationElement],
+        signing_key: &SigningKey,
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+        T: Rng + CryptoRng,
+    {
+        Proof::_new::<D>(
+            Scalar::random(rng),
+            blinded_elements,
+            evaluation_elements,
+            signing_key,
+        )
+    }
+
+    /// Verify the `Proof`
+    pub fn verify<D>(
+        &self,
+        blinded_elements: &[BlindedElement],
+        evaluation_elements: &[EvaluationElement],
+        public_key: &PublicKey,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        let Y = deserialize_element(&public_key.0)?;
+        let (M, Z) = Proof::compute_composites::<D>(
+            None,
+            public_key,
+            blinded_elements,
+            evaluation_elements,
+        )?;
+
+        let t2 = RistrettoPoint::vartime_double_scalar_mul_basepoint(&self.c, &Y, &self.s);
+        let t3 = (self.s * M) + (self.c * Z);
+
+        let c = Proof::challenge::<D>(public_key, &M, &Z, &t2, &t3);
+
+        if c.ct_eq(&self.c).unwrap_u8() == 1 {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Verify the `Proof` then finalize each `EvaluationElement` using the corresponding
+    /// input and `Blind`.
+    ///
+    /// This is `Finalize` from RFC 9497, applied to a whole batch.
+    pub fn verify_and_finalize<D>(
+        &self,
+        inputs: &[&[u8]],
+        blinds: &[Blind],
+        blinded_elements: &[BlindedElement],
+        evaluation_elements: &[EvaluationElement],
+        public_key: &PublicKey,
+    ) -> Result<Vec<Output>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + BlockInput + Default,
+    {
+        if inputs.len() != evaluation_elements.len() || blinds.len() != evaluation_elements.len() {
+            return Err(TokenError(InternalError::LengthMismatchError));
+        }
+
+        self.verify::<D>(blinded_elements, evaluation_elements, public_key)?;
+
+        inputs
+            .iter()
+            .zip(blinds.iter())
+            .zip(evaluation_elements.iter())
+            .map(|((input, blind), evaluation_element)| {
+                blind.finalize::<D>(input, evaluation_element)
+            })
+            .collect()
+    }
+
+    /// Convert this `Proof` to a byte array.
+    pub fn to_bytes(&self) -> [u8; PROOF_LENGTH] {
+        let mut proof_bytes: [u8; PROOF_LENGTH] = [0u8; PROOF_LENGTH];
+
+        proof_bytes[..32].copy_from_slice(&self.c.to_bytes());
+        proof_bytes[32..].copy_from_slice(&self.s.to_bytes());
+        proof_bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Proof",
+            length: PROOF_LENGTH,
+        })
+    }
+
+    /// Construct a `Proof` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Proof, TokenError> {
+        if bytes.len() != PROOF_LENGTH {
+            return Err(Proof::bytes_length_error());
+        }
+
+        let mut c_bits: [u8; 32] = [0u8; 32];
+        let mut s_bits: [u8; 32] = [0u8; 32];
+
+        c_bits.copy_from_slice(&bytes[..32]);
+        s_bits.copy_from_slice(&bytes[32..]);
+
+        let c = Scalar::from_canonical_bytes(c_bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        let s = Scalar::from_canonical_bytes(s_bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+
+        Ok(Proof { c, s })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn scalar(s: &str) -> Scalar {
+        let mut bits = [0u8; 32];
+        bits.copy_from_slice(&hex(s));
+        Scalar::from_canonical_bytes(bits).unwrap()
+    }
+
+    #[test]
+    fn derive_key_pair_vector() {
+        // RFC 9497 appendix A.1.2, ristretto255-SHA512 VOPRF mode
+        let seed = [0xa3u8; 32];
+        let key = SigningKey::derive_key_pair::<Sha512>(&seed, b"test key").unwrap();
+        assert_eq!(
+            key.to_bytes().to_vec(),
+            hex("e6f73f344b79b379f1a0dd37e07ff62e38d9f71345ce62ae3a9bc60b04ccd909")
+        );
+        assert_eq!(
+            key.public_key.to_bytes().to_vec(),
+            hex("c803e2cc6b05fc15064549b5920659ca4a77b2cca6f04f6b357009335476ad4e")
+        );
+    }
+
+    #[test]
+    fn vector_tests() {
+        // RFC 9497 appendix A.1.2, ristretto255-SHA512 VOPRF mode
+        let vectors = [
+            (
+                vec!["00"],
+                vec!["64d37aed22a27f5191de1c1d69fadb899d8862b58eb4220029e036ec4c1f6706"],
+                vec!["863f330cc1a1259ed5a5998a23acfd37fb4351a793a5b3c090b642ddc439b945"],
+                vec!["aa8fa048764d5623868679402ff6108d2521884fa138cd7f9c7669a9a014267e"],
+                "ddef93772692e535d1a53903db24367355cc2cc78de93b3be5a8ffcc6985dd066d4346421d17bf5117a2a1ff0fcb2a759f58a539dfbe857a40bce4cf49ec600d",
+                "222a5e897cf59db8145db8d16e597e8facb80ae7d4e26d9881aa6f61d645fc0e",
+                vec!["b58cfbe118e0cb94d79b5fd6a6dafb98764dff49c14e1770b566e42402da1a7da4d8527693914139caee5bd03903af43a491351d23b430948dd50cde10d32b3c"],
+            ),
+            (
+                vec!["5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a"],
+                vec!["64d37aed22a27f5191de1c1d69fadb899d8862b58eb4220029e036ec4c1f6706"],
+                vec!["cc0b2a350101881d8a4cba4c80241d74fb7dcbfde4a61fde2f91443c2bf9ef0c"],
+                vec!["60a59a57208d48aca71e9e850d22674b611f752bed48b36f7a91b372bd7ad468"],
+                "401a0da6264f8cf45bb2f5264bc31e109155600babb3cd4e5af7d181a2c9dc0a67154fabf031fd936051dec80b0b6ae29c9503493dde7393b722eafdf5a50b02",
+                "222a5e897cf59db8145db8d16e597e8facb80ae7d4e26d9881aa6f61d645fc0e",
+                vec!["8a9a2f3c7f085b65933594309041fc1898d42d0858e59f90814ae90571a6df60356f4610bf816f27afdd84f47719e480906d27ecd994985890e5f539e7ea74b6"],
+            ),
+            (
+                vec!["00", "5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a"],
+                vec![
+                    "64d37aed22a27f5191de1c1d69fadb899d8862b58eb4220029e036ec4c1f6706",
+                    "222a5e897cf59db8145db8d16e597e8facb80ae7d4e26d9881aa6f61d645fc0e",
+                ],
+                vec![
+                    "863f330cc1a1259ed5a5998a23acfd37fb4351a793a5b3c090b642ddc439b945",
+                    "90a0145ea9da29254c3a56be4fe185465ebb3bf2a1801f7124bbbadac751e654",
+                ],
+                vec![
+                    "aa8fa048764d5623868679402ff6108d2521884fa138cd7f9c7669a9a014267e",
+                    "cc5ac221950a49ceaa73c8db41b82c20372a4c8d63e5dded2db920b7eee36a2a",
+                ],
+                "cc203910175d786927eeb44ea847328047892ddf8590e723c37205cb74600b0a5ab5337c8eb4ceae0494c2cf89529dcf94572ed267473d567aeed6ab873dee08",
+                "419c4f4f5052c53c45f3da494d2b67b220d02118e0857cdbcf037f9ea84bbe0c",
+                vec![
+                    "b58cfbe118e0cb94d79b5fd6a6dafb98764dff49c14e1770b566e42402da1a7da4d8527693914139caee5bd03903af43a491351d23b430948dd50cde10d32b3c",
+                    "8a9a2f3c7f085b65933594309041fc1898d42d0858e59f90814ae90571a6df60356f4610bf816f27afdd84f47719e480906d27ecd994985890e5f539e7ea74b6",
+                ],
+            ),
+        ];
+
+        let server_key = SigningKey::derive_key_pair::<Sha512>(&[0xa3u8; 32], b"test key").unwrap();
+
+        for (inputs, blinds, blinded, evaluated, proof, proof_random, outputs) in vectors.iter() {
+            let inputs: Vec<Vec<u8>> = inputs.iter().map(|input| hex(input)).collect();
+            let inputs: Vec<&[u8]> = inputs.iter().map(|input| &input[..]).collect();
+            let blinds: Vec<Blind> = blinds
+                .iter()
+                .map(|blind| Blind::from_bytes(&hex(blind)).unwrap())
+                .collect();
+
+            let blinded_elements: Vec<BlindedElement> = inputs
+                .iter()
+                .zip(blinds.iter())
+                .zip(blinded.iter())
+                .map(|((input, blind), expected)| {
+                    let blinded_element = blind.blind::<Sha512>(input).unwrap();
+                    assert_eq!(blinded_element.to_bytes().to_vec(), hex(expected));
+                    blinded_element
+                })
+                .collect();
+
+            let evaluation_elements: Vec<EvaluationElement> = blinded_elements
+                .iter()
+                .zip(evaluated.iter())
+                .map(|(blinded_element, expected)| {
+                    let evaluation_element = server_key.blind_evaluate(blinded_element).unwrap();
+                    assert_eq!(evaluation_element.to_bytes().to_vec(), hex(expected));
+                    evaluation_element
+                })
+                .collect();
+
+            let batch_proof = Proof::_new::<Sha512>(
+                scalar(proof_random),
+                &blinded_elements,
+                &evaluation_elements,
+                &server_key,
+            )
+            .unwrap();
+            assert_eq!(batch_proof.to_bytes().to_vec(), hex(proof));
+
+            let finalized = batch_proof
+                .verify_and_finalize::<Sha512>(
+                    &inputs,
+                    &blinds,
+                    &blinded_elements,
+                    &evaluation_elements,
+                    &server_key.public_key,
+                )
+                .unwrap();
+
+            for ((input, output), expected) in inputs.iter().zip(finalized.iter()).zip(outputs) {
+                assert_eq!(output.to_bytes().to_vec(), hex(expected));
+                assert!(server_key.evaluate::<Sha512>(input).unwrap() == *output);
+            }
+        }
+    }
+
+    #[test]
+    fn works() {
+        let mut rng = OsRng;
+
+        let key1 = SigningKey::random(&mut rng);
+        let key2 = SigningKey::random(&mut rng);
+
+        let input: &[u8] = b"test input";
+        let blind = Blind::random(&mut rng);
+        let blinded_element = blind.blind::<Sha512>(input).unwrap();
+
+        let evaluation_element = key1.blind_evaluate(&blinded_element).unwrap();
+        let proof =
+            Proof::new::<Sha512, _>(&mut rng, &[blinded_element], &[evaluation_element], &key1)
+                .unwrap();
+
+        let outputs = proof
+            .verify_and_finalize::<Sha512>(
+                &[input],
+                &[blind],
+                &[blinded_element],
+                &[evaluation_element],
+                &key1.public_key,
+            )
+            .unwrap();
+        assert!(outputs[0] == key1.evaluate::<Sha512>(input).unwrap());
+
+        assert!(proof
+            .verify::<Sha512>(&[blinded_element], &[evaluation_element], &key2.public_key)
+            .is_err());
+    }
+}
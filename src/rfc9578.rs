@@ -0,0 +1,484 @@
+//! Wire formats for privately verifiable token issuance from
+//! [RFC 9578](https://www.rfc-editor.org/rfc/rfc9578.html) (Privacy Pass).
+//!
+//! The `TokenRequest`, `TokenResponse` and `Token` structures follow the layout of the
+//! privately verifiable token type `0x0001`, built on the `voprf` types: the request
+//! carries a `BlindedToken`, the response a `SignedToken` and `DLEQProof`, and the
+//! authenticator is a `VerificationSignature` over the token input. Since the registered
+//! `0x0001` token type uses P-384 with SHA-384, the structures are tagged with the
+//! private-use `TOKEN_TYPE` instead, and are only interoperable with issuers of this crate.
+//!
+//! `D` is the hash for the VOPRF and should be `Sha512`, `H` is the hash used for the
+//! `token_key_id` and `challenge_digest` and should be `Sha256`, and `M` is the MAC of
+//! the authenticator and should be `Hmac<Sha512>`.
+
+use core::fmt::Debug;
+
+use digest::generic_array::typenum::{U32, U64};
+use digest::Digest;
+use hmac::{Mac, NewMac};
+use rand::{CryptoRng, Rng};
+
+use crate::errors::{InternalError, TokenError};
+use crate::voprf::{
+    BlindedToken, DLEQProof, PublicKey, SignedToken, SigningKey, Token as VoprfToken,
+    TokenPreimage, VerificationSignature, BLINDED_TOKEN_LENGTH, DLEQ_PROOF_LENGTH,
+    SIGNED_TOKEN_LENGTH, VERIFICATION_SIGNATURE_LENGTH,
+};
+
+/// The token type of privately verifiable tokens issued with ristretto255.
+///
+/// This is a private-use value, distinct from the `0x0001` registered for P-384.
+pub const TOKEN_TYPE: u16 = 0xF501;
+
+/// The length of a `token_key_id`, in bytes.
+pub const TOKEN_KEY_ID_LENGTH: usize = 32;
+/// The length of a `nonce`, in bytes.
+pub const NONCE_LENGTH: usize = 32;
+/// The length of a `challenge_digest`, in bytes.
+pub const CHALLENGE_DIGEST_LENGTH: usize = 32;
+/// The length of a `TokenRequest`, in bytes.
+pub const TOKEN_REQUEST_LENGTH: usize = 2 + 1 + BLINDED_TOKEN_LENGTH;
+/// The length of a `TokenResponse`, in bytes.
+pub const TOKEN_RESPONSE_LENGTH: usize = SIGNED_TOKEN_LENGTH + DLEQ_PROOF_LENGTH;
+/// The length of a `Token`, in bytes.
+pub const TOKEN_LENGTH: usize = 2
+    + NONCE_LENGTH
+    + CHALLENGE_DIGEST_LENGTH
+    + TOKEN_KEY_ID_LENGTH
+    + VERIFICATION_SIGNATURE_LENGTH;
+
+const TOKEN_INPUT_LENGTH: usize = 2 + NONCE_LENGTH + CHALLENGE_DIGEST_LENGTH + TOKEN_KEY_ID_LENGTH;
+
+/// Compute the `token_key_id` of an issuer `PublicKey`.
+///
+/// \\(token\\_key\\_id = H(Y)\\)
+pub fn token_key_id<H>(public_key: &PublicKey) -> [u8; TOKEN_KEY_ID_LENGTH]
+where
+    H: Digest<OutputSize = U32> + Default,
+{
+    let mut h = H::default();
+    h.update(public_key.to_bytes());
+
+    let mut key_id = [0u8; TOKEN_KEY_ID_LENGTH];
+    key_id.copy_from_slice(&h.finalize());
+    key_id
+}
+
+fn token_type_error() -> TokenError {
+    TokenError(InternalError::DecodingError)
+}
+
+/// The input which the final `Token` authenticates.
+fn token_input(
+    nonce: &[u8; NONCE_LENGTH],
+    challenge_digest: &[u8; CHALLENGE_DIGEST_LENGTH],
+    token_key_id: &[u8; TOKEN_KEY_ID_LENGTH],
+) -> [u8; TOKEN_INPUT_LENGTH] {
+    let mut input = [0u8; TOKEN_INPUT_LENGTH];
+    input[..2].copy_from_slice(&TOKEN_TYPE.to_be_bytes());
+    input[2..34].copy_from_slice(nonce);
+    input[34..66].copy_from_slice(challenge_digest);
+    input[66..].copy_from_slice(token_key_id);
+    input
+}
+
+/// A `TokenRequest` is sent by the client to the issuer.
+///
+/// ```text
+/// struct {
+///    uint16_t token_type = TOKEN_TYPE;
+///    uint8_t truncated_token_key_id;
+///    uint8_t blinded_msg[Ne];
+/// } TokenRequest;
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct TokenRequest {
+    /// The least significant byte of the issuer's `token_key_id`
+    pub truncated_token_key_id: u8,
+    /// The blinded token input
+    pub blinded_msg: BlindedToken,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(TokenRequest);
+
+#[cfg(feature = "serde")]
+impl_serde!(TokenRequest);
+
+impl TokenRequest {
+    /// Prepare a new `TokenRequest` for the issuer `PublicKey` in response to `challenge`,
+    /// the encoded `TokenChallenge` presented by the origin.
+    ///
+    /// The returned `TokenRequestState` must be retained by the client to finalize the
+    /// issuer's `TokenResponse`.
+    pub fn new<D, H, T>(
+        rng: &mut T,
+        public_key: &PublicKey,
+        challenge: &[u8],
+    ) -> Result<(TokenRequest, TokenRequestState), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        H: Digest<OutputSize = U32> + Default,
+        T: Rng + CryptoRng,
+    {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rng.fill(&mut nonce);
+
+        let mut challenge_digest = [0u8; CHALLENGE_DIGEST_LENGTH];
+        challenge_digest.copy_from_slice(&H::digest(challenge));
+
+        let token_key_id = token_key_id::<H>(public_key);
+
+        let token = VoprfToken::hash_from_bytes::<D, T>(
+            rng,
+            &token_input(&nonce, &challenge_digest, &token_key_id),
+        );
+        let blinded_msg = token.blind();
+
+        Ok((
+            TokenRequest {
+                truncated_token_key_id: token_key_id[TOKEN_KEY_ID_LENGTH - 1],
+                blinded_msg,
+            },
+            TokenRequestState {
+                public_key: *public_key,
+                nonce,
+                challenge_digest,
+                token_key_id,
+                token,
+                blinded_msg,
+            },
+        ))
+    }
+
+    /// Convert this `TokenRequest` to a byte array.
+    pub fn to_bytes(&self) -> [u8; TOKEN_REQUEST_LENGTH] {
+        let mut request_bytes = [0u8; TOKEN_REQUEST_LENGTH];
+
+        request_bytes[..2].copy_from_slice(&TOKEN_TYPE.to_be_bytes());
+        request_bytes[2] = self.truncated_token_key_id;
+        request_bytes[3..].copy_from_slice(&self.blinded_msg.to_bytes());
+        request_bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "TokenRequest",
+            length: TOKEN_REQUEST_LENGTH,
+        })
+    }
+
+    /// Construct a `TokenRequest` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TokenRequest, TokenError> {
+        if bytes.len() != TOKEN_REQUEST_LENGTH {
+            return Err(TokenRequest::bytes_length_error());
+        }
+        if bytes[..2] != TOKEN_TYPE.to_be_bytes() {
+            return Err(token_type_error());
+        }
+
+        Ok(TokenRequest {
+            truncated_token_key_id: bytes[2],
+            blinded_msg: BlindedToken::from_bytes(&bytes[3..])?,
+        })
+    }
+}
+
+/// The client state retained between sending a `TokenRequest` and receiving the
+/// corresponding `TokenResponse`.
+///
+/// Since it includes the blinding factor of the `voprf::Token` it should be treated as a
+/// client secret.
+#[derive(Debug)]
+pub struct TokenRequestState {
+    public_key: PublicKey,
+    nonce: [u8; NONCE_LENGTH],
+    challenge_digest: [u8; CHALLENGE_DIGEST_LENGTH],
+    token_key_id: [u8; TOKEN_KEY_ID_LENGTH],
+    token: VoprfToken,
+    blinded_msg: BlindedToken,
+}
+
+impl TokenRequestState {
+    /// Verify the issuer's proof and finalize the `TokenResponse` into a `Token`.
+    pub fn finalize<D, M>(&self, response: &TokenResponse) -> Result<Token, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        M: Mac<OutputSize = U64> + NewMac,
+    {
+        response.evaluate_proof.verify::<D>(
+            &self.blinded_msg,
+            &response.evaluate_msg,
+            &self.public_key,
+        )?;
+        let unblinded_token = self.token.unblind(&response.evaluate_msg)?;
+
+        let input = token_input(&self.nonce, &self.challenge_digest, &self.token_key_id);
+        Ok(Token {
+            nonce: self.nonce,
+            challenge_digest: self.challenge_digest,
+            token_key_id: self.token_key_id,
+            authenticator: unblinded_token
+                .derive_verification_key::<D>()
+                .sign::<M>(&input),
+        })
+    }
+}
+
+/// A `TokenResponse` is returned by the issuer.
+///
+/// ```text
+/// struct {
+///    uint8_t evaluate_msg[Ne];
+///    uint8_t evaluate_proof[Ns+Ns];
+/// } TokenResponse;
+/// ```
+#[derive(Debug)]
+pub struct TokenResponse {
+    /// The signed token input
+    pub evaluate_msg: SignedToken,
+    /// The proof that `evaluate_msg` was signed with the issuer's key
+    pub evaluate_proof: DLEQProof,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(TokenResponse);
+
+#[cfg(feature = "serde")]
+impl_serde!(TokenResponse);
+
+impl TokenResponse {
+    /// Evaluate a `TokenRequest` with the issuer `SigningKey`.
+    ///
+    /// Returns a `TokenError` if the request's `truncated_token_key_id` does not match
+    /// this key.
+    pub fn new<D, H, T>(
+        rng: &mut T,
+        request: &TokenRequest,
+        signing_key: &SigningKey,
+    ) -> Result<TokenResponse, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        H: Digest<OutputSize = U32> + Default,
+        T: Rng + CryptoRng,
+    {
+        let key_id = token_key_id::<H>(&signing_key.public_key);
+        if request.truncated_token_key_id != key_id[TOKEN_KEY_ID_LENGTH - 1] {
+            return Err(TokenError(InternalError::VerifyError));
+        }
+
+        let evaluate_msg = signing_key.sign(&request.blinded_msg)?;
+        let evaluate_proof =
+            DLEQProof::new::<D, T>(rng, &request.blinded_msg, &evaluate_msg, signing_key)?;
+
+        Ok(TokenResponse {
+            evaluate_msg,
+            evaluate_proof,
+        })
+    }
+
+    /// Convert this `TokenResponse` to a byte array.
+    pub fn to_bytes(&self) -> [u8; TOKEN_RESPONSE_LENGTH] {
+        let mut response_bytes = [0u8; TOKEN_RESPONSE_LENGTH];
+
+        response_bytes[..SIGNED_TOKEN_LENGTH].copy_from_slice(&self.evaluate_msg.to_bytes());
+        response_bytes[SIGNED_TOKEN_LENGTH..].copy_from_slice(&self.evaluate_proof.to_bytes());
+        response_bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "TokenResponse",
+            length: TOKEN_RESPONSE_LENGTH,
+        })
+    }
+
+    /// Construct a `TokenResponse` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TokenResponse, TokenError> {
+        if bytes.len() != TOKEN_RESPONSE_LENGTH {
+            return Err(TokenResponse::bytes_length_error());
+        }
+
+        Ok(TokenResponse {
+            evaluate_msg: SignedToken::from_bytes(&bytes[..SIGNED_TOKEN_LENGTH])?,
+            evaluate_proof: DLEQProof::from_bytes(&bytes[SIGNED_TOKEN_LENGTH..])?,
+        })
+    }
+}
+
+/// A `Token` is presented by the client to the origin for redemption.
+///
+/// ```text
+/// struct {
+///     uint16_t token_type = TOKEN_TYPE;
+///     uint8_t nonce[32];
+///     uint8_t challenge_digest[32];
+///     uint8_t token_key_id[32];
+///     uint8_t authenticator[Nk];
+/// } Token;
+/// ```
+pub struct Token {
+    /// A random nonce chosen by the client
+    pub nonce: [u8; NONCE_LENGTH],
+    /// The digest of the `TokenChallenge` the token was issued for
+    pub challenge_digest: [u8; CHALLENGE_DIGEST_LENGTH],
+    /// The `token_key_id` of the issuer key
+    pub token_key_id: [u8; TOKEN_KEY_ID_LENGTH],
+    /// The `VerificationSignature` over the other fields, with the `VerificationKey` of
+    /// the unblinded token
+    pub authenticator: VerificationSignature,
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        f.debug_struct("Token")
+            .field("nonce", &self.nonce)
+            .field("challenge_digest", &self.challenge_digest)
+            .field("token_key_id", &self.token_key_id)
+            .finish()
+    }
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Token);
+
+#[cfg(feature = "serde")]
+impl_serde!(Token);
+
+impl Token {
+    /// Verify the `Token` authenticator with the issuer `SigningKey`.
+    ///
+    /// The caller is responsible for checking that `challenge_digest` matches a challenge
+    /// it issued, that `token_key_id` identifies `signing_key`, and that the token has
+    /// not been spent before.
+    pub fn verify<D, M>(&self, signing_key: &SigningKey) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        M: Mac<OutputSize = U64> + NewMac,
+    {
+        let input = token_input(&self.nonce, &self.challenge_digest, &self.token_key_id);
+        let t = TokenPreimage::from_bytes(&D::digest(&input))?;
+
+        if signing_key
+            .rederive_unblinded_token(&t)
+            .derive_verification_key::<D>()
+            .verify::<M>(&self.authenticator, &input)
+        {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Convert this `Token` to a byte array.
+    pub fn to_bytes(&self) -> [u8; TOKEN_LENGTH] {
+        let mut token_bytes = [0u8; TOKEN_LENGTH];
+
+        token_bytes[..TOKEN_INPUT_LENGTH].copy_from_slice(&token_input(
+            &self.nonce,
+            &self.challenge_digest,
+            &self.token_key_id,
+        ));
+        token_bytes[TOKEN_INPUT_LENGTH..].copy_from_slice(&self.authenticator.to_bytes());
+        token_bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Token",
+            length: TOKEN_LENGTH,
+        })
+    }
+
+    /// Construct a `Token` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Token, TokenError> {
+        if bytes.len() != TOKEN_LENGTH {
+            return Err(Token::bytes_length_error());
+        }
+        if bytes[..2] != TOKEN_TYPE.to_be_bytes() {
+            return Err(token_type_error());
+        }
+
+        let mut nonce = [0u8; NONCE_LENGTH];
+        let mut challenge_digest = [0u8; CHALLENGE_DIGEST_LENGTH];
+        let mut token_key_id = [0u8; TOKEN_KEY_ID_LENGTH];
+
+        nonce.copy_from_slice(&bytes[2..34]);
+        challenge_digest.copy_from_slice(&bytes[34..66]);
+        token_key_id.copy_from_slice(&bytes[66..TOKEN_INPUT_LENGTH]);
+
+        Ok(Token {
+            nonce,
+            challenge_digest,
+            token_key_id,
+            authenticator: VerificationSignature::from_bytes(&bytes[TOKEN_INPUT_LENGTH..])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::Hmac;
+    use rand::rngs::OsRng;
+    use sha2::{Sha256, Sha512};
+
+    use super::*;
+
+    type HmacSha512 = Hmac<Sha512>;
+
+    #[test]
+    fn works() {
+        let mut rng = OsRng;
+
+        let issuer_key = SigningKey::random(&mut rng);
+        let challenge = b"\x00\x01\x00\x0bissuer.test\x00\x00\x00";
+
+        let (request, state) =
+            TokenRequest::new::<Sha512, Sha256, _>(&mut rng, &issuer_key.public_key, challenge)
+                .unwrap();
+        let request = TokenRequest::decode_base64(&request.encode_base64()).unwrap();
+
+        let response =
+            TokenResponse::new::<Sha512, Sha256, _>(&mut rng, &request, &issuer_key).unwrap();
+        let response = TokenResponse::decode_base64(&response.encode_base64()).unwrap();
+
+        let token = state.finalize::<Sha512, HmacSha512>(&response).unwrap();
+        let token = Token::decode_base64(&token.encode_base64()).unwrap();
+
+        assert_eq!(
+            token.token_key_id,
+            token_key_id::<Sha256>(&issuer_key.public_key)
+        );
+        assert_eq!(&token.challenge_digest[..], &Sha256::digest(challenge)[..]);
+        assert!(token.verify::<Sha512, HmacSha512>(&issuer_key).is_ok());
+
+        let other_key = SigningKey::random(&mut rng);
+        assert!(token.verify::<Sha512, HmacSha512>(&other_key).is_err());
+
+        let mut forged = Token::from_bytes(&token.to_bytes()).unwrap();
+        forged.nonce[0] ^= 1;
+        assert!(forged.verify::<Sha512, HmacSha512>(&issuer_key).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_type() {
+        let mut rng = OsRng;
+
+        let issuer_key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+
+        let (mut request, _state) =
+            TokenRequest::new::<Sha512, Sha256, _>(&mut rng, &issuer_key.public_key, b"challenge")
+                .unwrap();
+
+        // make sure the truncated key id cannot collide with the other key by chance
+        request.truncated_token_key_id =
+            !token_key_id::<Sha256>(&other_key.public_key)[TOKEN_KEY_ID_LENGTH - 1];
+        assert!(TokenResponse::new::<Sha512, Sha256, _>(&mut rng, &request, &other_key).is_err());
+
+        let mut request_bytes = request.to_bytes();
+        request_bytes[1] = 0x02;
+        assert!(TokenRequest::from_bytes(&request_bytes).is_err());
+    }
+}
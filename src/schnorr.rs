@@ -0,0 +1,649 @@
+//! Schnorr signatures over the Ristretto group, for authenticating key lists and other
+//! issuer metadata with a long-term key.
+//!
+//! A signature over `message` under key \\(a\\) with public key \\(A = aB\\) is the pair
+//! \\((R, s)\\) where \\(R = rB\\), \\(s = r + ca\\) and \\(c = H(context, R, A, message)\\).
+//! The challenge is computed with `D` regardless of the enabled features, and always covers an
+//! application supplied `context` so that signatures made for one purpose cannot be replayed
+//! for another.
+//!
+//! With the `merlin` feature, `SigningKey::sign_merlin` and `PublicKey::verify_merlin`
+//! instead derive the challenge from a merlin transcript which carries the context. These
+//! signatures have the same encoding but only verify with `verify_merlin`.
+//!
+//! Nonces are derived deterministically from the secret key, the context and the message,
+//! so signing does not require a random number generator.
+//!
+//! `SigningKey::with_context` and `PublicKey::with_context` implement the `signature`
+//! crate's `Signer` / `Verifier` traits, so that these keys can for example sign a
+//! `KeyCommitment`.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::fmt::Debug;
+use core::iter;
+use core::marker::PhantomData;
+
+use curve25519_dalek::constants;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+#[cfg(feature = "merlin")]
+use merlin::Transcript;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{PUBLIC_KEY_LENGTH, SIGNING_KEY_LENGTH};
+#[cfg(feature = "merlin")]
+use crate::transcript::ProofTranscript;
+
+/// The length of a Schnorr `Signature`, in bytes.
+pub const SIGNATURE_LENGTH: usize = 64;
+
+/// The domain separator for challenges.
+const SCHNORR_DOMAIN: &[u8] = b"challenge-bypass-ristretto schnorr";
+
+/// The domain separator for deterministic nonces.
+const SCHNORR_NONCE_DOMAIN: &[u8] = b"challenge-bypass-ristretto schnorr nonce";
+
+/// A `PublicKey` against which Schnorr signatures are verified.
+///
+/// \\(A = aB\\)
+#[derive(Copy, Clone, Debug)]
+pub struct PublicKey(CompressedRistretto);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(PublicKey);
+
+#[cfg(feature = "serde")]
+impl_serde!(PublicKey);
+
+#[allow(non_snake_case)]
+impl PublicKey {
+    /// Verify `signature` over `message` in `context`.
+    pub fn verify<D>(
+        &self,
+        context: &[u8],
+        message: &[u8],
+        signature: &Signature,
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let (R, s) = signature.decode()?;
+        let A = self
+            .0
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        let c = challenge::<D>(context, &R, &self.0, message);
+
+        let expected_R = RistrettoPoint::vartime_double_scalar_mul_basepoint(&c, &(-A), &s);
+        if expected_R.compress() == R {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Verify `signature` over `message`, made by `SigningKey::sign_merlin` with the same
+    /// `transcript`.
+    #[cfg(feature = "merlin")]
+    pub fn verify_merlin(
+        &self,
+        transcript: &mut Transcript,
+        message: &[u8],
+        signature: &Signature,
+    ) -> Result<(), TokenError> {
+        let (R, s) = signature.decode()?;
+        let A = self
+            .0
+            .decompress()
+            .ok_or(TokenError(InternalError::PointDecompressionError))?;
+        append_merlin(transcript, &self.0, message);
+        transcript.append_point(b"R", &R);
+        let c = transcript.challenge_scalar(b"c");
+
+        let expected_R = RistrettoPoint::vartime_double_scalar_mul_basepoint(&c, &(-A), &s);
+        if expected_R.compress() == R {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// A `Verifier` for signatures made by `SigningKey::with_context` in `context`.
+    pub fn with_context<'a, D>(&'a self, context: &'a [u8]) -> ContextVerifier<'a, D> {
+        ContextVerifier {
+            public_key: self,
+            context,
+            digest: PhantomData,
+        }
+    }
+
+    /// Convert this `PublicKey` to a byte array.
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "PublicKey",
+            length: PUBLIC_KEY_LENGTH,
+        })
+    }
+
+    /// Construct a `PublicKey` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKey, TokenError> {
+        if bytes.len() != PUBLIC_KEY_LENGTH {
+            return Err(PublicKey::bytes_length_error());
+        }
+
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(&bytes[..32]);
+
+        Ok(PublicKey(CompressedRistretto(bits)))
+    }
+}
+
+/// A long-term `SigningKey` used to make Schnorr signatures.
+///
+/// This is an issuer secret and should NEVER be revealed.
+#[derive(Debug)]
+pub struct SigningKey {
+    /// The `PublicKey` corresponding to this key
+    pub public_key: PublicKey,
+    /// `a` is the actual key
+    a: Scalar,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(SigningKey);
+
+#[cfg(feature = "serde")]
+impl_serde!(SigningKey);
+
+/// Overwrite signing key with null when it goes out of scope.
+impl Drop for SigningKey {
+    fn drop(&mut self) {
+        self.a.zeroize();
+    }
+}
+
+#[allow(non_snake_case)]
+impl SigningKey {
+    /// Generates a new random `SigningKey` using the provided random number generator.
+    pub fn random<T: Rng + CryptoRng>(rng: &mut T) -> Self {
+        SigningKey::from_scalar(Scalar::random(rng))
+    }
+
+    fn from_scalar(a: Scalar) -> Self {
+        let A = &a * &constants::RISTRETTO_BASEPOINT_TABLE;
+        SigningKey {
+            public_key: PublicKey(A.compress()),
+            a,
+        }
+    }
+
+    /// Sign `message` in `context`.
+    ///
+    /// The nonce is derived from the key, `context` and `message` using `D`, so signing
+    /// the same message twice produces the same signature.
+    pub fn sign<D>(&self, context: &[u8], message: &[u8]) -> Signature
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let mut nonce = D::default();
+        nonce.update(SCHNORR_NONCE_DOMAIN);
+        nonce.update(self.a.as_bytes());
+        nonce.update((context.len() as u64).to_be_bytes());
+        nonce.update(context);
+        nonce.update(message);
+        let mut r = Scalar::from_hash(nonce);
+
+        let R = (&r * &constants::RISTRETTO_BASEPOINT_TABLE).compress();
+        let c = challenge::<D>(context, &R, &self.public_key.0, message);
+        let s = r + c * self.a;
+        r.zeroize();
+
+        Signature::encode(&R, &s)
+    }
+
+    /// Sign `message` with the merlin `transcript`, to which any context should already have
+    /// been appended.
+    ///
+    /// The nonce is derived from the transcript, the key and `message`, so signing is
+    /// deterministic as with `sign`.
+    #[cfg(feature = "merlin")]
+    pub fn sign_merlin(&self, transcript: &mut Transcript, message: &[u8]) -> Signature {
+        append_merlin(transcript, &self.public_key.0, message);
+
+        let mut nonce = transcript.clone();
+        nonce.append_message(b"nonce-key", self.a.as_bytes());
+        let mut r = nonce.challenge_scalar(b"r");
+
+        let R = (&r * &constants::RISTRETTO_BASEPOINT_TABLE).compress();
+        transcript.append_point(b"R", &R);
+        let c = transcript.challenge_scalar(b"c");
+        let s = r + c * self.a;
+        r.zeroize();
+
+        Signature::encode(&R, &s)
+    }
+
+    /// A `Signer` which signs messages in `context`.
+    pub fn with_context<'a, D>(&'a self, context: &'a [u8]) -> ContextSigner<'a, D> {
+        ContextSigner {
+            signing_key: self,
+            context,
+            digest: PhantomData,
+        }
+    }
+
+    /// Convert this `SigningKey` to a byte array.
+    pub fn to_bytes(&self) -> [u8; SIGNING_KEY_LENGTH] {
+        self.a.to_bytes()
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "SigningKey",
+            length: SIGNING_KEY_LENGTH,
+        })
+    }
+
+    /// Construct a `SigningKey` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SigningKey, TokenError> {
+        if bytes.len() != SIGNING_KEY_LENGTH {
+            return Err(SigningKey::bytes_length_error());
+        }
+
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(&bytes[..32]);
+        let a = Scalar::from_canonical_bytes(bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+
+        Ok(SigningKey::from_scalar(a))
+    }
+}
+
+/// A Schnorr `Signature`, consisting of the commitment \\(R\\) followed by the response
+/// \\(s\\).
+#[derive(Copy, Clone)]
+pub struct Signature([u8; SIGNATURE_LENGTH]);
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(Signature);
+
+#[cfg(feature = "serde")]
+impl_serde!(Signature);
+
+impl Debug for Signature {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "Signature: {:?}", &self.0[..])
+    }
+}
+
+#[allow(non_snake_case)]
+impl Signature {
+    fn encode(R: &CompressedRistretto, s: &Scalar) -> Signature {
+        let mut bytes = [0u8; SIGNATURE_LENGTH];
+        bytes[..32].copy_from_slice(R.as_bytes());
+        bytes[32..].copy_from_slice(s.as_bytes());
+        Signature(bytes)
+    }
+
+    fn decode(&self) -> Result<(CompressedRistretto, Scalar), TokenError> {
+        let mut R_bits: [u8; 32] = [0u8; 32];
+        R_bits.copy_from_slice(&self.0[..32]);
+
+        let mut s_bits: [u8; 32] = [0u8; 32];
+        s_bits.copy_from_slice(&self.0[32..]);
+        let s = Scalar::from_canonical_bytes(s_bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+
+        Ok((CompressedRistretto(R_bits), s))
+    }
+
+    /// Verify many signatures at once, each over a message in a context under a
+    /// `PublicKey`.
+    ///
+    /// Each equation \\(s_i B = R_i + c_i A_i\\) is weighted by a random scalar
+    /// \\(z_i\\) and the sum checked with a single multiscalar multiplication. This only
+    /// reports whether every signature is valid, not which ones are not.
+    pub fn verify_batch<D, T>(
+        rng: &mut T,
+        signatures: &[(&PublicKey, &[u8], &[u8], &Signature)],
+    ) -> Result<(), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let mut B_coefficient = Scalar::zero();
+        let mut scalars: Vec<Scalar> = Vec::with_capacity(2 * signatures.len());
+        let mut points: Vec<Option<RistrettoPoint>> = Vec::with_capacity(2 * signatures.len());
+
+        for (public_key, context, message, signature) in signatures {
+            let (R, s) = signature.decode()?;
+            let c = challenge::<D>(context, &R, &public_key.0, message);
+            let z = Scalar::random(rng);
+
+            B_coefficient -= z * s;
+            scalars.push(z);
+            points.push(R.decompress());
+            scalars.push(z * c);
+            points.push(public_key.0.decompress());
+        }
+
+        let sum = RistrettoPoint::optional_multiscalar_mul(
+            iter::once(B_coefficient).chain(scalars),
+            iter::once(Some(constants::RISTRETTO_BASEPOINT_POINT)).chain(points),
+        )
+        .ok_or(TokenError(InternalError::PointDecompressionError))?;
+
+        if sum.is_identity() {
+            Ok(())
+        } else {
+            Err(TokenError(InternalError::VerifyError))
+        }
+    }
+
+    /// Convert this `Signature` to a byte array.
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_LENGTH] {
+        self.0
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "Signature",
+            length: SIGNATURE_LENGTH,
+        })
+    }
+
+    /// Construct a `Signature` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Signature, TokenError> {
+        if bytes.len() != SIGNATURE_LENGTH {
+            return Err(Signature::bytes_length_error());
+        }
+
+        let mut bits = [0u8; SIGNATURE_LENGTH];
+        bits.copy_from_slice(bytes);
+        let signature = Signature(bits);
+        signature.decode()?;
+        Ok(signature)
+    }
+}
+
+impl AsRef<[u8]> for Signature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl signature::Signature for Signature {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, signature::Error> {
+        Signature::from_bytes(bytes).or(Err(signature::Error::new()))
+    }
+}
+
+/// A `Signer` for a `SigningKey` in a fixed context, returned by `SigningKey::with_context`.
+#[derive(Debug)]
+pub struct ContextSigner<'a, D> {
+    signing_key: &'a SigningKey,
+    context: &'a [u8],
+    digest: PhantomData<D>,
+}
+
+impl<'a, D> signature::Signer<Signature> for ContextSigner<'a, D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    fn try_sign(&self, message: &[u8]) -> Result<Signature, signature::Error> {
+        Ok(self.signing_key.sign::<D>(self.context, message))
+    }
+}
+
+/// A `Verifier` for a `PublicKey` in a fixed context, returned by `PublicKey::with_context`.
+#[derive(Debug)]
+pub struct ContextVerifier<'a, D> {
+    public_key: &'a PublicKey,
+    context: &'a [u8],
+    digest: PhantomData<D>,
+}
+
+impl<'a, D> signature::Verifier<Signature> for ContextVerifier<'a, D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), signature::Error> {
+        self.public_key
+            .verify::<D>(self.context, message, signature)
+            .or(Err(signature::Error::new()))
+    }
+}
+
+/// Compute the challenge \\(c = H(context, R, A, message)\\).
+#[allow(non_snake_case)]
+fn challenge<D>(
+    context: &[u8],
+    R: &CompressedRistretto,
+    A: &CompressedRistretto,
+    message: &[u8],
+) -> Scalar
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    let mut hash = D::default();
+    hash.update(SCHNORR_DOMAIN);
+    hash.update((context.len() as u64).to_be_bytes());
+    hash.update(context);
+    hash.update(R.as_bytes());
+    hash.update(A.as_bytes());
+    hash.update(message);
+    Scalar::from_hash(hash)
+}
+
+/// Append the public inputs other than \\(R\\) to a merlin `transcript`.
+#[cfg(feature = "merlin")]
+#[allow(non_snake_case)]
+fn append_merlin(transcript: &mut Transcript, A: &CompressedRistretto, message: &[u8]) {
+    transcript.domain_separator(SCHNORR_DOMAIN);
+    transcript.append_point(b"A", A);
+    transcript.append_message(b"message", message);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::{Sha256, Sha512};
+
+    use super::*;
+    use crate::commitment::KeyCommitment;
+
+    #[test]
+    fn signature_vector() {
+        // the signature format does not depend on which features are enabled
+        let key = SigningKey::from_bytes(&[1u8; SIGNING_KEY_LENGTH]).unwrap();
+        let signature = key.sign::<Sha512>(b"vector", b"message");
+        assert_eq!(signature.encode_base64(), "PKlEgWot9W6PnVXfPBm2Y4ZLpkH04kNZFXPgll1ykSLAyhh64KBmBvuhQ1LOs5vYmRh1GEw4cAtRM5ffHo5ZBQ==");
+    }
+
+    #[test]
+    fn signature_works() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+
+        let signature = key.sign::<Sha512>(b"test context", b"test message");
+        assert!(key
+            .public_key
+            .verify::<Sha512>(b"test context", b"test message", &signature)
+            .is_ok());
+
+        // signing is deterministic
+        assert_eq!(
+            key.sign::<Sha512>(b"test context", b"test message").0[..],
+            signature.0[..]
+        );
+
+        // the signature is bound to the key, context and message
+        assert_eq!(
+            other_key
+                .public_key
+                .verify::<Sha512>(b"test context", b"test message", &signature)
+                .unwrap_err(),
+            TokenError(InternalError::VerifyError)
+        );
+        assert!(key
+            .public_key
+            .verify::<Sha512>(b"other context", b"test message", &signature)
+            .is_err());
+        assert!(key
+            .public_key
+            .verify::<Sha512>(b"test context", b"other message", &signature)
+            .is_err());
+
+        // and survives serialization
+        let key = SigningKey::decode_base64(&key.encode_base64()).unwrap();
+        let public_key = PublicKey::decode_base64(&key.public_key.encode_base64()).unwrap();
+        let signature = Signature::decode_base64(&signature.encode_base64()).unwrap();
+        assert!(public_key
+            .verify::<Sha512>(b"test context", b"test message", &signature)
+            .is_ok());
+    }
+
+    #[cfg(feature = "merlin")]
+    #[test]
+    fn merlin_signature_works() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+
+        let signature = key.sign_merlin(&mut Transcript::new(b"schnorrtest"), b"test message");
+        assert!(key
+            .public_key
+            .verify_merlin(
+                &mut Transcript::new(b"schnorrtest"),
+                b"test message",
+                &signature
+            )
+            .is_ok());
+
+        // signing is deterministic
+        assert_eq!(
+            key.sign_merlin(&mut Transcript::new(b"schnorrtest"), b"test message")
+                .0[..],
+            signature.0[..]
+        );
+
+        // the signature is bound to the key, transcript and message
+        assert!(other_key
+            .public_key
+            .verify_merlin(
+                &mut Transcript::new(b"schnorrtest"),
+                b"test message",
+                &signature
+            )
+            .is_err());
+        assert!(key
+            .public_key
+            .verify_merlin(
+                &mut Transcript::new(b"othertest"),
+                b"test message",
+                &signature
+            )
+            .is_err());
+        assert!(key
+            .public_key
+            .verify_merlin(
+                &mut Transcript::new(b"schnorrtest"),
+                b"other message",
+                &signature
+            )
+            .is_err());
+
+        // and is not interchangeable with a digest signature
+        assert!(key
+            .public_key
+            .verify::<Sha512>(b"schnorrtest", b"test message", &signature)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_non_canonical_signatures() {
+        let key = SigningKey::random(&mut OsRng);
+        let mut bytes = key.sign::<Sha512>(b"", b"test message").to_bytes();
+        bytes[63] = 0xff;
+        assert_eq!(
+            Signature::from_bytes(&bytes).unwrap_err(),
+            TokenError(InternalError::ScalarFormatError)
+        );
+    }
+
+    #[test]
+    fn verify_batch_works() {
+        let mut rng = OsRng;
+
+        let keys: Vec<SigningKey> = (0..5).map(|_| SigningKey::random(&mut rng)).collect();
+        let messages: Vec<[u8; 4]> = (0..5u32).map(|i| i.to_be_bytes()).collect();
+        let signatures: Vec<Signature> = keys
+            .iter()
+            .zip(messages.iter())
+            .map(|(key, message)| key.sign::<Sha512>(b"batch", message))
+            .collect();
+
+        let mut items: Vec<(&PublicKey, &[u8], &[u8], &Signature)> = keys
+            .iter()
+            .zip(messages.iter())
+            .zip(signatures.iter())
+            .map(|((key, message), signature)| {
+                (&key.public_key, &b"batch"[..], &message[..], signature)
+            })
+            .collect();
+        assert!(Signature::verify_batch::<Sha512, _>(&mut rng, &items).is_ok());
+        assert!(Signature::verify_batch::<Sha512, _>(&mut rng, &[]).is_ok());
+
+        // a single bad signature fails the batch
+        items[3].2 = b"forged";
+        assert_eq!(
+            Signature::verify_batch::<Sha512, _>(&mut rng, &items).unwrap_err(),
+            TokenError(InternalError::VerifyError)
+        );
+    }
+
+    #[test]
+    fn signs_key_commitments() {
+        let mut rng = OsRng;
+
+        let issuer = SigningKey::random(&mut rng);
+        let other = SigningKey::random(&mut rng);
+
+        let mut commitment = KeyCommitment::new(1, 1000);
+        commitment.add_key::<Sha256>(
+            &crate::oprf::SigningKey::random(&mut rng).public_key,
+            1000,
+            2000,
+        );
+        let signed = commitment
+            .sign(&issuer.with_context::<Sha512>(b"key commitment"))
+            .unwrap();
+
+        assert!(signed
+            .verify(&issuer.public_key.with_context::<Sha512>(b"key commitment"))
+            .is_ok());
+        assert!(signed
+            .verify(&issuer.public_key.with_context::<Sha512>(b"other"))
+            .is_err());
+        assert!(signed
+            .verify(&other.public_key.with_context::<Sha512>(b"key commitment"))
+            .is_err());
+    }
+}
@@ -0,0 +1,1029 @@
+//! Threshold issuance, where the `SigningKey` is split into Shamir shares so that no single
+//! server needs to hold it.
+//!
+//! `split` divides a `SigningKey` \\(k\\) into `n` `KeyShare`s \\(k_i = f(i)\\) of a random
+//! polynomial \\(f\\) of degree \\(t - 1\\) with \\(f(0) = k\\), any `t` of which suffice to
+//! issue tokens. The group `PublicKey` is unchanged, so clients verify and redeem tokens
+//! exactly as they would for a single issuer.
+//!
+//! Issuance runs in two stages, both driven by a `Combiner` which holds only public values:
+//!
+//! 1. Each share-holder signs the `BlindedToken`s with its share and proves it did so with a
+//!    `BatchDLEQProof` against its `PublicKeyShare`, returning a `PartialIssuance`.
+//!    `Combiner::combine` checks these proofs and interpolates the partial `SignedToken`s in
+//!    the exponent, \\(Q = \\sum \\lambda_i k_i P = kP\\).
+//! 2. The share-holders jointly prove that the combined `SignedToken`s were signed with
+//!    \\(k\\). The combiner sends a `ProofRequest` for the composites \\(M, Z\\), each
+//!    share-holder replies with a `NonceCommitment` via `KeyShare::commit` and then a
+//!    `ProofShare` via `KeyShare::respond`, and `Combiner::finish` sums the responses into a
+//!    `BatchDLEQProof` against the group `PublicKey`. Nonces are bound to the full set of
+//!    commitments as in FROST, so that responses from concurrent runs cannot be combined.
+//!
+//! Each share-holder's contribution is checked on its own, so that one which misbehaves is
+//! identified by an `InternalError::InvalidShareError` carrying its index.
+//!
+//! Share indices start at 1. Any `t` share-holders may take part, but the same set must be
+//! used for both rounds of the proof, and a `NonceShare` must never be used twice.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::iter;
+
+use curve25519_dalek::constants;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroize;
+
+use crate::codec::Reader;
+use crate::dleq::{BatchDLEQProof, DLEQProof, DLEQ_PROOF_LENGTH};
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::*;
+use crate::transcript::DigestTranscript;
+
+/// The domain separator for the nonce binding factors.
+const BINDING_DOMAIN: &[u8] = b"challenge-bypass-ristretto threshold binding";
+
+/// The length of a `KeyShare`, in bytes.
+pub const KEY_SHARE_LENGTH: usize = 4 + PUBLIC_KEY_LENGTH + SIGNING_KEY_LENGTH;
+/// The length of a `PublicKeyShare`, in bytes.
+pub const PUBLIC_KEY_SHARE_LENGTH: usize = 4 + PUBLIC_KEY_LENGTH;
+/// The length of a `ProofRequest`, in bytes.
+pub const PROOF_REQUEST_LENGTH: usize = 64;
+/// The length of a `NonceCommitment`, in bytes.
+pub const NONCE_COMMITMENT_LENGTH: usize = 4 + 5 * 32;
+/// The length of a `ProofShare`, in bytes.
+pub const PROOF_SHARE_LENGTH: usize = 4 + 32;
+
+/// Split `signing_key` into `shares` `KeyShare`s, any `threshold` of which can issue tokens.
+///
+/// Returns a `TokenError` if `threshold` is zero or greater than `shares`.
+pub fn split<T>(
+    rng: &mut T,
+    signing_key: &SigningKey,
+    threshold: u32,
+    shares: u32,
+) -> Result<Vec<KeyShare>, TokenError>
+where
+    T: Rng + CryptoRng,
+{
+    if threshold == 0 || threshold > shares {
+        return Err(TokenError(InternalError::InvalidInputError));
+    }
+
+    let mut coefficients: Vec<Scalar> = iter::once(signing_key.k)
+        .chain(iter::repeat_with(|| Scalar::random(rng)).take(threshold as usize - 1))
+        .collect();
+
+    let key_shares = (1..=shares)
+        .map(|index| {
+            let x = Scalar::from(index);
+            let k = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::zero(), |acc, a| acc * x + a);
+            KeyShare::new(index, signing_key.public_key, k)
+        })
+        .collect();
+
+    coefficients.zeroize();
+    Ok(key_shares)
+}
+
+/// The Lagrange coefficient \\(\\lambda_i\\) for interpolating at zero over `indices`.
+fn lagrange_coefficient(index: u32, indices: &[u32]) -> Scalar {
+    let i = Scalar::from(index);
+    let (num, den) = indices
+        .iter()
+        .filter(|&&j| j != index)
+        .map(|&j| Scalar::from(j))
+        .fold((Scalar::one(), Scalar::one()), |(num, den), j| {
+            (num * j, den * (j - i))
+        });
+    num * den.invert()
+}
+
+/// Check that `indices` are non-zero and strictly increasing.
+fn check_indices<I: Iterator<Item = u32>>(indices: I) -> Result<(), TokenError> {
+    let mut last = 0;
+    for index in indices {
+        if index <= last {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        last = index;
+    }
+    Ok(())
+}
+
+fn decompress(point: &CompressedRistretto) -> Result<RistrettoPoint, TokenError> {
+    point
+        .decompress()
+        .ok_or(TokenError(InternalError::PointDecompressionError))
+}
+
+fn invalid_share(index: u32) -> TokenError {
+    TokenError(InternalError::InvalidShareError { index })
+}
+
+fn read_point(reader: &mut Reader) -> Result<CompressedRistretto, TokenError> {
+    let point = CompressedRistretto::from_slice(reader.read(32)?);
+    decompress(&point)?;
+    Ok(point)
+}
+
+/// A share of a `SigningKey`, held by one of the issuers.
+///
+/// This is a server secret and should NEVER be revealed to the client or the combiner.
+#[derive(Debug)]
+pub struct KeyShare {
+    index: u32,
+    group_public_key: PublicKey,
+    signing_key: SigningKey,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(KeyShare);
+
+#[cfg(feature = "serde")]
+impl_serde!(KeyShare);
+
+#[allow(non_snake_case)]
+impl KeyShare {
+    pub(crate) fn new(index: u32, group_public_key: PublicKey, k: Scalar) -> Self {
+        let Y = &k * &constants::RISTRETTO_BASEPOINT_TABLE;
+        KeyShare {
+            index,
+            group_public_key,
+            signing_key: SigningKey {
+                public_key: PublicKey(Y.compress()),
+                k,
+            },
+        }
+    }
+
+    /// The index of this share.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The `PublicKey` of the `SigningKey` this share was split from.
+    pub fn group_public_key(&self) -> &PublicKey {
+        &self.group_public_key
+    }
+
+    /// The `PublicKeyShare` against which this share's `PartialIssuance`s are verified.
+    pub fn public_share(&self) -> PublicKeyShare {
+        PublicKeyShare {
+            index: self.index,
+            public_key: self.signing_key.public_key,
+        }
+    }
+
+    /// Sign each of the provided `BlindedToken`s with this share.
+    pub fn issue<D, T>(
+        &self,
+        rng: &mut T,
+        blinded_tokens: &[BlindedToken],
+    ) -> Result<PartialIssuance, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let (signed_tokens, proof) = self.signing_key.issue::<D, T>(rng, blinded_tokens)?;
+        Ok(PartialIssuance {
+            index: self.index,
+            signed_tokens,
+            proof,
+        })
+    }
+
+    /// Generate the nonces for this share's part in proving `request`.
+    ///
+    /// The `NonceCommitment` is sent to the combiner, while the `NonceShare` is kept until
+    /// `KeyShare::respond`.
+    pub fn commit<T>(&self, rng: &mut T, request: &ProofRequest) -> (NonceShare, NonceCommitment)
+    where
+        T: Rng + CryptoRng,
+    {
+        let nonces = NonceShare {
+            d: Scalar::random(rng),
+            e: Scalar::random(rng),
+        };
+        let commitment = NonceCommitment {
+            index: self.index,
+            d_x: (&nonces.d * &constants::RISTRETTO_BASEPOINT_TABLE).compress(),
+            e_x: (&nonces.e * &constants::RISTRETTO_BASEPOINT_TABLE).compress(),
+            d_m: (nonces.d * request.M).compress(),
+            e_m: (nonces.e * request.M).compress(),
+            y_m: (self.signing_key.k * request.M).compress(),
+        };
+        (nonces, commitment)
+    }
+
+    /// Respond to `request`, given the `NonceCommitment`s of every share-holder taking part.
+    ///
+    /// The `commitments` must be ordered by index and include this share's own commitment
+    /// to `nonces`, otherwise a `TokenError` is returned.
+    pub fn respond<D>(
+        &self,
+        nonces: NonceShare,
+        request: &ProofRequest,
+        commitments: &[NonceCommitment],
+    ) -> Result<ProofShare, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let own = commitments
+            .iter()
+            .find(|commitment| commitment.index == self.index)
+            .ok_or(TokenError(InternalError::InvalidInputError))?;
+        if own.d_x != (&nonces.d * &constants::RISTRETTO_BASEPOINT_TABLE).compress()
+            || own.e_x != (&nonces.e * &constants::RISTRETTO_BASEPOINT_TABLE).compress()
+        {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+
+        let (c, rho) = request.challenge::<D>(&self.group_public_key, commitments)?;
+        let indices: Vec<u32> = commitments
+            .iter()
+            .map(|commitment| commitment.index)
+            .collect();
+        let position = indices.iter().position(|&i| i == self.index).unwrap();
+        let lambda = lagrange_coefficient(self.index, &indices);
+
+        Ok(ProofShare {
+            index: self.index,
+            s: nonces.d + rho[position] * nonces.e - c * lambda * self.signing_key.k,
+        })
+    }
+}
+
+impl KeyShare {
+    /// Convert this `KeyShare` to a byte array.
+    pub fn to_bytes(&self) -> [u8; KEY_SHARE_LENGTH] {
+        let mut bytes: [u8; KEY_SHARE_LENGTH] = [0u8; KEY_SHARE_LENGTH];
+        bytes[..4].copy_from_slice(&self.index.to_be_bytes());
+        bytes[4..4 + PUBLIC_KEY_LENGTH].copy_from_slice(&self.group_public_key.to_bytes());
+        bytes[4 + PUBLIC_KEY_LENGTH..].copy_from_slice(&self.signing_key.to_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "KeyShare",
+            length: KEY_SHARE_LENGTH,
+        })
+    }
+
+    /// Construct a `KeyShare` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<KeyShare, TokenError> {
+        if bytes.len() != KEY_SHARE_LENGTH {
+            return Err(KeyShare::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        let index = reader.read_u32()?;
+        if index == 0 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(KeyShare {
+            index,
+            group_public_key: PublicKey::from_bytes(reader.read(PUBLIC_KEY_LENGTH)?)?,
+            signing_key: SigningKey::from_bytes(reader.read(SIGNING_KEY_LENGTH)?)?,
+        })
+    }
+}
+
+/// The public half of a `KeyShare`.
+#[derive(Copy, Clone, Debug)]
+pub struct PublicKeyShare {
+    /// The index of the share
+    pub index: u32,
+    /// The `PublicKey` of the share, \\(Y_i = k_i X\\)
+    pub public_key: PublicKey,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(PublicKeyShare);
+
+#[cfg(feature = "serde")]
+impl_serde!(PublicKeyShare);
+
+impl PublicKeyShare {
+    /// Convert this `PublicKeyShare` to a byte array.
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_SHARE_LENGTH] {
+        let mut bytes: [u8; PUBLIC_KEY_SHARE_LENGTH] = [0u8; PUBLIC_KEY_SHARE_LENGTH];
+        bytes[..4].copy_from_slice(&self.index.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.public_key.to_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "PublicKeyShare",
+            length: PUBLIC_KEY_SHARE_LENGTH,
+        })
+    }
+
+    /// Construct a `PublicKeyShare` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PublicKeyShare, TokenError> {
+        if bytes.len() != PUBLIC_KEY_SHARE_LENGTH {
+            return Err(PublicKeyShare::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        let index = reader.read_u32()?;
+        if index == 0 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(PublicKeyShare {
+            index,
+            public_key: PublicKey::from_bytes(reader.read(PUBLIC_KEY_LENGTH)?)?,
+        })
+    }
+}
+
+/// The `SignedToken`s produced by a single `KeyShare`, with a `BatchDLEQProof` against its
+/// `PublicKeyShare`.
+#[derive(Debug)]
+pub struct PartialIssuance {
+    /// The index of the share which signed the tokens
+    pub index: u32,
+    /// The `BlindedToken`s signed with the share
+    pub signed_tokens: Vec<SignedToken>,
+    /// A proof that the tokens were signed with the share
+    pub proof: BatchDLEQProof,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(PartialIssuance);
+
+impl PartialIssuance {
+    /// Convert this `PartialIssuance` to bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            8 + self.signed_tokens.len() * SIGNED_TOKEN_LENGTH + DLEQ_PROOF_LENGTH,
+        );
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&(self.signed_tokens.len() as u32).to_be_bytes());
+        for signed_token in &self.signed_tokens {
+            bytes.extend_from_slice(&signed_token.to_bytes());
+        }
+        bytes.extend_from_slice(&self.proof.to_bytes());
+        bytes
+    }
+
+    /// Construct a `PartialIssuance` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PartialIssuance, TokenError> {
+        let mut reader = Reader(bytes);
+        let index = reader.read_u32()?;
+        if index == 0 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let signed_tokens = (0..reader.read_u32()?)
+            .map(|_| SignedToken::from_bytes(reader.read(SIGNED_TOKEN_LENGTH)?))
+            .collect::<Result<Vec<_>, TokenError>>()?;
+        let proof = BatchDLEQProof::from_bytes(reader.read(DLEQ_PROOF_LENGTH)?)?;
+        if !reader.0.is_empty() {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+        Ok(PartialIssuance {
+            index,
+            signed_tokens,
+            proof,
+        })
+    }
+}
+
+/// A request from the combiner for share-holders to prove the composites \\(Z = kM\\) of a
+/// combined issuance.
+#[allow(non_snake_case)]
+#[derive(Copy, Clone, Debug)]
+pub struct ProofRequest {
+    M: RistrettoPoint,
+    Z: RistrettoPoint,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(ProofRequest);
+
+#[cfg(feature = "serde")]
+impl_serde!(ProofRequest);
+
+#[allow(non_snake_case)]
+impl ProofRequest {
+    /// Construct the `ProofRequest` for `signed_tokens` issued under `public_key`.
+    pub fn new<D>(
+        blinded_tokens: &[BlindedToken],
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+    ) -> Result<Self, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let (M, Z) = BatchDLEQProof::calculate_composites(
+            &mut DigestTranscript::<D>::new(),
+            blinded_tokens,
+            signed_tokens,
+            public_key,
+        )?;
+        Ok(ProofRequest { M, Z })
+    }
+
+    /// Compute the challenge of the joint proof and the binding factor of each commitment.
+    fn challenge<D>(
+        &self,
+        public_key: &PublicKey,
+        commitments: &[NonceCommitment],
+    ) -> Result<(Scalar, Vec<Scalar>), TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        check_indices(commitments.iter().map(|commitment| commitment.index))?;
+
+        let mut h = D::default();
+        h.update(BINDING_DOMAIN);
+        h.update(public_key.0.as_bytes());
+        h.update(self.to_bytes());
+        for commitment in commitments {
+            h.update(commitment.to_bytes());
+        }
+        let binding = h.finalize();
+
+        let mut A = RistrettoPoint::default();
+        let mut B = RistrettoPoint::default();
+        let mut rho = Vec::with_capacity(commitments.len());
+        for commitment in commitments {
+            let rho_i = Scalar::from_hash(
+                D::default()
+                    .chain(binding)
+                    .chain(commitment.index.to_be_bytes()),
+            );
+            A += decompress(&commitment.d_x)? + rho_i * decompress(&commitment.e_x)?;
+            B += decompress(&commitment.d_m)? + rho_i * decompress(&commitment.e_m)?;
+            rho.push(rho_i);
+        }
+
+        let c = DLEQProof::challenge(
+            &mut DigestTranscript::<D>::new(),
+            public_key,
+            &self.M,
+            &self.Z,
+            &A,
+            &B,
+        );
+        Ok((c, rho))
+    }
+}
+
+impl ProofRequest {
+    /// Convert this `ProofRequest` to a byte array.
+    pub fn to_bytes(&self) -> [u8; PROOF_REQUEST_LENGTH] {
+        let mut bytes: [u8; PROOF_REQUEST_LENGTH] = [0u8; PROOF_REQUEST_LENGTH];
+        bytes[..32].copy_from_slice(self.M.compress().as_bytes());
+        bytes[32..].copy_from_slice(self.Z.compress().as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "ProofRequest",
+            length: PROOF_REQUEST_LENGTH,
+        })
+    }
+
+    /// Construct a `ProofRequest` from a slice of bytes.
+    #[allow(non_snake_case)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<ProofRequest, TokenError> {
+        if bytes.len() != PROOF_REQUEST_LENGTH {
+            return Err(ProofRequest::bytes_length_error());
+        }
+        let M = decompress(&CompressedRistretto::from_slice(&bytes[..32]))?;
+        let Z = decompress(&CompressedRistretto::from_slice(&bytes[32..]))?;
+        Ok(ProofRequest { M, Z })
+    }
+}
+
+/// The secret nonces of one share-holder for a single `ProofRequest`.
+///
+/// These must be used for at most one call to `KeyShare::respond`, and should NEVER be
+/// revealed.
+#[derive(Debug)]
+pub struct NonceShare {
+    d: Scalar,
+    e: Scalar,
+}
+
+/// Overwrite nonces with null when they go out of scope.
+impl Drop for NonceShare {
+    fn drop(&mut self) {
+        self.d.zeroize();
+        self.e.zeroize();
+    }
+}
+
+/// The commitment of one share-holder to its `NonceShare`, along with its share of the
+/// composite \\(k_i M\\) so that its response can be checked on its own.
+#[derive(Copy, Clone, Debug)]
+pub struct NonceCommitment {
+    /// The index of the share
+    pub index: u32,
+    d_x: CompressedRistretto,
+    e_x: CompressedRistretto,
+    d_m: CompressedRistretto,
+    e_m: CompressedRistretto,
+    y_m: CompressedRistretto,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(NonceCommitment);
+
+#[cfg(feature = "serde")]
+impl_serde!(NonceCommitment);
+
+impl NonceCommitment {
+    /// Convert this `NonceCommitment` to a byte array.
+    pub fn to_bytes(&self) -> [u8; NONCE_COMMITMENT_LENGTH] {
+        let mut bytes: [u8; NONCE_COMMITMENT_LENGTH] = [0u8; NONCE_COMMITMENT_LENGTH];
+        bytes[..4].copy_from_slice(&self.index.to_be_bytes());
+        bytes[4..36].copy_from_slice(self.d_x.as_bytes());
+        bytes[36..68].copy_from_slice(self.e_x.as_bytes());
+        bytes[68..100].copy_from_slice(self.d_m.as_bytes());
+        bytes[100..132].copy_from_slice(self.e_m.as_bytes());
+        bytes[132..].copy_from_slice(self.y_m.as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "NonceCommitment",
+            length: NONCE_COMMITMENT_LENGTH,
+        })
+    }
+
+    /// Construct a `NonceCommitment` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<NonceCommitment, TokenError> {
+        if bytes.len() != NONCE_COMMITMENT_LENGTH {
+            return Err(NonceCommitment::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        let index = reader.read_u32()?;
+        if index == 0 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(NonceCommitment {
+            index,
+            d_x: read_point(&mut reader)?,
+            e_x: read_point(&mut reader)?,
+            d_m: read_point(&mut reader)?,
+            e_m: read_point(&mut reader)?,
+            y_m: read_point(&mut reader)?,
+        })
+    }
+}
+
+/// The response of one share-holder to a `ProofRequest`.
+#[derive(Copy, Clone, Debug)]
+pub struct ProofShare {
+    /// The index of the share
+    pub index: u32,
+    s: Scalar,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl_base64!(ProofShare);
+
+#[cfg(feature = "serde")]
+impl_serde!(ProofShare);
+
+impl ProofShare {
+    /// Convert this `ProofShare` to a byte array.
+    pub fn to_bytes(&self) -> [u8; PROOF_SHARE_LENGTH] {
+        let mut bytes: [u8; PROOF_SHARE_LENGTH] = [0u8; PROOF_SHARE_LENGTH];
+        bytes[..4].copy_from_slice(&self.index.to_be_bytes());
+        bytes[4..].copy_from_slice(self.s.as_bytes());
+        bytes
+    }
+
+    fn bytes_length_error() -> TokenError {
+        TokenError(InternalError::BytesLengthError {
+            name: "ProofShare",
+            length: PROOF_SHARE_LENGTH,
+        })
+    }
+
+    /// Construct a `ProofShare` from a slice of bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ProofShare, TokenError> {
+        if bytes.len() != PROOF_SHARE_LENGTH {
+            return Err(ProofShare::bytes_length_error());
+        }
+        let mut reader = Reader(bytes);
+        let index = reader.read_u32()?;
+        if index == 0 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let mut bits: [u8; 32] = [0u8; 32];
+        bits.copy_from_slice(reader.read(32)?);
+        let s = Scalar::from_canonical_bytes(bits)
+            .ok_or(TokenError(InternalError::ScalarFormatError))?;
+        Ok(ProofShare { index, s })
+    }
+}
+
+/// Combines the output of `KeyShare`s into `SignedToken`s and a `BatchDLEQProof` which
+/// verify against the group `PublicKey`.
+#[derive(Clone, Debug)]
+pub struct Combiner {
+    public_key: PublicKey,
+    threshold: u32,
+    shares: Vec<PublicKeyShare>,
+}
+
+#[allow(non_snake_case)]
+impl Combiner {
+    /// Construct a `Combiner` for the group `public_key`, split into `shares` with the given
+    /// `threshold`.
+    ///
+    /// Returns a `TokenError` if `threshold` is zero or greater than the number of `shares`,
+    /// or if any share index is zero or repeated.
+    pub fn new(
+        public_key: PublicKey,
+        threshold: u32,
+        shares: Vec<PublicKeyShare>,
+    ) -> Result<Self, TokenError> {
+        if threshold == 0 || threshold as usize > shares.len() {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        let mut indices: Vec<u32> = shares.iter().map(|share| share.index).collect();
+        indices.sort_unstable();
+        check_indices(indices.into_iter())?;
+        Ok(Combiner {
+            public_key,
+            threshold,
+            shares,
+        })
+    }
+
+    /// The group `PublicKey`.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn share(&self, index: u32) -> Result<&PublicKeyShare, TokenError> {
+        self.shares
+            .iter()
+            .find(|share| share.index == index)
+            .ok_or(TokenError(InternalError::UnknownKeyError))
+    }
+
+    /// Verify each `PartialIssuance` and interpolate the first `threshold` of them into the
+    /// `SignedToken`s of the group key.
+    ///
+    /// The `partials` must be ordered by index. Returns a `TokenError` if there are fewer
+    /// than `threshold`, or an `InternalError::InvalidShareError` with the index of the first
+    /// which fails verification.
+    pub fn combine<D>(
+        &self,
+        blinded_tokens: &[BlindedToken],
+        partials: &[PartialIssuance],
+    ) -> Result<Vec<SignedToken>, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        check_indices(partials.iter().map(|partial| partial.index))?;
+        if partials.len() < self.threshold as usize {
+            return Err(TokenError(InternalError::VerifyError));
+        }
+        for partial in partials {
+            let share = self.share(partial.index)?;
+            partial
+                .proof
+                .verify::<D>(blinded_tokens, &partial.signed_tokens, &share.public_key)
+                .map_err(|_| invalid_share(partial.index))?;
+        }
+
+        let partials = &partials[..self.threshold as usize];
+        let indices: Vec<u32> = partials.iter().map(|partial| partial.index).collect();
+        let lambdas: Vec<Scalar> = indices
+            .iter()
+            .map(|&index| lagrange_coefficient(index, &indices))
+            .collect();
+
+        (0..blinded_tokens.len())
+            .map(|i| {
+                let mut Q = RistrettoPoint::default();
+                for (lambda, partial) in lambdas.iter().zip(partials) {
+                    Q += lambda * decompress(&partial.signed_tokens[i].0)?;
+                }
+                Ok(SignedToken(Q.compress()))
+            })
+            .collect()
+    }
+
+    /// Combine the `ProofShare`s of the share-holders which committed to `commitments` into
+    /// a `BatchDLEQProof` for `request`.
+    ///
+    /// Each response is checked against both the share-holder's `PublicKeyShare` and its
+    /// share of the composite \\(k_i M\\), so that one which responds incorrectly is
+    /// identified by an `InternalError::InvalidShareError` carrying its index.
+    pub fn finish<D>(
+        &self,
+        request: &ProofRequest,
+        commitments: &[NonceCommitment],
+        responses: &[ProofShare],
+    ) -> Result<BatchDLEQProof, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        if commitments.len() < self.threshold as usize {
+            return Err(TokenError(InternalError::VerifyError));
+        }
+        if commitments.len() != responses.len() {
+            return Err(TokenError(InternalError::LengthMismatchError));
+        }
+
+        let (c, rho) = request.challenge::<D>(&self.public_key, commitments)?;
+        let indices: Vec<u32> = commitments
+            .iter()
+            .map(|commitment| commitment.index)
+            .collect();
+
+        let mut s = Scalar::zero();
+        for ((commitment, response), rho_i) in commitments.iter().zip(responses).zip(rho) {
+            if commitment.index != response.index {
+                return Err(TokenError(InternalError::InvalidInputError));
+            }
+            let share = self.share(response.index)?;
+            let lambda = lagrange_coefficient(response.index, &indices);
+
+            let R_x = decompress(&commitment.d_x)? + rho_i * decompress(&commitment.e_x)?;
+            let R_m = decompress(&commitment.d_m)? + rho_i * decompress(&commitment.e_m)?;
+            let Y_x = decompress(&share.public_key.0)?;
+            let Y_m = decompress(&commitment.y_m)?;
+            if &response.s * &constants::RISTRETTO_BASEPOINT_TABLE + c * lambda * Y_x != R_x
+                || response.s * request.M + c * lambda * Y_m != R_m
+            {
+                return Err(invalid_share(response.index));
+            }
+            s += response.s;
+        }
+
+        let proof = DLEQProof { c, s };
+        proof._verify(
+            &mut DigestTranscript::<D>::new(),
+            request.M,
+            request.Z,
+            &self.public_key,
+        )?;
+        Ok(BatchDLEQProof(proof))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::Token;
+
+    fn blind(n: usize) -> (Vec<Token>, Vec<BlindedToken>) {
+        let tokens: Vec<Token> = (0..n)
+            .map(|_| Token::random::<Sha512, _>(&mut OsRng))
+            .collect();
+        let blinded_tokens = tokens.iter().map(|token| token.blind()).collect();
+        (tokens, blinded_tokens)
+    }
+
+    #[test]
+    fn threshold_issuance_works() {
+        let mut rng = OsRng;
+
+        let signing_key = SigningKey::random(&mut rng);
+        let shares = split(&mut rng, &signing_key, 3, 5).unwrap();
+        let combiner = Combiner::new(
+            signing_key.public_key,
+            3,
+            shares.iter().map(|share| share.public_share()).collect(),
+        )
+        .unwrap();
+
+        let (tokens, blinded_tokens) = blind(5);
+
+        let participants = [&shares[0], &shares[2], &shares[4]];
+        let partials: Vec<PartialIssuance> = participants
+            .iter()
+            .map(|share| share.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap())
+            .collect();
+        let signed_tokens = combiner
+            .combine::<Sha512>(&blinded_tokens, &partials)
+            .unwrap();
+
+        for (blinded_token, signed_token) in blinded_tokens.iter().zip(&signed_tokens) {
+            assert_eq!(signing_key.sign(blinded_token).unwrap().0, signed_token.0);
+        }
+
+        let request =
+            ProofRequest::new::<Sha512>(&blinded_tokens, &signed_tokens, combiner.public_key())
+                .unwrap();
+        let (nonces, commitments): (Vec<NonceShare>, Vec<NonceCommitment>) = participants
+            .iter()
+            .map(|share| share.commit(&mut rng, &request))
+            .unzip();
+        let responses: Vec<ProofShare> = participants
+            .iter()
+            .zip(nonces)
+            .map(|(share, nonces)| {
+                share
+                    .respond::<Sha512>(nonces, &request, &commitments)
+                    .unwrap()
+            })
+            .collect();
+        let proof = combiner
+            .finish::<Sha512>(&request, &commitments, &responses)
+            .unwrap();
+
+        let unblinded_tokens = proof
+            .verify_and_unblind::<Sha512, _>(
+                &tokens,
+                &blinded_tokens,
+                &signed_tokens,
+                &signing_key.public_key,
+            )
+            .unwrap();
+        assert_eq!(unblinded_tokens.len(), tokens.len());
+    }
+
+    #[test]
+    fn rejects_bad_shares() {
+        let mut rng = OsRng;
+
+        let signing_key = SigningKey::random(&mut rng);
+        assert!(split(&mut rng, &signing_key, 0, 3).is_err());
+        assert!(split(&mut rng, &signing_key, 4, 3).is_err());
+
+        let shares = split(&mut rng, &signing_key, 2, 3).unwrap();
+
+        // Zero and repeated share indices
+        let mut public_shares: Vec<PublicKeyShare> =
+            shares.iter().map(|share| share.public_share()).collect();
+        public_shares[2].index = 1;
+        assert!(Combiner::new(signing_key.public_key, 2, public_shares.clone()).is_err());
+        public_shares[2].index = 0;
+        assert!(Combiner::new(signing_key.public_key, 2, public_shares.clone()).is_err());
+        public_shares[2].index = 3;
+        public_shares.swap(0, 2);
+        let combiner = Combiner::new(signing_key.public_key, 2, public_shares).unwrap();
+
+        let (_, blinded_tokens) = blind(2);
+        let mut partials: Vec<PartialIssuance> = shares[..2]
+            .iter()
+            .map(|share| share.issue::<Sha512, _>(&mut rng, &blinded_tokens).unwrap())
+            .collect();
+
+        // Too few partials
+        assert!(combiner
+            .combine::<Sha512>(&blinded_tokens, &partials[..1])
+            .is_err());
+
+        // Out of order
+        partials.swap(0, 1);
+        assert!(combiner
+            .combine::<Sha512>(&blinded_tokens, &partials)
+            .is_err());
+        partials.swap(0, 1);
+
+        // Signed with the wrong share
+        partials[1].index = 3;
+        assert_eq!(
+            combiner
+                .combine::<Sha512>(&blinded_tokens, &partials)
+                .unwrap_err(),
+            TokenError(InternalError::InvalidShareError { index: 3 })
+        );
+        partials[1].index = 2;
+
+        let signed_tokens = combiner
+            .combine::<Sha512>(&blinded_tokens, &partials)
+            .unwrap();
+        let request =
+            ProofRequest::new::<Sha512>(&blinded_tokens, &signed_tokens, combiner.public_key())
+                .unwrap();
+        let (nonces, commitments): (Vec<NonceShare>, Vec<NonceCommitment>) = shares[..2]
+            .iter()
+            .map(|share| share.commit(&mut rng, &request))
+            .unzip();
+
+        // Responding without its own commitment
+        let (other_nonces, _) = shares[2].commit(&mut rng, &request);
+        assert!(shares[2]
+            .respond::<Sha512>(other_nonces, &request, &commitments)
+            .is_err());
+
+        let mut responses: Vec<ProofShare> = shares[..2]
+            .iter()
+            .zip(nonces)
+            .map(|(share, nonces)| {
+                share
+                    .respond::<Sha512>(nonces, &request, &commitments)
+                    .unwrap()
+            })
+            .collect();
+
+        // A tampered response is detected
+        responses[0].s += Scalar::one();
+        assert_eq!(
+            combiner
+                .finish::<Sha512>(&request, &commitments, &responses)
+                .unwrap_err(),
+            TokenError(InternalError::InvalidShareError { index: 1 })
+        );
+
+        // A response which only holds on the X side is detected
+        let (nonces, mut commitments): (Vec<NonceShare>, Vec<NonceCommitment>) = shares[..2]
+            .iter()
+            .map(|share| share.commit(&mut rng, &request))
+            .unzip();
+        commitments[1].d_m = commitments[1].d_x;
+        let responses: Vec<ProofShare> = shares[..2]
+            .iter()
+            .zip(nonces)
+            .map(|(share, nonces)| {
+                share
+                    .respond::<Sha512>(nonces, &request, &commitments)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            combiner
+                .finish::<Sha512>(&request, &commitments, &responses)
+                .unwrap_err(),
+            TokenError(InternalError::InvalidShareError { index: 2 })
+        );
+    }
+
+    #[test]
+    fn serialization_works() {
+        let mut rng = OsRng;
+
+        let signing_key = SigningKey::random(&mut rng);
+        let shares = split(&mut rng, &signing_key, 2, 2).unwrap();
+
+        let share = KeyShare::decode_base64(&shares[0].encode_base64()).unwrap();
+        assert_eq!(share.index(), 1);
+        assert_eq!(share.group_public_key().0, signing_key.public_key.0);
+        assert_eq!(share.signing_key.k, shares[0].signing_key.k);
+
+        let public_share =
+            PublicKeyShare::from_bytes(&shares[1].public_share().to_bytes()).unwrap();
+        assert_eq!(public_share.index, 2);
+        assert_eq!(
+            public_share.public_key.0,
+            shares[1].signing_key.public_key.0
+        );
+
+        let (_, blinded_tokens) = blind(3);
+        let partial = shares[0]
+            .issue::<Sha512, _>(&mut rng, &blinded_tokens)
+            .unwrap();
+        let decoded = PartialIssuance::decode_base64(&partial.encode_base64()).unwrap();
+        assert_eq!(decoded.to_bytes(), partial.to_bytes());
+        assert!(PartialIssuance::from_bytes(&partial.to_bytes()[..40]).is_err());
+
+        let request = ProofRequest::new::<Sha512>(
+            &blinded_tokens,
+            &partial.signed_tokens,
+            &shares[0].signing_key.public_key,
+        )
+        .unwrap();
+        assert_eq!(
+            ProofRequest::from_bytes(&request.to_bytes())
+                .unwrap()
+                .to_bytes(),
+            request.to_bytes()
+        );
+
+        let (nonces, commitment) = shares[0].commit(&mut rng, &request);
+        assert_eq!(
+            NonceCommitment::from_bytes(&commitment.to_bytes())
+                .unwrap()
+                .to_bytes()[..],
+            commitment.to_bytes()[..]
+        );
+
+        let response = shares[0]
+            .respond::<Sha512>(nonces, &request, &[commitment])
+            .unwrap();
+        assert_eq!(
+            ProofShare::from_bytes(&response.to_bytes())
+                .unwrap()
+                .to_bytes(),
+            response.to_bytes()
+        );
+        assert!(ProofShare::from_bytes(&[0u8; PROOF_SHARE_LENGTH]).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! Fiat–Shamir transcripts from which the challenges of DLEQ proofs are derived.
+//!
+//! `DLEQProof`, `BatchDLEQProof` and `SigningKey::issue` are generic over a
+//! `ProofTranscript` via their `*_with_transcript` methods. Three are provided:
+//!
+//! * `DigestTranscript` hashes the points with a plain `Digest`, and produces exactly the
+//!   proofs of `DLEQProof::new` and `BatchDLEQProof::new`.
+//! * `merlin::Transcript`, with the `merlin` feature, produces proofs which verify as a
+//!   `MerlinBatchDLEQProof`.
+//! * `DstTranscript` length-prefixes every value and hashes to a scalar under a domain
+//!   separation tag in the manner of RFC 9497.
+//!
+//! The nonce of each proof is taken from `ProofTranscript::witness_nonce`, which the merlin
+//! `Transcript` hedges with the transcript and the signing key as merlin intends.
+//!
+//! Application context, such as an issuer ID or request ID, is bound into every later
+//! challenge with `ProofTranscript::append_context`. The prover and verifier must append
+//! the same context to the same kind of transcript for a proof to verify.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use core::iter;
+use core::mem;
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::scalar::Scalar;
+use digest::generic_array::typenum::U64;
+use digest::{BlockInput, Digest};
+#[cfg(feature = "merlin")]
+use merlin::Transcript;
+use rand::{CryptoRng, Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+use crate::errors::{InternalError, TokenError};
+use crate::rfc9497::hash_to_scalar_with_dst;
+
+/// A Fiat–Shamir transcript, to which the public inputs of a proof are appended before
+/// challenges are derived from them.
+pub trait ProofTranscript {
+    /// Separate the proofs of one protocol from those of another.
+    fn domain_separator(&mut self, domain: &'static [u8]);
+
+    /// Bind application context, such as an issuer ID or request ID, into every later
+    /// challenge.
+    fn append_context(&mut self, label: &'static [u8], context: &[u8]);
+
+    /// Append a point to the transcript.
+    fn append_point(&mut self, label: &'static [u8], point: &CompressedRistretto);
+
+    /// Derive a challenge from the transcript.
+    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar;
+
+    /// Derive `n` challenges from the transcript, such as the weights of a composite.
+    fn challenge_scalars(&mut self, label: &'static [u8], n: usize) -> Vec<Scalar> {
+        iter::repeat_with(|| self.challenge_scalar(label))
+            .take(n)
+            .collect()
+    }
+
+    /// Derive the nonce of a proof of knowledge of `witness`.
+    ///
+    /// By default the nonce is drawn from `rng` alone. A transcript may instead hedge it
+    /// with the public inputs appended so far and the witness.
+    fn witness_nonce<T>(&self, _label: &'static [u8], _witness: &Scalar, rng: &mut T) -> Scalar
+    where
+        T: Rng + CryptoRng,
+    {
+        Scalar::random(rng)
+    }
+}
+
+/// A `ProofTranscript` which hashes the points appended since the last challenge with `D`.
+///
+/// Labels and domain separators are ignored, so that without any context the proofs are
+/// identical to those of `DLEQProof::new` and `BatchDLEQProof::new`. Context is hashed at
+/// the start of every challenge.
+#[derive(Default)]
+pub struct DigestTranscript<D> {
+    hash: D,
+    context: Vec<u8>,
+}
+
+impl<D> DigestTranscript<D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    /// Construct a new `DigestTranscript`.
+    pub fn new() -> Self {
+        DigestTranscript {
+            hash: D::default(),
+            context: Vec::new(),
+        }
+    }
+
+    /// Take the hash of everything appended since the last challenge.
+    fn take_hash(&mut self) -> D {
+        let mut hash = D::default();
+        hash.update(&self.context);
+        mem::replace(&mut self.hash, hash)
+    }
+}
+
+impl<D> ProofTranscript for DigestTranscript<D>
+where
+    D: Digest<OutputSize = U64> + Default,
+{
+    fn domain_separator(&mut self, _domain: &'static [u8]) {}
+
+    fn append_context(&mut self, label: &'static [u8], context: &[u8]) {
+        let start = self.context.len();
+        for part in [label, context].iter() {
+            self.context
+                .extend_from_slice(&(part.len() as u64).to_be_bytes());
+            self.context.extend_from_slice(part);
+        }
+        self.hash.update(&self.context[start..]);
+    }
+
+    fn append_point(&mut self, _label: &'static [u8], point: &CompressedRistretto) {
+        self.hash.update(point.as_bytes());
+    }
+
+    fn challenge_scalar(&mut self, _label: &'static [u8]) -> Scalar {
+        Scalar::from_hash(self.take_hash())
+    }
+
+    fn challenge_scalars(&mut self, _label: &'static [u8], n: usize) -> Vec<Scalar> {
+        let result = self.take_hash().finalize();
+
+        let mut seed: [u8; 32] = [0u8; 32];
+        seed.copy_from_slice(&result[..32]);
+
+        let mut prng: ChaChaRng = SeedableRng::from_seed(seed);
+        iter::repeat_with(|| Scalar::random(&mut prng))
+            .take(n)
+            .collect()
+    }
+}
+
+#[cfg(feature = "merlin")]
+impl ProofTranscript for Transcript {
+    fn domain_separator(&mut self, domain: &'static [u8]) {
+        self.append_message(b"dom-sep", domain);
+    }
+
+    fn append_context(&mut self, label: &'static [u8], context: &[u8]) {
+        self.append_message(label, context);
+    }
+
+    fn append_point(&mut self, label: &'static [u8], point: &CompressedRistretto) {
+        self.append_message(label, point.as_bytes());
+    }
+
+    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        let mut buf = [0; 64];
+        self.challenge_bytes(label, &mut buf);
+        Scalar::from_bytes_mod_order_wide(&buf)
+    }
+
+    /// Derive the nonce from the transcript rekeyed with the witness, so that it is
+    /// deterministic given a fixed `rng` and safe given a weak one.
+    fn witness_nonce<T>(&self, label: &'static [u8], witness: &Scalar, rng: &mut T) -> Scalar
+    where
+        T: Rng + CryptoRng,
+    {
+        let mut rng = self
+            .build_rng()
+            .rekey_with_witness_bytes(label, witness.as_bytes())
+            .finalize(rng);
+        Scalar::random(&mut rng)
+    }
+}
+
+/// A `ProofTranscript` in the style of RFC 9497, in which every label and value is
+/// prefixed with its eight byte length and challenges are computed as
+/// \\(HashToScalar(transcript \Vert label)\\) under the domain separation tag
+/// \\(\texttt{"HashToScalar-"} \Vert dst\\).
+///
+/// Each challenge is appended to the transcript, so that it binds every earlier one.
+pub struct DstTranscript<D> {
+    dst: Vec<u8>,
+    transcript: Vec<u8>,
+    hash: core::marker::PhantomData<D>,
+}
+
+impl<D> DstTranscript<D>
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    /// Construct a new `DstTranscript` with the domain separation tag `dst`, such as
+    /// `rfc9497::CONTEXT_STRING`.
+    ///
+    /// Returns a `TokenError` if `dst` is too long to be used with `expand_message_xmd`.
+    pub fn new(dst: &[u8]) -> Result<Self, TokenError> {
+        if b"HashToScalar-".len() + dst.len() > 255 {
+            return Err(TokenError(InternalError::InvalidInputError));
+        }
+        Ok(DstTranscript {
+            dst: dst.to_vec(),
+            transcript: Vec::new(),
+            hash: core::marker::PhantomData,
+        })
+    }
+
+    fn append(&mut self, label: &[u8], value: &[u8]) {
+        for part in [label, value].iter() {
+            self.transcript
+                .extend_from_slice(&(part.len() as u64).to_be_bytes());
+            self.transcript.extend_from_slice(part);
+        }
+    }
+}
+
+impl<D> ProofTranscript for DstTranscript<D>
+where
+    D: Digest<OutputSize = U64> + BlockInput + Default,
+{
+    fn domain_separator(&mut self, domain: &'static [u8]) {
+        self.append(b"dom-sep", domain);
+    }
+
+    fn append_context(&mut self, label: &'static [u8], context: &[u8]) {
+        self.append(label, context);
+    }
+
+    fn append_point(&mut self, label: &'static [u8], point: &CompressedRistretto) {
+        self.append(label, point.as_bytes());
+    }
+
+    fn challenge_scalar(&mut self, label: &'static [u8]) -> Scalar {
+        let c = hash_to_scalar_with_dst::<D>(
+            &[&self.transcript, &(label.len() as u64).to_be_bytes(), label],
+            &[b"HashToScalar-", &self.dst],
+        );
+        self.append(label, c.as_bytes());
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use crate::dleq::BatchDLEQProof;
+    use crate::oprf::*;
+    use crate::rfc9497::CONTEXT_STRING;
+
+    fn check_context<F, N>(new_transcript: N)
+    where
+        F: ProofTranscript,
+        N: Fn() -> F,
+    {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..3)
+            .map(|_| Token::random::<Sha512, OsRng>(&mut rng).blind())
+            .collect();
+        let signed_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .map(|t| key.sign(t).unwrap())
+            .collect();
+
+        let with_context = |issuer: &[u8]| {
+            let mut transcript = new_transcript();
+            transcript.append_context(b"issuer", issuer);
+            transcript
+        };
+
+        let proof = BatchDLEQProof::new_with_transcript(
+            &mut with_context(b"issuer-1"),
+            &mut rng,
+            &blinded_tokens,
+            &signed_tokens,
+            &key,
+        )
+        .unwrap();
+
+        assert!(proof
+            .verify_with_transcript(
+                &mut with_context(b"issuer-1"),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_ok());
+        assert!(proof
+            .verify_with_transcript(
+                &mut with_context(b"issuer-2"),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_err());
+        assert!(proof
+            .verify_with_transcript(
+                &mut new_transcript(),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_err());
+
+        let (signed_tokens, proof) = key
+            .issue_with_transcript(&mut with_context(b"issuer-1"), &mut rng, &blinded_tokens)
+            .unwrap();
+        assert!(proof
+            .verify_with_transcript(
+                &mut with_context(b"issuer-1"),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn transcripts_bind_context() {
+        check_context(DigestTranscript::<Sha512>::new);
+        check_context(|| DstTranscript::<Sha512>::new(CONTEXT_STRING).unwrap());
+        #[cfg(feature = "merlin")]
+        check_context(|| Transcript::new(b"transcripttest"));
+    }
+
+    #[test]
+    fn dst_transcript_binds_long_context() {
+        let context = vec![7u8; 70000];
+
+        let challenge = |context: &[u8]| {
+            let mut transcript = DstTranscript::<Sha512>::new(CONTEXT_STRING).unwrap();
+            transcript.append_context(b"request", context);
+            transcript.challenge_scalar(b"c")
+        };
+
+        assert_eq!(challenge(&context), challenge(&context));
+        assert_ne!(challenge(&context), challenge(&context[..65535]));
+        assert_ne!(challenge(&context), challenge(&context[..70000 - 65536]));
+    }
+
+    #[test]
+    fn digest_transcript_matches_digest_proofs() {
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..3)
+            .map(|_| Token::random::<Sha512, OsRng>(&mut rng).blind())
+            .collect();
+
+        let (signed_tokens, proof) = key
+            .issue_with_transcript(
+                &mut DigestTranscript::<Sha512>::new(),
+                &mut rng,
+                &blinded_tokens,
+            )
+            .unwrap();
+        assert!(proof
+            .verify::<Sha512>(&blinded_tokens, &signed_tokens, &key.public_key)
+            .is_ok());
+    }
+
+    #[cfg(feature = "merlin")]
+    #[test]
+    fn merlin_transcript_matches_merlin_proofs() {
+        use crate::dleq_merlin::MerlinBatchDLEQProof;
+
+        let mut rng = OsRng;
+
+        let key = SigningKey::random(&mut rng);
+        let blinded_tokens: Vec<BlindedToken> = (0..3)
+            .map(|_| Token::random::<Sha512, OsRng>(&mut rng).blind())
+            .collect();
+
+        let (signed_tokens, proof) = key
+            .issue_with_transcript(
+                &mut Transcript::new(b"transcripttest"),
+                &mut rng,
+                &blinded_tokens,
+            )
+            .unwrap();
+        let proof = MerlinBatchDLEQProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert!(proof
+            .verify(
+                &mut Transcript::new(b"transcripttest"),
+                &blinded_tokens,
+                &signed_tokens,
+                &key.public_key
+            )
+            .is_ok());
+    }
+}
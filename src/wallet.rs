@@ -0,0 +1,570 @@
+//! Client side storage of tokens across their lifecycle.
+//!
+//! A `Wallet` tracks each `Token` from the moment it is blinded and sent to the server,
+//! through to the `UnblindedToken` recovered from the server's response, until it is taken
+//! for redemption. Tokens are indexed by the `PublicKey` of the issuer, and a batch can
+//! only be unblinded against the `PublicKey` it was requested for.
+//!
+//! The whole wallet can be converted to bytes for persistence. Since the encoding
+//! includes blinding factors and unblinded tokens it must be stored securely.
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use digest::generic_array::typenum::U64;
+use digest::Digest;
+use rand::{CryptoRng, Rng};
+use zeroize::Zeroizing;
+
+#[cfg(feature = "merlin")]
+use merlin::Transcript;
+
+use crate::codec::Reader;
+use crate::dleq::unblind_all;
+use crate::errors::{InternalError, TokenError};
+use crate::oprf::{
+    BlindedToken, PublicKey, SignedToken, Token, TokenPreimage, UnblindedToken, PUBLIC_KEY_LENGTH,
+    TOKEN_LENGTH, TOKEN_PREIMAGE_LENGTH, UNBLINDED_TOKEN_LENGTH,
+};
+use crate::voprf::BatchDLEQProof;
+#[cfg(feature = "merlin")]
+use crate::voprf::MerlinBatchDLEQProof;
+
+/// The lifecycle state of a token held in a `Wallet`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TokenState {
+    /// The token has been blinded and sent to the server, but not yet signed
+    Pending,
+    /// The token has been signed and unblinded, and is available for redemption
+    Signed,
+    /// The token has been taken from the wallet for redemption
+    Spent,
+}
+
+/// A `BatchId` identifies a batch of tokens requested from the server together.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BatchId(u64);
+
+impl BatchId {
+    /// The numeric value of this `BatchId`.
+    pub fn to_u64(self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+struct PendingBatch {
+    id: BatchId,
+    public_key: PublicKey,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug)]
+struct KeyTokens {
+    public_key: PublicKey,
+    signed: Vec<UnblindedToken>,
+    spent: Vec<TokenPreimage>,
+}
+
+/// A `Wallet` holds a client's tokens, indexed by the `PublicKey` they were issued under.
+///
+/// Blinding factors and `UnblindedToken`s are zeroized when the wallet is dropped.
+#[derive(Debug, Default)]
+pub struct Wallet {
+    next_batch: u64,
+    pending: Vec<PendingBatch>,
+    keys: Vec<KeyTokens>,
+}
+
+#[cfg(any(test, feature = "base64"))]
+impl Wallet {
+    #[cfg(all(feature = "alloc", not(feature = "std")))]
+    /// Encode to a base64 string, which is zeroized when dropped
+    pub fn encode_base64(&self) -> Zeroizing<::alloc::string::String> {
+        Zeroizing::new(::base64::encode(&self.to_bytes()[..]))
+    }
+
+    #[cfg(feature = "std")]
+    /// Encode to a base64 string, which is zeroized when dropped
+    pub fn encode_base64(&self) -> Zeroizing<::std::string::String> {
+        Zeroizing::new(::base64::encode(&self.to_bytes()[..]))
+    }
+
+    /// Decode from a base64 string
+    pub fn decode_base64(s: &str) -> Result<Self, TokenError> {
+        let bytes =
+            Zeroizing::new(::base64::decode(s).or(Err(TokenError(InternalError::DecodingError)))?);
+        Wallet::from_bytes(&bytes)
+    }
+}
+
+fn unknown_batch_error() -> TokenError {
+    TokenError(InternalError::InvalidInputError)
+}
+
+impl Wallet {
+    /// Construct an empty `Wallet`.
+    pub fn new() -> Self {
+        Wallet {
+            next_batch: 0,
+            pending: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Generate `n` new random tokens to be signed under `public_key`.
+    ///
+    /// Returns the `BatchId` of the new batch and the `BlindedToken`s to send to the server.
+    pub fn request_tokens<D, T>(
+        &mut self,
+        rng: &mut T,
+        public_key: &PublicKey,
+        n: usize,
+    ) -> (BatchId, Vec<BlindedToken>)
+    where
+        D: Digest<OutputSize = U64> + Default,
+        T: Rng + CryptoRng,
+    {
+        let tokens: Vec<Token> = (0..n).map(|_| Token::random::<D, T>(rng)).collect();
+        let blinded_tokens = tokens.iter().map(Token::blind).collect();
+
+        let id = BatchId(self.next_batch);
+        self.next_batch += 1;
+        self.pending.push(PendingBatch {
+            id,
+            public_key: *public_key,
+            tokens,
+        });
+        (id, blinded_tokens)
+    }
+
+    /// The `BlindedToken`s of a pending batch, for example to retry a signing request.
+    pub fn blinded_tokens(&self, batch: BatchId) -> Option<Vec<BlindedToken>> {
+        self.pending_batch(batch)
+            .map(|pending| pending.tokens.iter().map(Token::blind).collect())
+    }
+
+    /// The `PublicKey` a pending batch was requested for.
+    pub fn batch_public_key(&self, batch: BatchId) -> Option<&PublicKey> {
+        self.pending_batch(batch).map(|pending| &pending.public_key)
+    }
+
+    /// The `BatchId`s of every pending batch.
+    pub fn pending_batches(&self) -> impl Iterator<Item = BatchId> + '_ {
+        self.pending.iter().map(|pending| pending.id)
+    }
+
+    /// Abandon a pending batch, dropping its tokens.
+    pub fn cancel_batch(&mut self, batch: BatchId) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|pending| pending.id != batch);
+        self.pending.len() != before
+    }
+
+    /// Verify the server's `BatchDLEQProof` for a pending batch and store the resulting
+    /// `UnblindedToken`s, returning how many were added.
+    ///
+    /// Returns a `TokenError` if the batch is unknown, if `public_key` differs from the
+    /// key the batch was requested for, or if the proof does not verify. In each case the
+    /// batch remains pending.
+    pub fn finalize_batch<D>(
+        &mut self,
+        batch: BatchId,
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        proof: &BatchDLEQProof,
+    ) -> Result<usize, TokenError>
+    where
+        D: Digest<OutputSize = U64> + Default,
+    {
+        let blinded_tokens = self.check_batch(batch, public_key)?;
+        proof.verify::<D>(&blinded_tokens, signed_tokens, public_key)?;
+        self.unblind_batch(batch, signed_tokens)
+    }
+
+    /// Verify the server's `MerlinBatchDLEQProof` for a pending batch and store the resulting
+    /// `UnblindedToken`s, returning how many were added.
+    ///
+    /// This is the merlin counterpart of `Wallet::finalize_batch`, and fails in the same cases.
+    #[cfg(feature = "merlin")]
+    pub fn finalize_batch_merlin(
+        &mut self,
+        transcript: &mut Transcript,
+        batch: BatchId,
+        signed_tokens: &[SignedToken],
+        public_key: &PublicKey,
+        proof: &MerlinBatchDLEQProof,
+    ) -> Result<usize, TokenError> {
+        let blinded_tokens = self.check_batch(batch, public_key)?;
+        proof.verify(transcript, &blinded_tokens, signed_tokens, public_key)?;
+        self.unblind_batch(batch, signed_tokens)
+    }
+
+    fn check_batch(
+        &self,
+        batch: BatchId,
+        public_key: &PublicKey,
+    ) -> Result<Vec<BlindedToken>, TokenError> {
+        let pending = self.pending_batch(batch).ok_or_else(unknown_batch_error)?;
+        if pending.public_key.0 != public_key.0 {
+            return Err(TokenError(InternalError::KeyMismatchError));
+        }
+        Ok(pending.tokens.iter().map(Token::blind).collect())
+    }
+
+    fn unblind_batch(
+        &mut self,
+        batch: BatchId,
+        signed_tokens: &[SignedToken],
+    ) -> Result<usize, TokenError> {
+        let index = self
+            .pending
+            .iter()
+            .position(|pending| pending.id == batch)
+            .ok_or_else(unknown_batch_error)?;
+
+        let unblinded_tokens = unblind_all(&self.pending[index].tokens, signed_tokens)?;
+
+        let pending = self.pending.remove(index);
+        let count = unblinded_tokens.len();
+        self.key_tokens_mut(&pending.public_key)
+            .signed
+            .extend(unblinded_tokens);
+        Ok(count)
+    }
+
+    /// Take an `UnblindedToken` issued under `public_key` for redemption, marking it spent.
+    pub fn take_token(&mut self, public_key: &PublicKey) -> Option<UnblindedToken> {
+        let key_tokens = self
+            .keys
+            .iter_mut()
+            .find(|key_tokens| key_tokens.public_key.0 == public_key.0)?;
+        let token = key_tokens.signed.pop()?;
+        key_tokens.spent.push(token.t);
+        Some(token)
+    }
+
+    /// The number of tokens issued under `public_key` which are available for redemption.
+    pub fn balance(&self, public_key: &PublicKey) -> usize {
+        self.key_tokens(public_key)
+            .map_or(0, |key_tokens| key_tokens.signed.len())
+    }
+
+    /// The `PublicKey`s under which this wallet holds signed or spent tokens.
+    pub fn public_keys(&self) -> impl Iterator<Item = &PublicKey> {
+        self.keys.iter().map(|key_tokens| &key_tokens.public_key)
+    }
+
+    /// Look up the `TokenState` of the token with preimage `t`.
+    pub fn state(&self, t: &TokenPreimage) -> Option<TokenState> {
+        if self
+            .pending
+            .iter()
+            .any(|pending| pending.tokens.iter().any(|token| token.t == *t))
+        {
+            return Some(TokenState::Pending);
+        }
+        for key_tokens in self.keys.iter() {
+            if key_tokens.signed.iter().any(|token| token.t == *t) {
+                return Some(TokenState::Signed);
+            }
+            if key_tokens.spent.contains(t) {
+                return Some(TokenState::Spent);
+            }
+        }
+        None
+    }
+
+    /// Drop every token issued under `public_key`, once the key has been retired.
+    pub fn forget_key(&mut self, public_key: &PublicKey) {
+        self.pending
+            .retain(|pending| pending.public_key.0 != public_key.0);
+        self.keys
+            .retain(|key_tokens| key_tokens.public_key.0 != public_key.0);
+    }
+
+    fn pending_batch(&self, batch: BatchId) -> Option<&PendingBatch> {
+        self.pending.iter().find(|pending| pending.id == batch)
+    }
+
+    fn key_tokens(&self, public_key: &PublicKey) -> Option<&KeyTokens> {
+        self.keys
+            .iter()
+            .find(|key_tokens| key_tokens.public_key.0 == public_key.0)
+    }
+
+    fn key_tokens_mut(&mut self, public_key: &PublicKey) -> &mut KeyTokens {
+        match self
+            .keys
+            .iter()
+            .position(|key_tokens| key_tokens.public_key.0 == public_key.0)
+        {
+            Some(index) => &mut self.keys[index],
+            None => {
+                self.keys.push(KeyTokens {
+                    public_key: *public_key,
+                    signed: Vec::new(),
+                    spent: Vec::new(),
+                });
+                self.keys.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Convert this `Wallet` to bytes.
+    ///
+    /// The encoding contains the blinding factors of pending tokens and the unblinded
+    /// signed tokens, so it must be stored securely. The returned buffer is zeroized when
+    /// dropped.
+    pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(Vec::new());
+        bytes.extend_from_slice(&self.next_batch.to_be_bytes());
+
+        bytes.extend_from_slice(&(self.pending.len() as u32).to_be_bytes());
+        for pending in self.pending.iter() {
+            bytes.extend_from_slice(&pending.id.0.to_be_bytes());
+            bytes.extend_from_slice(&pending.public_key.to_bytes());
+            bytes.extend_from_slice(&(pending.tokens.len() as u32).to_be_bytes());
+            for token in pending.tokens.iter() {
+                bytes.extend_from_slice(&token.to_bytes());
+            }
+        }
+
+        bytes.extend_from_slice(&(self.keys.len() as u32).to_be_bytes());
+        for key_tokens in self.keys.iter() {
+            bytes.extend_from_slice(&key_tokens.public_key.to_bytes());
+            bytes.extend_from_slice(&(key_tokens.signed.len() as u32).to_be_bytes());
+            for token in key_tokens.signed.iter() {
+                bytes.extend_from_slice(&token.to_bytes());
+            }
+            bytes.extend_from_slice(&(key_tokens.spent.len() as u32).to_be_bytes());
+            for t in key_tokens.spent.iter() {
+                bytes.extend_from_slice(&t.to_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Construct a `Wallet` from bytes produced by `to_bytes`.
+    ///
+    /// Returns a `TokenError` if a `BatchId` or `PublicKey` appears more than once, or if a
+    /// pending `BatchId` could be handed out again by `request_tokens`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Wallet, TokenError> {
+        let mut reader = Reader(bytes);
+
+        let next_batch = reader.read_u64()?;
+
+        let mut pending: Vec<PendingBatch> = Vec::new();
+        for _ in 0..reader.read_u32()? {
+            let id = BatchId(reader.read_u64()?);
+            if id.0 >= next_batch || pending.iter().any(|pending| pending.id == id) {
+                return Err(TokenError(InternalError::DecodingError));
+            }
+            let public_key = PublicKey::from_bytes(reader.read(PUBLIC_KEY_LENGTH)?)?;
+            let tokens = (0..reader.read_u32()?)
+                .map(|_| Token::from_bytes(reader.read(TOKEN_LENGTH)?))
+                .collect::<Result<Vec<Token>, TokenError>>()?;
+            pending.push(PendingBatch {
+                id,
+                public_key,
+                tokens,
+            });
+        }
+
+        let mut keys: Vec<KeyTokens> = Vec::new();
+        for _ in 0..reader.read_u32()? {
+            let public_key = PublicKey::from_bytes(reader.read(PUBLIC_KEY_LENGTH)?)?;
+            if keys
+                .iter()
+                .any(|key_tokens| key_tokens.public_key.0 == public_key.0)
+            {
+                return Err(TokenError(InternalError::DecodingError));
+            }
+            let signed = (0..reader.read_u32()?)
+                .map(|_| UnblindedToken::from_bytes(reader.read(UNBLINDED_TOKEN_LENGTH)?))
+                .collect::<Result<Vec<UnblindedToken>, TokenError>>()?;
+            let spent = (0..reader.read_u32()?)
+                .map(|_| TokenPreimage::from_bytes(reader.read(TOKEN_PREIMAGE_LENGTH)?))
+                .collect::<Result<Vec<TokenPreimage>, TokenError>>()?;
+            keys.push(KeyTokens {
+                public_key,
+                signed,
+                spent,
+            });
+        }
+
+        if !reader.0.is_empty() {
+            return Err(TokenError(InternalError::DecodingError));
+        }
+
+        Ok(Wallet {
+            next_batch,
+            pending,
+            keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::oprf::SigningKey;
+
+    fn sign_batch(
+        signing_key: &SigningKey,
+        blinded_tokens: &[BlindedToken],
+    ) -> (Vec<SignedToken>, BatchDLEQProof) {
+        let signed_tokens: Vec<SignedToken> = blinded_tokens
+            .iter()
+            .map(|t| signing_key.sign(t).unwrap())
+            .collect();
+        let proof = BatchDLEQProof::new::<Sha512, _>(
+            &mut OsRng,
+            blinded_tokens,
+            &signed_tokens,
+            signing_key,
+        )
+        .unwrap();
+        (signed_tokens, proof)
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut rng = OsRng;
+
+        let signing_key = SigningKey::random(&mut rng);
+        let other_key = SigningKey::random(&mut rng);
+        let public_key = signing_key.public_key;
+
+        let mut wallet = Wallet::new();
+        let (batch, blinded_tokens) = wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 3);
+        assert_eq!(wallet.pending_batches().count(), 1);
+        assert_eq!(wallet.balance(&public_key), 0);
+
+        // a response signed under a different key is refused before the proof is checked
+        let (signed_tokens, proof) = sign_batch(&other_key, &blinded_tokens);
+        assert_eq!(
+            wallet
+                .finalize_batch::<Sha512>(batch, &signed_tokens, &other_key.public_key, &proof)
+                .unwrap_err(),
+            TokenError(InternalError::KeyMismatchError)
+        );
+        assert_eq!(
+            wallet.batch_public_key(batch).unwrap().to_bytes(),
+            public_key.to_bytes()
+        );
+
+        let (signed_tokens, proof) = sign_batch(&signing_key, &blinded_tokens);
+
+        // persist while the batch is pending
+        let mut wallet = Wallet::from_bytes(&wallet.to_bytes()).unwrap();
+        assert_eq!(
+            wallet.finalize_batch::<Sha512>(batch, &signed_tokens, &public_key, &proof),
+            Ok(3)
+        );
+        assert_eq!(wallet.pending_batches().count(), 0);
+        assert_eq!(wallet.balance(&public_key), 3);
+
+        let token = wallet.take_token(&public_key).unwrap();
+        assert_eq!(wallet.state(&token.t), Some(TokenState::Spent));
+        assert!(wallet.take_token(&other_key.public_key).is_none());
+
+        // the redeemed token matches what the server rederives
+        let server_token = signing_key.rederive_unblinded_token(&token.t);
+        assert_eq!(token.to_bytes()[..], server_token.to_bytes()[..]);
+
+        let wallet = Wallet::decode_base64(&wallet.encode_base64()).unwrap();
+        assert_eq!(wallet.balance(&public_key), 2);
+        assert_eq!(wallet.state(&token.t), Some(TokenState::Spent));
+        assert_eq!(wallet.public_keys().count(), 1);
+    }
+
+    #[cfg(feature = "merlin")]
+    #[test]
+    fn finalize_merlin_batch() {
+        let mut rng = OsRng;
+
+        let signing_key = SigningKey::random(&mut rng);
+        let public_key = signing_key.public_key;
+
+        let mut wallet = Wallet::new();
+        let (batch, blinded_tokens) = wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 2);
+        let (signed_tokens, proof) = signing_key
+            .issue_merlin(&mut Transcript::new(b"wallettest"), &blinded_tokens)
+            .unwrap();
+
+        assert!(wallet
+            .finalize_batch_merlin(
+                &mut Transcript::new(b"othertest"),
+                batch,
+                &signed_tokens,
+                &public_key,
+                &proof
+            )
+            .is_err());
+        assert_eq!(
+            wallet.finalize_batch_merlin(
+                &mut Transcript::new(b"wallettest"),
+                batch,
+                &signed_tokens,
+                &public_key,
+                &proof
+            ),
+            Ok(2)
+        );
+        assert_eq!(wallet.balance(&public_key), 2);
+    }
+
+    #[test]
+    fn forget_and_cancel() {
+        let mut rng = OsRng;
+
+        let public_key = SigningKey::random(&mut rng).public_key;
+
+        let mut wallet = Wallet::new();
+        let (first, _) = wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 2);
+        let (second, _) = wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 2);
+        assert_ne!(first, second);
+        assert_eq!(wallet.blinded_tokens(first).unwrap().len(), 2);
+
+        assert!(wallet.cancel_batch(first));
+        assert!(!wallet.cancel_batch(first));
+        assert!(wallet.blinded_tokens(first).is_none());
+
+        wallet.forget_key(&public_key);
+        assert_eq!(wallet.pending_batches().count(), 0);
+
+        assert!(Wallet::from_bytes(&[0u8; 3]).is_err());
+        assert!(Wallet::from_bytes(&Wallet::new().to_bytes()).is_ok());
+    }
+
+    #[test]
+    fn from_bytes_rejects_inconsistent_batches() {
+        let mut rng = OsRng;
+
+        let public_key = SigningKey::random(&mut rng).public_key;
+
+        let mut wallet = Wallet::new();
+        wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 1);
+        wallet.request_tokens::<Sha512, _>(&mut rng, &public_key, 1);
+        let bytes = wallet.to_bytes();
+        assert!(Wallet::from_bytes(&bytes).is_ok());
+
+        // a pending batch whose id would be handed out again
+        let mut reused = bytes.to_vec();
+        reused[..8].copy_from_slice(&1u64.to_be_bytes());
+        assert!(Wallet::from_bytes(&reused).is_err());
+
+        // two pending batches with the same id
+        let batch_length = 8 + PUBLIC_KEY_LENGTH + 4 + TOKEN_LENGTH;
+        let mut duplicated = bytes.to_vec();
+        duplicated[12 + batch_length..20 + batch_length].copy_from_slice(&0u64.to_be_bytes());
+        assert!(Wallet::from_bytes(&duplicated).is_err());
+    }
+}
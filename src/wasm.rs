@@ -0,0 +1,253 @@
+//! WebAssembly bindings for token clients.
+//!
+//! Each type is wrapped for use from JavaScript via `wasm-bindgen`, and exchanged with the
+//! server as a `Uint8Array` or base64 string using the existing `to_bytes` / `from_bytes`
+//! codecs. Randomness comes from the JavaScript crypto API through `getrandom`, and the
+//! hash and MAC are fixed to SHA-512 and HMAC-SHA512.
+//!
+//! Only the client side of the protocol is exposed, so `BatchDLEQProof` verification is
+//! available but signing is not.
+
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use hmac::Hmac;
+use rand::rngs::OsRng;
+use sha2::Sha512;
+use wasm_bindgen::prelude::*;
+
+use crate::errors::{InternalError, TokenError};
+use crate::voprf::*;
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn js_error(error: TokenError) -> JsValue {
+    JsError::new(&error.to_string()).into()
+}
+
+/// Decode `bytes` as a concatenation of encodings which are each `length` bytes long.
+fn decode_all<T, F>(bytes: &[u8], length: usize, from_bytes: F) -> Result<Vec<T>, JsValue>
+where
+    F: Fn(&[u8]) -> Result<T, TokenError>,
+{
+    if bytes.len() % length != 0 {
+        return Err(js_error(TokenError(InternalError::DecodingError)));
+    }
+    bytes
+        .chunks(length)
+        .map(|chunk| from_bytes(chunk).map_err(js_error))
+        .collect()
+}
+
+/// Define the `Uint8Array` and base64 codecs for a wrapped type.
+macro_rules! wasm_codec {
+    ($wrapper:ident, $t:ident) => {
+        #[wasm_bindgen(js_class = $t)]
+        impl $wrapper {
+            /// Encode to bytes.
+            #[wasm_bindgen(js_name = toBytes)]
+            pub fn to_bytes(&self) -> Vec<u8> {
+                self.0.to_bytes().to_vec()
+            }
+
+            /// Decode from bytes.
+            #[wasm_bindgen(js_name = fromBytes)]
+            pub fn from_bytes(bytes: &[u8]) -> Result<$wrapper, JsValue> {
+                $t::from_bytes(bytes).map($wrapper).map_err(js_error)
+            }
+
+            /// Encode to a base64 string.
+            #[wasm_bindgen(js_name = encodeBase64)]
+            pub fn encode_base64(&self) -> String {
+                self.0.encode_base64()
+            }
+
+            /// Decode from a base64 string.
+            #[wasm_bindgen(js_name = decodeBase64)]
+            pub fn decode_base64(s: &str) -> Result<$wrapper, JsValue> {
+                $t::decode_base64(s).map($wrapper).map_err(js_error)
+            }
+        }
+    };
+}
+
+/// A `Token`, which must be kept secret by the client.
+#[wasm_bindgen(js_name = Token)]
+pub struct WasmToken(Token);
+
+wasm_codec!(WasmToken, Token);
+
+#[wasm_bindgen(js_class = Token)]
+impl WasmToken {
+    /// Generate a new random `Token`.
+    pub fn random() -> WasmToken {
+        WasmToken(Token::random::<Sha512, _>(&mut OsRng))
+    }
+
+    /// Blind the `Token`, returning a `BlindedToken` to be sent to the server.
+    pub fn blind(&self) -> WasmBlindedToken {
+        WasmBlindedToken(self.0.blind())
+    }
+}
+
+/// A `BlindedToken`, sent to the server for signing.
+#[wasm_bindgen(js_name = BlindedToken)]
+pub struct WasmBlindedToken(BlindedToken);
+
+wasm_codec!(WasmBlindedToken, BlindedToken);
+
+/// A `SignedToken`, returned by the server.
+#[wasm_bindgen(js_name = SignedToken)]
+pub struct WasmSignedToken(SignedToken);
+
+wasm_codec!(WasmSignedToken, SignedToken);
+
+/// The `PublicKey` of the server.
+#[wasm_bindgen(js_name = PublicKey)]
+pub struct WasmPublicKey(PublicKey);
+
+wasm_codec!(WasmPublicKey, PublicKey);
+
+/// A `TokenPreimage`, sent to the server on redemption.
+#[wasm_bindgen(js_name = TokenPreimage)]
+pub struct WasmTokenPreimage(TokenPreimage);
+
+wasm_codec!(WasmTokenPreimage, TokenPreimage);
+
+/// An `UnblindedToken`, which the client redeems.
+#[wasm_bindgen(js_name = UnblindedToken)]
+pub struct WasmUnblindedToken(UnblindedToken);
+
+wasm_codec!(WasmUnblindedToken, UnblindedToken);
+
+#[wasm_bindgen(js_class = UnblindedToken)]
+impl WasmUnblindedToken {
+    /// The `TokenPreimage` of this token.
+    pub fn preimage(&self) -> WasmTokenPreimage {
+        WasmTokenPreimage(self.0.t)
+    }
+
+    /// Derive the `VerificationKey` used to sign a redemption request.
+    #[wasm_bindgen(js_name = deriveVerificationKey)]
+    pub fn derive_verification_key(&self) -> WasmVerificationKey {
+        WasmVerificationKey(self.0.derive_verification_key::<Sha512>())
+    }
+}
+
+/// A `VerificationKey` derived from an `UnblindedToken`.
+#[wasm_bindgen(js_name = VerificationKey)]
+pub struct WasmVerificationKey(VerificationKey);
+
+#[wasm_bindgen(js_class = VerificationKey)]
+impl WasmVerificationKey {
+    /// Sign `message`, producing a `VerificationSignature`.
+    pub fn sign(&self, message: &[u8]) -> WasmVerificationSignature {
+        WasmVerificationSignature(self.0.sign::<HmacSha512>(message))
+    }
+
+    /// Check that `signature` is valid for `message`.
+    pub fn verify(&self, signature: &WasmVerificationSignature, message: &[u8]) -> bool {
+        self.0.verify::<HmacSha512>(&signature.0, message)
+    }
+}
+
+/// A `VerificationSignature`, sent to the server on redemption.
+#[wasm_bindgen(js_name = VerificationSignature)]
+pub struct WasmVerificationSignature(VerificationSignature);
+
+wasm_codec!(WasmVerificationSignature, VerificationSignature);
+
+/// A `BatchDLEQProof`, returned by the server alongside the `SignedToken`s.
+#[wasm_bindgen(js_name = BatchDLEQProof)]
+pub struct WasmBatchDLEQProof(BatchDLEQProof);
+
+wasm_codec!(WasmBatchDLEQProof, BatchDLEQProof);
+
+#[wasm_bindgen(js_class = BatchDLEQProof)]
+impl WasmBatchDLEQProof {
+    /// Verify the proof and unblind the `SignedToken`s using the corresponding `Token`s.
+    ///
+    /// Each argument is the concatenation of the `toBytes` encodings of the tokens, so the
+    /// `Token` objects remain usable if verification fails.
+    #[wasm_bindgen(js_name = verifyAndUnblind)]
+    pub fn verify_and_unblind(
+        &self,
+        tokens: &[u8],
+        blinded_tokens: &[u8],
+        signed_tokens: &[u8],
+        public_key: &WasmPublicKey,
+    ) -> Result<Vec<WasmUnblindedToken>, JsValue> {
+        let tokens = decode_all(tokens, TOKEN_LENGTH, Token::from_bytes)?;
+        let blinded_tokens = decode_all(
+            blinded_tokens,
+            BLINDED_TOKEN_LENGTH,
+            BlindedToken::from_bytes,
+        )?;
+        let signed_tokens =
+            decode_all(signed_tokens, SIGNED_TOKEN_LENGTH, SignedToken::from_bytes)?;
+        if tokens.len() != blinded_tokens.len() {
+            return Err(js_error(TokenError(InternalError::LengthMismatchError)));
+        }
+
+        self.0
+            .verify_and_unblind::<Sha512, _>(
+                tokens.iter(),
+                &blinded_tokens,
+                &signed_tokens,
+                &public_key.0,
+            )
+            .map(|unblinded_tokens| {
+                unblinded_tokens
+                    .into_iter()
+                    .map(WasmUnblindedToken)
+                    .collect()
+            })
+            .map_err(js_error)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_flow() {
+        let signing_key = SigningKey::random(&mut OsRng);
+
+        let token = WasmToken::random();
+        let blinded_token = WasmBlindedToken::from_bytes(&token.blind().to_bytes()).unwrap();
+
+        let signed_token = signing_key.sign(&blinded_token.0).unwrap();
+        let proof = BatchDLEQProof::new::<Sha512, _>(
+            &mut OsRng,
+            &[blinded_token.0],
+            &[signed_token],
+            &signing_key,
+        )
+        .unwrap();
+
+        let proof = WasmBatchDLEQProof::decode_base64(&proof.encode_base64()).unwrap();
+        let public_key = WasmPublicKey::from_bytes(&signing_key.public_key.to_bytes()).unwrap();
+        let unblinded_tokens = proof
+            .verify_and_unblind(
+                &token.to_bytes(),
+                &blinded_token.to_bytes(),
+                &signed_token.to_bytes(),
+                &public_key,
+            )
+            .unwrap();
+
+        let signature = unblinded_tokens[0]
+            .derive_verification_key()
+            .sign(b"test message");
+        let signature = WasmVerificationSignature::from_bytes(&signature.to_bytes()).unwrap();
+        let preimage =
+            WasmTokenPreimage::decode_base64(&unblinded_tokens[0].preimage().encode_base64())
+                .unwrap();
+
+        let server_key = signing_key
+            .rederive_unblinded_token(&preimage.0)
+            .derive_verification_key::<Sha512>();
+        assert!(server_key.verify::<HmacSha512>(&signature.0, b"test message"));
+    }
+}
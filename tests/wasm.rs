@@ -0,0 +1,101 @@
+//! Run with `wasm-pack test --node -- --features wasm`, or
+//! `cargo test --target wasm32-unknown-unknown --features wasm --test wasm` with
+//! `wasm-bindgen-test-runner` configured as the target runner.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+extern crate challenge_bypass_ristretto;
+extern crate rand;
+extern crate sha2;
+extern crate wasm_bindgen_test;
+
+use rand::rngs::OsRng;
+use sha2::Sha512;
+use wasm_bindgen_test::*;
+
+use challenge_bypass_ristretto::voprf::{BatchDLEQProof, SigningKey};
+use challenge_bypass_ristretto::wasm::*;
+
+#[wasm_bindgen_test]
+fn client_flow() {
+    let signing_key = SigningKey::random(&mut OsRng);
+
+    let token = WasmToken::random();
+    let blinded_token = token.blind();
+
+    // the server signs the blinded token
+    let blinded =
+        challenge_bypass_ristretto::voprf::BlindedToken::from_bytes(&blinded_token.to_bytes())
+            .unwrap();
+    let signed = signing_key.sign(&blinded).unwrap();
+    let proof =
+        BatchDLEQProof::new::<Sha512, _>(&mut OsRng, &[blinded], &[signed], &signing_key).unwrap();
+
+    let signed_token = WasmSignedToken::decode_base64(&signed.encode_base64()).unwrap();
+    let proof = WasmBatchDLEQProof::from_bytes(&proof.to_bytes()).unwrap();
+    let public_key = WasmPublicKey::from_bytes(&signing_key.public_key.to_bytes()).unwrap();
+
+    let unblinded_tokens = proof
+        .verify_and_unblind(
+            &token.to_bytes(),
+            &blinded_token.to_bytes(),
+            &signed_token.to_bytes(),
+            &public_key,
+        )
+        .unwrap();
+    assert_eq!(unblinded_tokens.len(), 1);
+
+    let verification_key = unblinded_tokens[0].derive_verification_key();
+    let signature = verification_key.sign(b"test message");
+    assert!(verification_key.verify(&signature, b"test message"));
+    assert!(!verification_key.verify(&signature, b"other message"));
+}
+
+#[wasm_bindgen_test]
+fn rejects_invalid_input() {
+    assert!(WasmPublicKey::from_bytes(&[0u8; 3]).is_err());
+    assert!(WasmToken::decode_base64("not base64!").is_err());
+
+    let other_key = SigningKey::random(&mut OsRng);
+    let token = WasmToken::random();
+    let blinded_token = token.blind();
+    let blinded =
+        challenge_bypass_ristretto::voprf::BlindedToken::from_bytes(&blinded_token.to_bytes())
+            .unwrap();
+    let signed = other_key.sign(&blinded).unwrap();
+    let proof =
+        BatchDLEQProof::new::<Sha512, _>(&mut OsRng, &[blinded], &[signed], &other_key).unwrap();
+
+    // a proof for a different key does not verify
+    let proof = WasmBatchDLEQProof::from_bytes(&proof.to_bytes()).unwrap();
+    let public_key =
+        WasmPublicKey::from_bytes(&SigningKey::random(&mut OsRng).public_key.to_bytes()).unwrap();
+    assert!(proof
+        .verify_and_unblind(
+            &token.to_bytes(),
+            &blinded_token.to_bytes(),
+            &signed.to_bytes(),
+            &public_key,
+        )
+        .is_err());
+
+    // but the tokens can still be unblinded against the right key
+    let public_key = WasmPublicKey::from_bytes(&other_key.public_key.to_bytes()).unwrap();
+    assert!(proof
+        .verify_and_unblind(
+            &token.to_bytes(),
+            &blinded_token.to_bytes(),
+            &signed.to_bytes(),
+            &public_key,
+        )
+        .is_ok());
+
+    // encodings of the wrong length are refused
+    assert!(proof
+        .verify_and_unblind(
+            &token.to_bytes()[1..],
+            &blinded_token.to_bytes(),
+            &signed.to_bytes(),
+            &public_key,
+        )
+        .is_err());
+}